@@ -0,0 +1,62 @@
+//! Compare the latency of minting installation tokens with a new
+//! connection for each mint against reusing one pooled connection.
+//!
+//! Run with `cargo bench --features test-util`. The mock server speaks
+//! plain HTTP, so the difference here is only the TCP connection
+//! setup; against GitHub each new connection also costs a TLS
+//! handshake.
+
+use github_app_auth::test_util::MockGithub;
+use github_app_auth::{GithubAuthParams, InstallationAccessToken};
+use std::time::{Duration, Instant};
+
+const MINTS: usize = 200;
+const CONCURRENCY: usize = 20;
+
+async fn mint_all(
+    github: &MockGithub,
+    params: impl Fn() -> GithubAuthParams,
+) -> Duration {
+    let start = Instant::now();
+    for _ in 0..MINTS / CONCURRENCY {
+        let mints = (0..CONCURRENCY)
+            .map(|_| InstallationAccessToken::new(params()))
+            .collect::<Vec<_>>();
+        for result in futures_util::future::join_all(mints).await {
+            result.unwrap();
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "  {} mints in {:?} ({:?} per mint), {} connections",
+        MINTS,
+        elapsed,
+        elapsed / MINTS as u32,
+        github.connections()
+    );
+    elapsed
+}
+
+#[tokio::main]
+async fn main() {
+    println!("new client per mint:");
+    let github = MockGithub::start().await.unwrap();
+    let fresh = mint_all(&github, || github.params()).await;
+
+    println!("shared client:");
+    let github = MockGithub::start().await.unwrap();
+    let client = reqwest::Client::builder()
+        .user_agent("github-app-auth-bench")
+        .build()
+        .unwrap();
+    let shared = mint_all(&github, || GithubAuthParams {
+        http_client: Some(client.clone()),
+        ..github.params()
+    })
+    .await;
+
+    println!(
+        "shared client is {:.1}x faster",
+        fresh.as_secs_f64() / shared.as_secs_f64()
+    );
+}
@@ -0,0 +1,126 @@
+use crate::AuthError;
+use chrono::{TimeZone, Utc};
+use http::header::{HeaderMap, RETRY_AFTER};
+use http::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Error body returned by the GitHub API.
+#[derive(Debug, Default, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    documentation_url: Option<String>,
+}
+
+/// Longest non-JSON error body that is used as the error message, so
+/// that an HTML error page from a proxy doesn't end up in the logs.
+const MAX_ERROR_TEXT: usize = 200;
+
+/// Turn an unsuccessful GitHub API response into the matching
+/// [`AuthError`]. `text` is the response body.
+pub(crate) fn error_from_response(
+    status: StatusCode,
+    headers: &HeaderMap,
+    text: &str,
+) -> AuthError {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+    let rate_limit_exhausted = headers
+        .get("x-ratelimit-remaining")
+        .is_some_and(|value| value == "0");
+    let reset_at = headers
+        .get("x-ratelimit-reset")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .and_then(|reset| Utc.timestamp_opt(reset, 0).single());
+    let body: ErrorBody = serde_json::from_str(text).unwrap_or_default();
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        return AuthError::ServiceUnavailable {
+            message: body.message,
+            retry_after,
+        };
+    }
+    // GitHub reports primary rate limits with an exhausted
+    // X-RateLimit-Remaining, and secondary rate limits with
+    // Retry-After or only in the message.
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (rate_limit_exhausted
+                || retry_after.is_some()
+                || body.message.to_lowercase().contains("rate limit")));
+    if rate_limited {
+        return AuthError::RateLimited {
+            status,
+            message: body.message,
+            reset_at,
+            retry_after,
+        };
+    }
+    if status == StatusCode::FORBIDDEN {
+        if let Some(policy_err) = classify_forbidden(text) {
+            return policy_err;
+        }
+    }
+    let message = if !body.message.is_empty() {
+        body.message
+    } else if !text.trim().is_empty() && text.len() <= MAX_ERROR_TEXT {
+        text.trim().to_string()
+    } else {
+        status.canonical_reason().unwrap_or_default().to_string()
+    };
+    AuthError::GithubApi {
+        status,
+        message,
+        documentation_url: body.documentation_url,
+    }
+}
+
+/// Recognize 403 responses caused by SAML enforcement or an IP allow
+/// list, which are otherwise indistinguishable from missing
+/// permissions.
+fn classify_forbidden(body: &str) -> Option<AuthError> {
+    let body: ErrorBody = serde_json::from_str(body).unwrap_or_default();
+    let message = body.message;
+    if message.contains("SAML enforcement") {
+        Some(AuthError::SamlEnforced(message))
+    } else if message.contains("IP allow list") {
+        Some(AuthError::IpNotAllowed(message))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_forbidden() {
+        let saml = r#"{
+            "message": "Resource protected by organization SAML enforcement. You must grant your Personal Access token access to this organization.",
+            "documentation_url": "https://docs.github.com/articles/authenticating-to-a-github-organization-with-saml-single-sign-on/"
+            }"#;
+        assert!(matches!(
+            classify_forbidden(saml),
+            Some(AuthError::SamlEnforced(_))
+        ));
+
+        let ip = r#"{
+            "message": "Although you appear to have the correct authorization credentials, the `mycoolorg` organization has an IP allow list enabled, and your IP address is not permitted to access this resource.",
+            "documentation_url": "https://docs.github.com/rest"
+            }"#;
+        assert!(matches!(
+            classify_forbidden(ip),
+            Some(AuthError::IpNotAllowed(_))
+        ));
+
+        let other = r#"{"message": "Resource not accessible by integration"}"#;
+        assert!(classify_forbidden(other).is_none());
+        assert!(classify_forbidden("not json").is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::response::{check_status, send};
+use crate::signer::app_jwt;
+use crate::{Account, AuthError, GithubAuthParams, Permissions};
+use serde::Deserialize;
+use std::fmt;
+
+/// Metadata of a GitHub App, returned by [`get_app`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct App {
+    /// App ID.
+    pub id: u64,
+
+    /// URL-friendly name of the app, as used in
+    /// `https://github.com/apps/{slug}`.
+    pub slug: String,
+
+    /// Display name of the app.
+    pub name: String,
+
+    /// Account that owns the app.
+    pub owner: Option<Account>,
+
+    /// Permissions the app requests from installations.
+    #[serde(default)]
+    pub permissions: Permissions,
+
+    /// Webhook events the app subscribes to.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// Get the app that the parameters authenticate as, by calling
+/// `GET /app` with the app JWT.
+///
+/// This is a cheap way to check at startup that the private key
+/// belongs to the configured app: GitHub rejects a JWT signed with
+/// the wrong key with [`AuthError::is_credential_error`] set, and the
+/// returned [`App::id`] can be compared with the expected one.
+pub async fn get_app(params: &GithubAuthParams) -> Result<App, AuthError> {
+    let client = params.build_client()?;
+    let jwt = app_jwt(params).await?;
+    let request =
+        params.api_headers(client.get(params.api_url("/app")).bearer_auth(jwt));
+    let resp = send(params, request).await?;
+    Ok(check_status(resp).await?.json().await?)
+}
+
+/// Credentials of an app created from a manifest, returned by
+/// [`convert_app_manifest`].
+///
+/// GitHub only returns these once, so they must be stored right away.
+#[derive(Clone, Deserialize)]
+pub struct AppManifestConversion {
+    /// App ID.
+    pub id: u64,
+
+    /// URL-friendly name of the app.
+    pub slug: String,
+
+    /// Display name of the app.
+    pub name: String,
+
+    /// Account that owns the app.
+    pub owner: Option<Account>,
+
+    /// The app's client ID.
+    pub client_id: String,
+
+    /// The app's client secret, for the
+    /// [user authorization flows](crate::user_auth).
+    pub client_secret: String,
+
+    /// Secret used to sign webhook deliveries, if the manifest
+    /// configured a webhook.
+    pub webhook_secret: Option<String>,
+
+    /// The app's private key, in PEM format.
+    pub pem: String,
+}
+
+impl AppManifestConversion {
+    /// Parameters that authenticate as the new app, taking the other
+    /// settings, such as the user agent and installation ID, from
+    /// `params`.
+    pub fn auth_params(&self, params: GithubAuthParams) -> GithubAuthParams {
+        GithubAuthParams {
+            app_id: self.id,
+            client_id: Some(self.client_id.clone()),
+            private_key: self.pem.clone().into_bytes(),
+            ..params
+        }
+    }
+}
+
+/// The secrets and private key are redacted.
+impl fmt::Debug for AppManifestConversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppManifestConversion")
+            .field("id", &self.id)
+            .field("slug", &self.slug)
+            .field("name", &self.name)
+            .field("owner", &self.owner)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"[redacted]")
+            .field(
+                "webhook_secret",
+                &self.webhook_secret.as_ref().map(|_| "[redacted]"),
+            )
+            .field("pem", &"[redacted]")
+            .finish()
+    }
+}
+
+/// Finish creating an app from a manifest, by exchanging the `code`
+/// that GitHub passed to the manifest's redirect URL for the app's
+/// credentials with `POST /app-manifests/{code}/conversions`.
+///
+/// The code expires after one hour. This request is not
+/// authenticated, so only the user agent, base URL, and HTTP settings
+/// of `params` are used; use
+/// [`AppManifestConversion::auth_params`] to authenticate as the new
+/// app afterwards.
+pub async fn convert_app_manifest(
+    params: &GithubAuthParams,
+    code: &str,
+) -> Result<AppManifestConversion, AuthError> {
+    let client = params.build_client()?;
+    let url = params.api_url(&format!("/app-manifests/{}/conversions", code));
+    let request = params.api_headers(client.post(url));
+    let resp = send(params, request).await?;
+    Ok(check_status(resp).await?.json().await?)
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockGithub, TEST_APP_ID, TEST_MANIFEST_CODE};
+
+    #[tokio::test]
+    async fn test_get_app() {
+        let github = MockGithub::start().await.unwrap();
+        let app = get_app(&github.params()).await.unwrap();
+        assert_eq!(app.id, TEST_APP_ID);
+        assert_eq!(app.slug, "mock-app");
+        assert_eq!(app.events, ["push"]);
+        assert_eq!(app.owner.unwrap().login, "mock-org");
+    }
+
+    #[tokio::test]
+    async fn test_convert_app_manifest() {
+        let github = MockGithub::start().await.unwrap();
+        let base = GithubAuthParams {
+            private_key: Vec::new(),
+            ..github.params()
+        };
+        let conversion = convert_app_manifest(&base, TEST_MANIFEST_CODE)
+            .await
+            .unwrap();
+        assert_eq!(conversion.id, TEST_APP_ID);
+        assert!(!format!("{:?}", conversion).contains("PRIVATE KEY"));
+
+        let params = conversion.auth_params(base.clone());
+        assert_eq!(get_app(&params).await.unwrap().id, TEST_APP_ID);
+
+        let err = convert_app_manifest(&base, "wrong").await.unwrap_err();
+        assert_eq!(err.status(), Some(http::StatusCode::NOT_FOUND));
+    }
+}
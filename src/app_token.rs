@@ -0,0 +1,104 @@
+use crate::{sign_jwt, AuthError, GithubAuthParams, DEFAULT_JWT_LIFETIME};
+use http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use std::fmt;
+use std::time::Instant;
+
+/// A reusable app JWT, for endpoints such as `GET /app` and
+/// `GET /app/installations` that must be called as the app rather
+/// than with an installation access token.
+///
+/// The JWT is signed once and reused until half of its lifetime (see
+/// [`GithubAuthParams::jwt_lifetime`]) has passed, then a new one is
+/// signed. This avoids an RSA signature per request while never
+/// handing out a JWT that is about to expire. No requests are made,
+/// so this is available without the `client` feature.
+pub struct AppToken {
+    params: GithubAuthParams,
+    key: jsonwebtoken::EncodingKey,
+    jwt: String,
+    renew_at: Instant,
+}
+
+impl AppToken {
+    /// Sign the first JWT. Fails if the private key can't be used or
+    /// the claims are invalid.
+    pub fn new(params: GithubAuthParams) -> Result<AppToken, AuthError> {
+        let key = params.signing_key()?.into_owned();
+        let mut token = AppToken {
+            params,
+            key,
+            jwt: String::new(),
+            renew_at: Instant::now(),
+        };
+        token.renew()?;
+        Ok(token)
+    }
+
+    /// Get the JWT, signing a new one first if necessary.
+    pub fn jwt(&mut self) -> Result<&str, AuthError> {
+        if Instant::now() >= self.renew_at {
+            self.renew()?;
+        }
+        Ok(&self.jwt)
+    }
+
+    /// Get an HTTP authentication header with the JWT as a bearer
+    /// token, signing a new JWT first if necessary.
+    pub fn header(&mut self) -> Result<HeaderMap, AuthError> {
+        let mut value =
+            HeaderValue::try_from(format!("Bearer {}", self.jwt()?))?;
+        value.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+        Ok(headers)
+    }
+
+    fn renew(&mut self) -> Result<(), AuthError> {
+        let signed_at = Instant::now();
+        self.jwt = sign_jwt(&self.params, &self.key)?;
+        let lifetime = self.params.jwt_lifetime.unwrap_or(DEFAULT_JWT_LIFETIME);
+        self.renew_at = signed_at + lifetime / 2;
+        Ok(())
+    }
+}
+
+/// The JWT is redacted, so the token can be logged.
+impl fmt::Debug for AppToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppToken")
+            .field("params", &self.params)
+            .field("jwt", &"[redacted]")
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_app_token() {
+        let mut token = AppToken::new(GithubAuthParams {
+            private_key: include_bytes!("../tests/data/test_private_key.pem")
+                .to_vec(),
+            app_id: 1234,
+            ..Default::default()
+        })
+        .unwrap();
+        let jwt = token.jwt().unwrap().to_string();
+        let headers = token.header().unwrap();
+        assert_eq!(headers[AUTHORIZATION], format!("Bearer {}", jwt));
+        assert!(headers[AUTHORIZATION].is_sensitive());
+        assert!(!format!("{:?}", token).contains(&jwt));
+
+        // Past half of the lifetime, a new JWT is signed.
+        let renew_at = token.renew_at;
+        assert!(renew_at > Instant::now() + Duration::from_secs(20));
+        token.renew_at = Instant::now();
+        token.jwt().unwrap();
+        assert!(token.renew_at >= renew_at);
+
+        assert!(AppToken::new(GithubAuthParams::default()).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{AuthError, InstallationAccessToken};
+use chrono::{DateTime, Utc};
+use futures_channel::oneshot;
+use futures_util::future::{self, Either};
+use reqwest::header::HeaderMap;
+use std::pin::pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How long to wait before trying again after a background refresh
+/// fails, unless the error asks for longer.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Handle to a token that is refreshed by a background task, created
+/// with [`InstallationAccessToken::spawn_auto_refresh`].
+///
+/// [`header`](Self::header) only copies the latest headers, so request
+/// paths never wait for a refresh. The task stops when the handle is
+/// dropped, or when [`shutdown`](Self::shutdown) is called.
+pub struct AutoRefreshHandle {
+    latest: Arc<RwLock<Latest>>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<InstallationAccessToken>,
+}
+
+struct Latest {
+    headers: HeaderMap,
+    expires_at: DateTime<Utc>,
+}
+
+impl InstallationAccessToken {
+    /// Move the token into a background task that refreshes it before
+    /// it expires, so that callers never pay for a refresh inline.
+    ///
+    /// The task refreshes the token once
+    /// [`needs_refresh`](Self::needs_refresh) is true, so set
+    /// [`refresh_safety_margin`](Self::refresh_safety_margin) first to
+    /// refresh earlier. If a refresh fails, it is logged and retried
+    /// after ten seconds, or after the error's
+    /// [`retry_after`](AuthError::retry_after) if that is longer; the
+    /// handle keeps returning the current token in the meantime.
+    ///
+    /// This must be called from within a Tokio runtime. It fails if
+    /// the token needs refreshing and the refresh fails.
+    pub async fn spawn_auto_refresh(
+        mut self,
+    ) -> Result<AutoRefreshHandle, AuthError> {
+        let latest = Arc::new(RwLock::new(Latest {
+            headers: self.header().await?,
+            expires_at: self.expires_at,
+        }));
+        let (shutdown, stop) = oneshot::channel();
+        let task = tokio::spawn(run(self, latest.clone(), stop));
+        Ok(AutoRefreshHandle {
+            latest,
+            shutdown,
+            task,
+        })
+    }
+}
+
+impl AutoRefreshHandle {
+    /// Get the headers for the latest token. This never makes a
+    /// request; see [`InstallationAccessToken::header`] for the
+    /// headers returned.
+    ///
+    /// If background refreshes keep failing, the token in the headers
+    /// may have expired, see [`expires_at`](Self::expires_at).
+    pub fn header(&self) -> HeaderMap {
+        self.latest.read().unwrap().headers.clone()
+    }
+
+    /// Time at which the latest token expires.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.latest.read().unwrap().expires_at
+    }
+
+    /// Stop the background task and return the token, for example to
+    /// [`revoke`](InstallationAccessToken::revoke) it. If a refresh is
+    /// in progress, it is cancelled.
+    ///
+    /// Returns [`AuthError::Cancelled`] if the task was cancelled, for
+    /// example because the runtime is shutting down, in which case the
+    /// token is lost. If the task panicked, the panic is resumed.
+    pub async fn shutdown(self) -> Result<InstallationAccessToken, AuthError> {
+        let _ = self.shutdown.send(());
+        match self.task.await {
+            Ok(token) => Ok(token),
+            Err(err) if err.is_panic() => {
+                std::panic::resume_unwind(err.into_panic())
+            }
+            Err(_) => Err(AuthError::Cancelled),
+        }
+    }
+}
+
+async fn run(
+    mut token: InstallationAccessToken,
+    latest: Arc<RwLock<Latest>>,
+    mut stop: oneshot::Receiver<()>,
+) -> InstallationAccessToken {
+    let mut delay = time_until_refresh(&token);
+    loop {
+        let refresh = async {
+            tokio::time::sleep(delay).await;
+            token.header().await
+        };
+        let headers = match future::select(pin!(refresh), &mut stop).await {
+            Either::Left((headers, _)) => headers,
+            // Either shutdown was called or the handle was dropped.
+            Either::Right(_) => break,
+        };
+        match headers {
+            Ok(headers) => {
+                *latest.write().unwrap() = Latest {
+                    headers,
+                    expires_at: token.expires_at,
+                };
+                // A failed refresh that kept the current token under
+                // the stale token policy also ends up here.
+                delay = if token.needs_refresh() {
+                    RETRY_DELAY
+                } else {
+                    time_until_refresh(&token)
+                };
+            }
+            Err(err) => {
+                warn!("background token refresh failed: {}", err);
+                delay = err.retry_after().unwrap_or_default().max(RETRY_DELAY);
+            }
+        }
+    }
+    token
+}
+
+fn time_until_refresh(token: &InstallationAccessToken) -> Duration {
+    (token.next_refresh_at() - token.params.now())
+        .to_std()
+        .unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+
+    #[tokio::test]
+    async fn test_auto_refresh() {
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(chrono::Duration::seconds(30));
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        token.refresh_safety_margin = chrono::Duration::milliseconds(29_900);
+
+        let handle = token.spawn_auto_refresh().await.unwrap();
+        let first = handle.header();
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert_ne!(handle.header(), first);
+        assert!(github.token_requests() >= 3);
+
+        let token = handle.shutdown().await.unwrap();
+        assert!(token.refresh_count() >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancelled() {
+        let github = MockGithub::start().await.unwrap();
+        let token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let handle = token.spawn_auto_refresh().await.unwrap();
+        handle.task.abort();
+        assert!(matches!(handle.shutdown().await, Err(AuthError::Cancelled)));
+    }
+}
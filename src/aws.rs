@@ -0,0 +1,1029 @@
+//! Load the app's private key from AWS Secrets Manager or SSM
+//! Parameter Store, and share installation access tokens through
+//! DynamoDB with [`DynamoDbTokenCache`].
+//!
+//! Requests are signed with the ambient AWS credentials, found the
+//! same way as the AWS SDKs do: the `AWS_ACCESS_KEY_ID`,
+//! `AWS_SECRET_ACCESS_KEY`, and `AWS_SESSION_TOKEN` environment
+//! variables, then the shared credentials file, then a web identity
+//! token (`AWS_WEB_IDENTITY_TOKEN_FILE` and `AWS_ROLE_ARN`, as set for
+//! EKS service accounts), then the ECS container credentials endpoint,
+//! and finally the EC2 instance metadata service. Temporary
+//! credentials are fetched again shortly before they expire. The
+//! region is read from `AWS_REGION` or `AWS_DEFAULT_REGION`.
+//!
+//! ```no_run
+//! use github_app_auth::aws::AwsKeySource;
+//! use github_app_auth::GithubAuthParams;
+//!
+//! # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+//! let source = AwsKeySource::from_env().await?;
+//! let params = GithubAuthParams {
+//!     private_key: source.secrets_manager("github-app-key").await?,
+//!     ..GithubAuthParams::new("my-app", Vec::new(), 1234, 5678)
+//! };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The key is fetched once. To pick up a rotated key, fetch it again
+//! and create new parameters, for example from a handler for the
+//! secret's rotation event.
+//!
+//! This module requires the `aws` feature.
+
+use crate::api_error::error_from_response;
+use crate::response::check_status;
+use crate::TokenState;
+use crate::{AuthError, TokenCacheFuture, TokenCacheKey, TokenCacheStore};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::{digest, hmac};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Address of the ECS container credentials endpoint.
+const ECS_CREDENTIALS_HOST: &str = "http://169.254.170.2";
+
+/// Address of the EC2 instance metadata service.
+const IMDS_HOST: &str = "http://169.254.169.254";
+
+/// The metadata services are local, so give up quickly if they are
+/// not there rather than stall startup off EC2.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long before they expire temporary credentials are replaced.
+const CREDENTIALS_REFRESH_MARGIN: chrono::Duration =
+    chrono::Duration::minutes(5);
+
+/// AWS credentials used to sign requests.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    /// Access key ID.
+    pub access_key_id: String,
+
+    /// Secret access key.
+    pub secret_access_key: String,
+
+    /// Session token, for temporary credentials.
+    pub session_token: Option<String>,
+
+    /// When temporary credentials expire, if known.
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"[redacted]")
+            .field("session_token", &self.session_token.as_ref().map(|_| ".."))
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
+
+/// Credentials as returned by the container and instance metadata
+/// endpoints.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: Option<String>,
+    expiration: Option<DateTime<Utc>>,
+}
+
+impl From<MetadataCredentials> for AwsCredentials {
+    fn from(creds: MetadataCredentials) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: creds.access_key_id,
+            secret_access_key: creds.secret_access_key,
+            session_token: creds.token,
+            expiration: creds.expiration,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssumeRoleWithWebIdentityResponse {
+    assume_role_with_web_identity_response: AssumeRoleWithWebIdentityOutput,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssumeRoleWithWebIdentityOutput {
+    assume_role_with_web_identity_result: AssumeRoleWithWebIdentityResult,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssumeRoleWithWebIdentityResult {
+    credentials: StsCredentials,
+}
+
+/// Credentials as returned by STS in JSON, with the expiration in
+/// seconds since the epoch.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    expiration: f64,
+}
+
+impl From<StsCredentials> for AwsCredentials {
+    fn from(creds: StsCredentials) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: creds.access_key_id,
+            secret_access_key: creds.secret_access_key,
+            session_token: Some(creds.session_token),
+            expiration: DateTime::from_timestamp(creds.expiration as i64, 0),
+        }
+    }
+}
+
+impl AwsCredentials {
+    /// Find credentials in the environment, the shared credentials
+    /// file, a web identity token, the ECS container credentials
+    /// endpoint, or the EC2 instance metadata service, in that order.
+    pub async fn from_env(
+        client: &reqwest::Client,
+    ) -> Result<AwsCredentials, AuthError> {
+        if let Some(creds) = Self::from_env_vars() {
+            return Ok(creds);
+        }
+        if let Some(creds) = Self::from_shared_file() {
+            return Ok(creds);
+        }
+        if let Some(creds) = Self::from_web_identity(client).await? {
+            return Ok(creds);
+        }
+        if let Some(creds) = Self::from_container(client).await? {
+            return Ok(creds);
+        }
+        if env::var("AWS_EC2_METADATA_DISABLED").as_deref() != Ok("true") {
+            if let Ok(creds) = Self::from_instance_metadata(client).await {
+                return Ok(creds);
+            }
+        }
+        Err(AuthError::InvalidConfig("no AWS credentials found".into()))
+    }
+
+    /// Whether the credentials expire within a few minutes, so that
+    /// they should be fetched again before signing a request.
+    pub fn needs_refresh(&self) -> bool {
+        self.expiration.is_some_and(|expiration| {
+            expiration - CREDENTIALS_REFRESH_MARGIN <= Utc::now()
+        })
+    }
+
+    fn from_env_vars() -> Option<AwsCredentials> {
+        Some(AwsCredentials {
+            access_key_id: env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            expiration: None,
+        })
+    }
+
+    fn from_shared_file() -> Option<AwsCredentials> {
+        let path = match env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                PathBuf::from(env::var_os("HOME")?).join(".aws/credentials")
+            }
+        };
+        let contents = std::fs::read_to_string(path).ok()?;
+        let profile =
+            env::var("AWS_PROFILE").unwrap_or_else(|_| "default".into());
+        parse_shared_credentials(&contents, &profile)
+    }
+
+    async fn from_web_identity(
+        client: &reqwest::Client,
+    ) -> Result<Option<AwsCredentials>, AuthError> {
+        let (Some(token_file), Ok(role_arn)) = (
+            env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE"),
+            env::var("AWS_ROLE_ARN"),
+        ) else {
+            return Ok(None);
+        };
+        let token = std::fs::read_to_string(token_file)?;
+        let session_name = env::var("AWS_ROLE_SESSION_NAME")
+            .unwrap_or_else(|_| "github-app-auth".into());
+        let endpoint = match env::var("AWS_ENDPOINT_URL_STS") {
+            Ok(url) => url,
+            Err(_) => match env::var("AWS_REGION")
+                .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            {
+                Ok(region) => format!("https://sts.{}.amazonaws.com", region),
+                Err(_) => "https://sts.amazonaws.com".into(),
+            },
+        };
+        let creds = assume_role_with_web_identity(
+            client,
+            &endpoint,
+            &role_arn,
+            &session_name,
+            token.trim(),
+        )
+        .await?;
+        Ok(Some(creds))
+    }
+
+    async fn from_container(
+        client: &reqwest::Client,
+    ) -> Result<Option<AwsCredentials>, AuthError> {
+        let url = if let Ok(uri) =
+            env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI")
+        {
+            format!("{}{}", ECS_CREDENTIALS_HOST, uri)
+        } else if let Ok(url) = env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI") {
+            url
+        } else {
+            return Ok(None);
+        };
+        let token = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN").ok();
+        let creds = fetch_container_credentials(client, &url, token).await?;
+        Ok(Some(creds))
+    }
+
+    async fn from_instance_metadata(
+        client: &reqwest::Client,
+    ) -> Result<AwsCredentials, AuthError> {
+        let token = client
+            .put(format!("{}/latest/api/token", IMDS_HOST))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+            .timeout(METADATA_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let get = |path: String| {
+            client
+                .get(format!(
+                    "{}/latest/meta-data/iam/security-credentials/{}",
+                    IMDS_HOST, path
+                ))
+                .header("X-aws-ec2-metadata-token", &token)
+                .timeout(METADATA_TIMEOUT)
+        };
+        let roles = get(String::new())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let role = roles.lines().next().unwrap_or_default().to_string();
+        let creds: MetadataCredentials =
+            get(role).send().await?.error_for_status()?.json().await?;
+        Ok(creds.into())
+    }
+}
+
+/// Exchange a web identity token for temporary credentials with the
+/// STS `AssumeRoleWithWebIdentity` action, which needs no signing.
+async fn assume_role_with_web_identity(
+    client: &reqwest::Client,
+    endpoint: &str,
+    role_arn: &str,
+    session_name: &str,
+    token: &str,
+) -> Result<AwsCredentials, AuthError> {
+    let request = client
+        .post(endpoint)
+        .header("Accept", "application/json")
+        .form(&[
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", "2011-06-15"),
+            ("RoleArn", role_arn),
+            ("RoleSessionName", session_name),
+            ("WebIdentityToken", token),
+        ]);
+    let resp = check_status(request.send().await?).await?;
+    let resp: AssumeRoleWithWebIdentityResponse = resp.json().await?;
+    Ok(resp
+        .assume_role_with_web_identity_response
+        .assume_role_with_web_identity_result
+        .credentials
+        .into())
+}
+
+/// Get credentials from the ECS container credentials endpoint at
+/// `url`.
+async fn fetch_container_credentials(
+    client: &reqwest::Client,
+    url: &str,
+    authorization: Option<String>,
+) -> Result<AwsCredentials, AuthError> {
+    let mut request = client.get(url).timeout(METADATA_TIMEOUT);
+    if let Some(token) = authorization {
+        request = request.header("Authorization", token);
+    }
+    let resp = check_status(request.send().await?).await?;
+    Ok(resp.json::<MetadataCredentials>().await?.into())
+}
+
+/// Get a profile's credentials from the contents of a shared
+/// credentials file.
+fn parse_shared_credentials(
+    contents: &str,
+    profile: &str,
+) -> Option<AwsCredentials> {
+    let mut in_profile = false;
+    let mut access_key_id = None;
+    let mut secret_access_key = None;
+    let mut session_token = None;
+    for line in contents.lines().map(str::trim) {
+        if let Some(section) =
+            line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+        {
+            in_profile = section.trim() == profile;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !in_profile {
+            continue;
+        }
+        let value = Some(value.trim().to_string());
+        match key.trim() {
+            "aws_access_key_id" => access_key_id = value,
+            "aws_secret_access_key" => secret_access_key = value,
+            "aws_session_token" => session_token = value,
+            _ => {}
+        }
+    }
+    Some(AwsCredentials {
+        access_key_id: access_key_id?,
+        secret_access_key: secret_access_key?,
+        session_token,
+        expiration: None,
+    })
+}
+
+/// Fetches secrets from AWS Secrets Manager or SSM Parameter Store.
+#[derive(Clone, Debug)]
+pub struct AwsKeySource {
+    /// AWS region, for example `us-east-1`.
+    pub region: String,
+
+    /// Endpoint to send requests to instead of the regional AWS
+    /// endpoint, for example for a VPC endpoint or LocalStack.
+    pub endpoint_url: Option<String>,
+
+    credentials: Arc<Mutex<AwsCredentials>>,
+    refresh_credentials: bool,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecretValue {
+    secret_string: Option<String>,
+    secret_binary: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetParameterOutput {
+    parameter: Parameter,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Parameter {
+    value: String,
+}
+
+impl AwsKeySource {
+    /// Create a source from the ambient AWS configuration: the region
+    /// from `AWS_REGION` or `AWS_DEFAULT_REGION`, the endpoint from
+    /// `AWS_ENDPOINT_URL` if set, and credentials as described in the
+    /// [module documentation](self).
+    pub async fn from_env() -> Result<AwsKeySource, AuthError> {
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .map_err(|_| AuthError::MissingEnvVar("AWS_REGION".into()))?;
+        let client = reqwest::Client::new();
+        let credentials = AwsCredentials::from_env(&client).await?;
+        Ok(AwsKeySource {
+            region,
+            endpoint_url: env::var("AWS_ENDPOINT_URL").ok(),
+            credentials: Arc::new(Mutex::new(credentials)),
+            refresh_credentials: true,
+            client,
+        })
+    }
+
+    /// Create a source with explicit settings. The credentials are
+    /// used as they are, even after they expire.
+    pub fn new(region: impl Into<String>, credentials: AwsCredentials) -> Self {
+        AwsKeySource {
+            region: region.into(),
+            endpoint_url: None,
+            credentials: Arc::new(Mutex::new(credentials)),
+            refresh_credentials: false,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Get the credentials used to sign requests, fetching new ones
+    /// first if they were found with [`from_env`](Self::from_env) and
+    /// are about to expire.
+    pub async fn credentials(&self) -> Result<AwsCredentials, AuthError> {
+        let credentials = self.credentials.lock().unwrap().clone();
+        if !(self.refresh_credentials && credentials.needs_refresh()) {
+            return Ok(credentials);
+        }
+        debug!("AWS credentials are about to expire, fetching new ones");
+        let credentials = AwsCredentials::from_env(&self.client).await?;
+        *self.credentials.lock().unwrap() = credentials.clone();
+        Ok(credentials)
+    }
+
+    /// Get the value of a Secrets Manager secret by name or ARN.
+    /// Both string and binary secrets are supported.
+    pub async fn secrets_manager(
+        &self,
+        secret_id: &str,
+    ) -> Result<Vec<u8>, AuthError> {
+        let body = serde_json::json!({ "SecretId": secret_id });
+        let value: SecretValue = self
+            .call("secretsmanager", "secretsmanager.GetSecretValue", &body)
+            .await?;
+        if let Some(string) = value.secret_string {
+            return Ok(string.into_bytes());
+        }
+        let binary = value.secret_binary.ok_or_else(|| {
+            AuthError::InvalidConfig(format!("secret {} is empty", secret_id))
+        })?;
+        STANDARD.decode(binary).map_err(|err| {
+            AuthError::InvalidConfig(format!(
+                "invalid binary secret {}: {}",
+                secret_id, err
+            ))
+        })
+    }
+
+    /// Get the value of an SSM parameter by name or ARN, decrypting it
+    /// if it is a `SecureString`.
+    pub async fn ssm_parameter(
+        &self,
+        name: &str,
+    ) -> Result<Vec<u8>, AuthError> {
+        let body = serde_json::json!({ "Name": name, "WithDecryption": true });
+        let output: GetParameterOutput =
+            self.call("ssm", "AmazonSSM.GetParameter", &body).await?;
+        Ok(output.parameter.value.into_bytes())
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        service: &str,
+        target: &str,
+        body: &serde_json::Value,
+    ) -> Result<T, AuthError> {
+        let content_type = "application/x-amz-json-1.1";
+        let resp = self.send(service, target, content_type, body).await?;
+        Ok(check_status(resp).await?.json().await?)
+    }
+
+    /// Send a signed request to a JSON API, returning the response
+    /// whatever its status.
+    async fn send(
+        &self,
+        service: &str,
+        target: &str,
+        content_type: &'static str,
+        body: &serde_json::Value,
+    ) -> Result<reqwest::Response, AuthError> {
+        let url = match &self.endpoint_url {
+            Some(url) => url.clone(),
+            None => {
+                format!("https://{}.{}.amazonaws.com", service, self.region)
+            }
+        };
+        let host = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|url| {
+                let host = url.host_str()?.to_string();
+                Some(match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host,
+                })
+            })
+            .ok_or_else(|| {
+                AuthError::InvalidConfig(format!(
+                    "invalid AWS endpoint {}",
+                    url
+                ))
+            })?;
+        let body = body.to_string();
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", host),
+            ("x-amz-target", target.to_string()),
+        ];
+        let authorization = sign(
+            &self.credentials().await?,
+            &self.region,
+            service,
+            Utc::now(),
+            "POST",
+            "/",
+            &mut headers,
+            body.as_bytes(),
+        );
+        let mut request = self.client.post(&url).body(body);
+        for (name, value) in headers {
+            if name != "host" {
+                request = request.header(name, value);
+            }
+        }
+        let request = request.header("authorization", authorization);
+        Ok(request.send().await?)
+    }
+}
+
+/// A [`TokenCacheStore`] that keeps installation access tokens in a
+/// DynamoDB table, so that replicas of a service share them.
+///
+/// The table needs a string partition key named `key`. Each item
+/// stores the [`TokenState`] as JSON in `state`, and the time at
+/// which the token expires, in seconds since the Unix epoch, in
+/// `expires_at`; enable DynamoDB's time to live on `expires_at` to
+/// have expired tokens deleted. A token only replaces the stored one
+/// if it expires later, so replicas that refresh at the same time
+/// don't overwrite a newer token with an older one.
+///
+/// ```no_run
+/// use github_app_auth::aws::{AwsKeySource, DynamoDbTokenCache};
+/// use github_app_auth::GithubAuthParams;
+/// use std::sync::Arc;
+///
+/// # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+/// let source = AwsKeySource::from_env().await?;
+/// let params = GithubAuthParams {
+///     token_cache: Some(Arc::new(DynamoDbTokenCache::new(
+///         source,
+///         "github-tokens",
+///     ))),
+///     ..GithubAuthParams::new("my-app", Vec::new(), 1234, 5678)
+/// };
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DynamoDbTokenCache {
+    /// Region, endpoint, and credentials used for the requests.
+    pub source: AwsKeySource,
+
+    /// Name of the table.
+    pub table: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetItemOutput {
+    item: Option<CachedItem>,
+}
+
+#[derive(Deserialize)]
+struct CachedItem {
+    state: StringAttribute,
+}
+
+#[derive(Deserialize)]
+struct StringAttribute {
+    #[serde(rename = "S")]
+    s: String,
+}
+
+#[derive(Deserialize)]
+struct DynamoDbError {
+    #[serde(rename = "__type", default)]
+    error_type: String,
+}
+
+impl DynamoDbTokenCache {
+    /// Create a cache that stores tokens in `table`.
+    pub fn new(source: AwsKeySource, table: impl Into<String>) -> Self {
+        DynamoDbTokenCache {
+            source,
+            table: table.into(),
+        }
+    }
+
+    async fn get_item(
+        &self,
+        key: &TokenCacheKey,
+    ) -> Result<Option<TokenState>, AuthError> {
+        let body = serde_json::json!({
+            "TableName": self.table,
+            "Key": { "key": { "S": key.to_string() } },
+            "ConsistentRead": true,
+        });
+        let resp = self
+            .source
+            .send(
+                "dynamodb",
+                "DynamoDB_20120810.GetItem",
+                "application/x-amz-json-1.0",
+                &body,
+            )
+            .await?;
+        let output: GetItemOutput = check_status(resp).await?.json().await?;
+        let Some(item) = output.item else {
+            return Ok(None);
+        };
+        let state: TokenState =
+            serde_json::from_str(&item.state.s).map_err(|err| {
+                AuthError::InvalidConfig(format!(
+                    "invalid token in DynamoDB table {}: {}",
+                    self.table, err
+                ))
+            })?;
+        // Time to live deletes expired items some time after they
+        // expire, not immediately.
+        Ok(Some(state).filter(|state| state.expires_at > Utc::now()))
+    }
+
+    async fn put_item(
+        &self,
+        key: &TokenCacheKey,
+        state: &TokenState,
+    ) -> Result<(), AuthError> {
+        let expires_at = state.expires_at.timestamp().to_string();
+        let json = serde_json::to_string(state).map_err(|err| {
+            AuthError::InvalidConfig(format!("cannot encode token: {}", err))
+        })?;
+        let body = serde_json::json!({
+            "TableName": self.table,
+            "Item": {
+                "key": { "S": key.to_string() },
+                "state": { "S": json },
+                "expires_at": { "N": expires_at },
+            },
+            "ConditionExpression":
+                "attribute_not_exists(#key) OR expires_at < :expires_at",
+            "ExpressionAttributeNames": { "#key": "key" },
+            "ExpressionAttributeValues": {
+                ":expires_at": { "N": expires_at },
+            },
+        });
+        let resp = self
+            .source
+            .send(
+                "dynamodb",
+                "DynamoDB_20120810.PutItem",
+                "application/x-amz-json-1.0",
+                &body,
+            )
+            .await?;
+        let status = resp.status();
+        if status != reqwest::StatusCode::BAD_REQUEST {
+            check_status(resp).await?;
+            return Ok(());
+        }
+        let headers = resp.headers().clone();
+        let text = resp.text().await.unwrap_or_default();
+        let error: Option<DynamoDbError> = serde_json::from_str(&text).ok();
+        if error.is_some_and(|error| {
+            error
+                .error_type
+                .ends_with("#ConditionalCheckFailedException")
+        }) {
+            debug!("DynamoDB already has a newer token for {}", key);
+            return Ok(());
+        }
+        Err(error_from_response(status, &headers, &text))
+    }
+}
+
+impl TokenCacheStore for DynamoDbTokenCache {
+    fn get<'a>(
+        &'a self,
+        key: &'a TokenCacheKey,
+    ) -> TokenCacheFuture<'a, Option<TokenState>> {
+        Box::pin(self.get_item(key))
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a TokenCacheKey,
+        state: &'a TokenState,
+    ) -> TokenCacheFuture<'a, ()> {
+        Box::pin(self.put_item(key, state))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+}
+
+/// Sign a request with AWS Signature Version 4, returning the value
+/// of the `Authorization` header. The `x-amz-date` header, and
+/// `x-amz-security-token` for temporary credentials, are added to
+/// `headers`, which must include `host`. Header names must be
+/// lowercase and the path must have no query string.
+#[allow(clippy::too_many_arguments)]
+fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    now: DateTime<Utc>,
+    method: &str,
+    path: &str,
+    headers: &mut Vec<(&'static str, String)>,
+    body: &[u8],
+) -> String {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    headers.push(("x-amz-date", amz_date.clone()));
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.sort();
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers,
+        sha256_hex(body)
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac_sha256(key.as_bytes(), &date);
+    let key = hmac_sha256(key.as_ref(), region);
+    let key = hmac_sha256(key.as_ref(), service);
+    let key = hmac_sha256(key.as_ref(), "aws4_request");
+    let signature = hex(hmac_sha256(key.as_ref(), &string_to_sign).as_ref());
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{serve_once, serve_once_with_status};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_sign() {
+        // The "get-vanilla" case from the AWS Signature Version 4 test
+        // suite.
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY"
+                .into(),
+            session_token: None,
+            expiration: None,
+        };
+        let mut headers = vec![("host", "example.amazonaws.com".to_string())];
+        let authorization = sign(
+            &credentials,
+            "us-east-1",
+            "service",
+            Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap(),
+            "GET",
+            "/",
+            &mut headers,
+            b"",
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 \
+             Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        assert!(!format!("{:?}", credentials).contains("EXAMPLEKEY"));
+    }
+
+    #[tokio::test]
+    async fn test_assume_role_with_web_identity() {
+        let (url, request) = serve_once(
+            r#"{"AssumeRoleWithWebIdentityResponse": {
+                "AssumeRoleWithWebIdentityResult": {
+                    "Credentials": {
+                        "AccessKeyId": "ASIAWEB",
+                        "SecretAccessKey": "web-secret",
+                        "SessionToken": "web-session",
+                        "Expiration": 1.893456E9
+                    }
+                }
+            }}"#,
+        )
+        .await;
+        let client = reqwest::Client::new();
+        let creds = assume_role_with_web_identity(
+            &client,
+            &url,
+            "arn:aws:iam::123456789012:role/app",
+            "session",
+            "web-token",
+        )
+        .await
+        .unwrap();
+        assert_eq!(creds.access_key_id, "ASIAWEB");
+        assert_eq!(creds.session_token.as_deref(), Some("web-session"));
+        assert_eq!(
+            creds.expiration,
+            Some(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap())
+        );
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST / "));
+        assert!(request.contains("Action=AssumeRoleWithWebIdentity"));
+        assert!(request.contains("WebIdentityToken=web-token"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_container_credentials() {
+        let (url, request) = serve_once(
+            r#"{"AccessKeyId": "ASIAECS", "SecretAccessKey": "ecs-secret",
+                "Token": "ecs-session",
+                "Expiration": "2030-01-01T00:00:00Z"}"#,
+        )
+        .await;
+        let client = reqwest::Client::new();
+        let creds = fetch_container_credentials(
+            &client,
+            &format!("{}/v2/credentials", url),
+            Some("ecs-auth".into()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(creds.access_key_id, "ASIAECS");
+        assert_eq!(
+            creds.expiration,
+            Some(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert!(!creds.needs_refresh());
+        let request = request.await.unwrap().to_lowercase();
+        assert!(request.starts_with("get /v2/credentials "));
+        assert!(request.contains("authorization: ecs-auth"));
+    }
+
+    #[tokio::test]
+    async fn test_credentials_refresh() {
+        let mut creds = AwsCredentials {
+            access_key_id: "AKID".into(),
+            secret_access_key: "secret".into(),
+            session_token: None,
+            expiration: None,
+        };
+        assert!(!creds.needs_refresh());
+        creds.expiration = Some(Utc::now() + chrono::Duration::hours(1));
+        assert!(!creds.needs_refresh());
+        creds.expiration = Some(Utc::now() + chrono::Duration::minutes(1));
+        assert!(creds.needs_refresh());
+
+        // Explicit credentials have nowhere to be fetched from again.
+        let source = AwsKeySource::new("us-east-1", creds);
+        let creds = source.credentials().await.unwrap();
+        assert_eq!(creds.access_key_id, "AKID");
+    }
+
+    fn dynamodb_cache(url: String) -> DynamoDbTokenCache {
+        let creds = AwsCredentials {
+            access_key_id: "AKID".into(),
+            secret_access_key: "secret".into(),
+            session_token: None,
+            expiration: None,
+        };
+        let mut source = AwsKeySource::new("us-east-1", creds);
+        source.endpoint_url = Some(url);
+        DynamoDbTokenCache::new(source, "tokens")
+    }
+
+    fn cache_key() -> TokenCacheKey {
+        TokenCacheKey::new(&crate::GithubAuthParams {
+            app_id: 1234,
+            installation_id: 5678,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_dynamodb_get() {
+        let (url, request) = serve_once(
+            r#"{"Item": {
+                "key": {"S": "1234:5678"},
+                "state": {"S": "{\"token\": \"cached\", \"expires_at\": \"2030-01-01T00:00:00Z\", \"installation_id\": 5678}"},
+                "expires_at": {"N": "1893456000"}
+            }}"#,
+        )
+        .await;
+        let cache = dynamodb_cache(url);
+        let state = cache.get(&cache_key()).await.unwrap().unwrap();
+        assert_eq!(state.token, "cached");
+        assert_eq!(state.installation_id, 5678);
+        let request = request.await.unwrap().to_lowercase();
+        assert!(request.contains("x-amz-target: dynamodb_20120810.getitem"));
+        assert!(request.contains("content-type: application/x-amz-json-1.0"));
+        assert!(request.contains(r#""key":{"key":{"s":"1234:5678"}}"#));
+
+        let (url, _) = serve_once("{}").await;
+        let cache = dynamodb_cache(url);
+        assert!(cache.get(&cache_key()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dynamodb_put() {
+        let state = TokenState {
+            token: "new".into(),
+            expires_at: Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap(),
+            installation_id: 5678,
+            permissions: Default::default(),
+            repositories: Vec::new(),
+            repository_selection: None,
+        };
+        let (url, request) = serve_once("{}").await;
+        let cache = dynamodb_cache(url);
+        cache.put(&cache_key(), &state).await.unwrap();
+        let request = request.await.unwrap();
+        assert!(request.contains("DynamoDB_20120810.PutItem"));
+        assert!(request.contains(r#""expires_at":{"N":"1893456000"}"#));
+        assert!(request.contains("expires_at < :expires_at"));
+
+        // A newer token is already stored.
+        let (url, _) = serve_once_with_status(
+            "400 Bad Request",
+            r#"{"__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException",
+                "message": "The conditional request failed"}"#,
+        )
+        .await;
+        let cache = dynamodb_cache(url);
+        cache.put(&cache_key(), &state).await.unwrap();
+
+        let (url, _) = serve_once_with_status(
+            "400 Bad Request",
+            r#"{"__type": "com.amazonaws.dynamodb.v20120810#ResourceNotFoundException",
+                "message": "Requested resource not found"}"#,
+        )
+        .await;
+        let cache = dynamodb_cache(url);
+        assert!(cache.put(&cache_key(), &state).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_shared_credentials() {
+        let contents = "\
+[default]
+aws_access_key_id = AKIDDEFAULT
+aws_secret_access_key = default-secret
+
+[deploy]
+aws_access_key_id=AKIDDEPLOY
+aws_secret_access_key=deploy-secret
+aws_session_token=deploy-token
+";
+        let creds = parse_shared_credentials(contents, "default").unwrap();
+        assert_eq!(creds.access_key_id, "AKIDDEFAULT");
+        assert_eq!(creds.session_token, None);
+        let creds = parse_shared_credentials(contents, "deploy").unwrap();
+        assert_eq!(creds.secret_access_key, "deploy-secret");
+        assert_eq!(creds.session_token.as_deref(), Some("deploy-token"));
+        assert!(parse_shared_credentials(contents, "missing").is_none());
+    }
+}
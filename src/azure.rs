@@ -0,0 +1,305 @@
+//! Load the app's private key from an Azure Key Vault secret.
+//!
+//! Requests are authorized with an access token for the ambient
+//! managed identity, fetched the same way as the Azure SDKs do: from
+//! the App Service and Functions identity endpoint if
+//! `IDENTITY_ENDPOINT` and `IDENTITY_HEADER` are set, and otherwise
+//! from the instance metadata service. Set `AZURE_CLIENT_ID` to use a
+//! user-assigned identity. The access token is fetched again shortly
+//! before it expires.
+//!
+//! ```no_run
+//! use github_app_auth::azure::AzureKeySource;
+//! use github_app_auth::GithubAuthParams;
+//!
+//! # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+//! let source = AzureKeySource::from_env("https://my-vault.vault.azure.net");
+//! let params = GithubAuthParams {
+//!     private_key: source.key_vault_secret("github-app-key").await?,
+//!     ..GithubAuthParams::new("my-app", Vec::new(), 1234, 5678)
+//! };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The key is fetched once. To pick up a rotated key, fetch it again
+//! and create new parameters.
+//!
+//! This module requires the `azure` feature.
+
+use crate::response::check_status;
+use crate::AuthError;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Token endpoint of the instance metadata service.
+const IMDS_TOKEN_URL: &str =
+    "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// The identity endpoints are local, so give up quickly if they are
+/// not there rather than stall startup off Azure.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Resource that Key Vault access tokens are requested for.
+const KEY_VAULT_RESOURCE: &str = "https://vault.azure.net";
+
+/// Key Vault REST API version.
+const KEY_VAULT_API_VERSION: &str = "7.4";
+
+/// How long before it expires the access token is replaced.
+const TOKEN_REFRESH_MARGIN: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Where managed identity access tokens come from.
+#[derive(Clone, Debug)]
+enum IdentityEndpoint {
+    /// The instance metadata service, at the given URL.
+    InstanceMetadata(String),
+
+    /// The App Service identity endpoint, with the value of
+    /// `IDENTITY_HEADER`.
+    AppService { url: String, header: String },
+}
+
+/// An OAuth access token for Key Vault.
+#[derive(Clone)]
+struct AccessToken {
+    token: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("token", &"[redacted]")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl AccessToken {
+    fn needs_refresh(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| {
+            expires_at - TOKEN_REFRESH_MARGIN <= Utc::now()
+        })
+    }
+}
+
+/// Seconds since the epoch, which the identity endpoints send as
+/// either a string or a number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Number(i64),
+    String(String),
+}
+
+impl Timestamp {
+    fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        let seconds = match self {
+            Timestamp::Number(seconds) => *seconds,
+            Timestamp::String(seconds) => seconds.parse().ok()?,
+        };
+        DateTime::from_timestamp(seconds, 0)
+    }
+}
+
+/// Access token as returned by the identity endpoints.
+#[derive(Deserialize)]
+struct IdentityToken {
+    access_token: String,
+    expires_on: Option<Timestamp>,
+}
+
+#[derive(Deserialize)]
+struct SecretBundle {
+    value: String,
+}
+
+/// Fetches secrets from Azure Key Vault.
+#[derive(Clone, Debug)]
+pub struct AzureKeySource {
+    /// URL of the vault, for example
+    /// `https://my-vault.vault.azure.net`.
+    pub vault_url: String,
+
+    /// Client ID of a user-assigned managed identity to use instead
+    /// of the system-assigned one.
+    pub client_id: Option<String>,
+
+    /// Where access tokens are fetched from, or `None` if the access
+    /// token was given explicitly.
+    identity: Option<IdentityEndpoint>,
+    token: Arc<Mutex<Option<AccessToken>>>,
+    client: reqwest::Client,
+}
+
+impl AzureKeySource {
+    /// Create a source for the vault at `vault_url` that uses the
+    /// ambient managed identity as described in the
+    /// [module documentation](self). No request is made until a
+    /// secret is fetched.
+    pub fn from_env(vault_url: impl Into<String>) -> AzureKeySource {
+        let identity = match (
+            env::var("IDENTITY_ENDPOINT"),
+            env::var("IDENTITY_HEADER"),
+        ) {
+            (Ok(url), Ok(header)) => {
+                IdentityEndpoint::AppService { url, header }
+            }
+            _ => IdentityEndpoint::InstanceMetadata(IMDS_TOKEN_URL.into()),
+        };
+        AzureKeySource {
+            vault_url: vault_url.into(),
+            client_id: env::var("AZURE_CLIENT_ID").ok(),
+            identity: Some(identity),
+            token: Arc::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Create a source for the vault at `vault_url` that uses an
+    /// explicit access token, for example one from
+    /// `az account get-access-token --resource https://vault.azure.net`.
+    /// The token is used as it is, even after it expires.
+    pub fn new(
+        vault_url: impl Into<String>,
+        access_token: impl Into<String>,
+    ) -> AzureKeySource {
+        AzureKeySource {
+            vault_url: vault_url.into(),
+            client_id: None,
+            identity: None,
+            token: Arc::new(Mutex::new(Some(AccessToken {
+                token: access_token.into(),
+                expires_at: None,
+            }))),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Get the value of a secret, given as its name for the latest
+    /// version or as `name/version` for a specific one.
+    pub async fn key_vault_secret(
+        &self,
+        secret: &str,
+    ) -> Result<Vec<u8>, AuthError> {
+        let url = format!(
+            "{}/secrets/{}",
+            self.vault_url.trim_end_matches('/'),
+            secret
+        );
+        let request = self
+            .client
+            .get(url)
+            .query(&[("api-version", KEY_VAULT_API_VERSION)])
+            .bearer_auth(self.access_token().await?);
+        let resp = check_status(request.send().await?).await?;
+        let bundle: SecretBundle = resp.json().await?;
+        Ok(bundle.value.into_bytes())
+    }
+
+    /// Get the access token, fetching a new one from the identity
+    /// endpoint first if there is none or it is about to expire.
+    async fn access_token(&self) -> Result<String, AuthError> {
+        let token = self.token.lock().unwrap().clone();
+        let identity = match (token, &self.identity) {
+            (Some(token), Some(_)) if !token.needs_refresh() => {
+                return Ok(token.token)
+            }
+            (Some(token), None) => return Ok(token.token),
+            (_, Some(identity)) => identity,
+            (None, None) => unreachable!("explicit sources have a token"),
+        };
+        debug!("fetching an Azure access token for the managed identity");
+        let mut query = vec![("resource", KEY_VAULT_RESOURCE)];
+        if let Some(client_id) = &self.client_id {
+            query.push(("client_id", client_id));
+        }
+        let request = match identity {
+            IdentityEndpoint::InstanceMetadata(url) => {
+                query.push(("api-version", "2018-02-01"));
+                self.client.get(url).header("Metadata", "true")
+            }
+            IdentityEndpoint::AppService { url, header } => {
+                query.push(("api-version", "2019-08-01"));
+                self.client.get(url).header("X-IDENTITY-HEADER", header)
+            }
+        };
+        let request = request.query(&query).timeout(METADATA_TIMEOUT);
+        let resp = check_status(request.send().await?).await?;
+        let resp: IdentityToken = resp.json().await?;
+        let token = AccessToken {
+            token: resp.access_token,
+            expires_at: resp.expires_on.and_then(|t| t.to_datetime()),
+        };
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_once;
+
+    #[tokio::test]
+    async fn test_key_vault_secret() {
+        let (identity_url, identity_request) = serve_once(
+            r#"{"access_token": "eyJ0.mock", "expires_on": "1893456000",
+                "resource": "https://vault.azure.net",
+                "token_type": "Bearer"}"#,
+        )
+        .await;
+        let (vault_url, secret_request) = serve_once(
+            r#"{"value": "my private key",
+                "id": "https://my-vault.vault.azure.net/secrets/github-app-key/1"}"#,
+        )
+        .await;
+        let source = AzureKeySource {
+            vault_url,
+            client_id: Some("my-client-id".into()),
+            identity: Some(IdentityEndpoint::InstanceMetadata(format!(
+                "{}/metadata/identity/oauth2/token",
+                identity_url
+            ))),
+            token: Arc::default(),
+            client: reqwest::Client::new(),
+        };
+        let key = source.key_vault_secret("github-app-key").await.unwrap();
+        assert_eq!(key, b"my private key");
+        assert!(!source
+            .token
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap()
+            .needs_refresh());
+
+        let request = identity_request.await.unwrap();
+        assert!(request.starts_with(
+            "GET /metadata/identity/oauth2/token?\
+             resource=https%3A%2F%2Fvault.azure.net&client_id=my-client-id&\
+             api-version=2018-02-01 "
+        ));
+        assert!(request.to_lowercase().contains("metadata: true"));
+        let request = secret_request.await.unwrap();
+        assert!(
+            request.starts_with("GET /secrets/github-app-key?api-version=7.4 ")
+        );
+        assert!(request.contains("authorization: Bearer eyJ0.mock"));
+        assert!(!format!("{:?}", source).contains("eyJ0"));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let expected = DateTime::from_timestamp(1893456000, 0);
+        let parse = |json| serde_json::from_str::<Timestamp>(json).unwrap();
+        assert_eq!(parse("1893456000").to_datetime(), expected);
+        assert_eq!(parse(r#""1893456000""#).to_datetime(), expected);
+        assert_eq!(parse(r#""soon""#).to_datetime(), None);
+    }
+}
@@ -0,0 +1,459 @@
+//! Token broker: holds the app's private key and serves installation
+//! access tokens to other processes on the host, so that they never
+//! see the key.
+
+use github_app_auth::{ConfigLoader, InstallationTokenStore};
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const USAGE: &str = "\
+usage: github-app-broker --listen ADDR --clients PATH [--config PATH]
+                         [--socket-mode MODE]
+
+Serve installation access tokens over HTTP. Clients request a token
+with:
+
+  GET /token/INSTALLATION_ID
+  Authorization: Bearer CLIENT_SECRET
+
+and get back {\"token\": ..., \"expires_at\": ...}. Tokens are cached
+and refreshed as needed.
+
+options:
+  --listen ADDR    HOST:PORT to listen on, or unix:PATH for a Unix
+                   socket
+  --clients PATH   JSON file listing the clients and the installations
+                   each one may get tokens for:
+                   {\"clients\": [{\"name\": \"ci\", \"secret\": \"...\",
+                                 \"installations\": [1234]}]}
+  --config PATH    JSON config file with the app parameters
+  --socket-mode MODE
+                   octal permissions of the Unix socket, 600 (only
+                   the broker's user) by default. A socket left over
+                   from an earlier run is replaced.
+
+App parameters that are not in the config file are read from the
+GITHUB_APP_* environment variables.";
+
+const DEFAULT_USER_AGENT: &str = "github-app-broker";
+
+/// How long a client has to send its request headers.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait before accepting connections again after an
+/// error, such as running out of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Default permissions of the Unix socket.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// A client allowed to request tokens.
+#[derive(Deserialize)]
+struct Client {
+    name: String,
+    secret: String,
+    installations: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct ClientsFile {
+    clients: Vec<Client>,
+}
+
+struct Broker {
+    clients: Vec<Client>,
+    tokens: InstallationTokenStore,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut listen = None;
+    let mut clients = None;
+    let mut socket_mode = DEFAULT_SOCKET_MODE;
+    let mut loader = ConfigLoader::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+        match arg.as_str() {
+            "--listen" => listen = Some(value()?),
+            "--clients" => clients = Some(PathBuf::from(value()?)),
+            "--config" => loader = loader.config_file(value()?),
+            "--socket-mode" => {
+                let mode = value()?;
+                socket_mode = u32::from_str_radix(&mode, 8)
+                    .map_err(|_| format!("invalid socket mode: {}", mode))?;
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let (Some(listen), Some(clients)) = (listen, clients) else {
+        return Err(USAGE.to_string());
+    };
+
+    let clients = std::fs::read(&clients)
+        .map_err(|err| format!("failed to read {}: {}", clients.display(), err))
+        .and_then(|json| {
+            serde_json::from_slice::<ClientsFile>(&json)
+                .map_err(|err| format!("invalid clients file: {}", err))
+        })?
+        .clients;
+    let (mut params, _) = loader.load().map_err(|err| err.to_string())?;
+    if params.user_agent.is_empty() {
+        params.user_agent = DEFAULT_USER_AGENT.into();
+    }
+    let broker = Arc::new(Broker {
+        clients,
+        tokens: InstallationTokenStore::new(params)
+            .map_err(|err| err.to_string())?,
+    });
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| format!("failed to start runtime: {}", err))?;
+    runtime.block_on(serve(broker, &listen, socket_mode))
+}
+
+async fn serve(
+    broker: Arc<Broker>,
+    listen: &str,
+    socket_mode: u32,
+) -> Result<(), String> {
+    let bind_err = |err| format!("failed to listen on {}: {}", listen, err);
+    if let Some(path) = listen.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let listener = bind_unix(path, socket_mode).map_err(bind_err)?;
+            eprintln!("listening on {}", listen);
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle_connection(stream, broker.clone()));
+                    }
+                    Err(err) => accept_failed(err).await,
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = socket_mode;
+            return Err(format!("Unix sockets are not supported: {}", path));
+        }
+    }
+    let listener = TcpListener::bind(listen).await.map_err(bind_err)?;
+    eprintln!("listening on {}", listen);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, broker.clone()));
+            }
+            Err(err) => accept_failed(err).await,
+        }
+    }
+}
+
+/// Log an error from accepting a connection, and wait a little before
+/// trying again, since errors such as running out of file descriptors
+/// usually clear up once other connections close.
+async fn accept_failed(err: std::io::Error) {
+    eprintln!("failed to accept connection: {}", err);
+    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+}
+
+/// Listen on a Unix socket at `path` with permissions `mode`. A socket
+/// left at `path` by an earlier run is removed first, but not one that
+/// another process is still listening on.
+#[cfg(unix)]
+fn bind_unix(
+    path: &str,
+    mode: u32,
+) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let stale = std::fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_type().is_socket());
+    if stale {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(std::io::ErrorKind::AddrInUse.into());
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+}
+
+/// Read the request line and headers. Request bodies are not used.
+async fn read_request<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> std::io::Result<Request> {
+    let mut buf = Vec::new();
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > 16 * 1024 {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let authorization = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("authorization")
+            .then(|| value.trim().to_string())
+    });
+    Ok(Request {
+        method,
+        path,
+        authorization,
+    })
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    broker: Arc<Broker>,
+) {
+    let read = tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream));
+    let Ok(Ok(request)) = read.await else {
+        return;
+    };
+    let (status, body) = route(&broker, &request).await;
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Error",
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn error(status: u16, message: &str) -> (u16, serde_json::Value) {
+    (status, serde_json::json!({ "message": message }))
+}
+
+async fn route(broker: &Broker, request: &Request) -> (u16, serde_json::Value) {
+    let installation_id = match (
+        request.method.as_str(),
+        request.path.strip_prefix("/token/"),
+    ) {
+        ("GET", Some(id)) => match id.parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => return error(404, "Not Found"),
+        },
+        _ => return error(404, "Not Found"),
+    };
+
+    let secret = request
+        .authorization
+        .as_deref()
+        .and_then(|auth| auth.strip_prefix("Bearer "));
+    let Some(client) = secret.and_then(|secret| {
+        broker.clients.iter().find(|client| {
+            ring::constant_time::verify_slices_are_equal(
+                client.secret.as_bytes(),
+                secret.as_bytes(),
+            )
+            .is_ok()
+        })
+    }) else {
+        return error(401, "unknown client");
+    };
+    if !client.installations.contains(&installation_id) {
+        eprintln!(
+            "{}: denied token for installation {}",
+            client.name, installation_id
+        );
+        return error(403, "installation not allowed for this client");
+    }
+
+    match broker.tokens.state(installation_id).await {
+        Ok(state) => {
+            eprintln!(
+                "{}: token for installation {}",
+                client.name, installation_id
+            );
+            (
+                200,
+                serde_json::json!({
+                    "token": state.token,
+                    "expires_at": state.expires_at,
+                }),
+            )
+        }
+        Err(err) => {
+            eprintln!(
+                "{}: failed to get token for installation {}: {}",
+                client.name, installation_id, err
+            );
+            error(502, &err.to_string())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use github_app_auth::test_util::MockGithub;
+
+    async fn broker(github: &MockGithub) -> Broker {
+        Broker {
+            clients: vec![Client {
+                name: "ci".into(),
+                secret: "ci-secret".into(),
+                installations: vec![1234],
+            }],
+            tokens: InstallationTokenStore::new(github.params()).unwrap(),
+        }
+    }
+
+    fn request(method: &str, path: &str, secret: Option<&str>) -> Request {
+        Request {
+            method: method.into(),
+            path: path.into(),
+            authorization: secret.map(|secret| format!("Bearer {}", secret)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let github = MockGithub::start().await.unwrap();
+        let broker = broker(&github).await;
+
+        let (status, body) =
+            route(&broker, &request("GET", "/token/1234", Some("ci-secret")))
+                .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["token"], "mock-token-1");
+        assert!(body["expires_at"].is_string());
+
+        // Unknown or missing secrets.
+        let (status, _) =
+            route(&broker, &request("GET", "/token/1234", Some("wrong"))).await;
+        assert_eq!(status, 401);
+        let (status, _) =
+            route(&broker, &request("GET", "/token/1234", None)).await;
+        assert_eq!(status, 401);
+
+        // An installation the client isn't allowed.
+        let (status, _) =
+            route(&broker, &request("GET", "/token/5678", Some("ci-secret")))
+                .await;
+        assert_eq!(status, 403);
+
+        for (method, path) in [
+            ("GET", "/token/abc"),
+            ("GET", "/tokens"),
+            ("POST", "/token/1234"),
+        ] {
+            let (status, _) =
+                route(&broker, &request(method, path, Some("ci-secret"))).await;
+            assert_eq!(status, 404, "{} {}", method, path);
+        }
+        assert_eq!(github.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection() {
+        let github = MockGithub::start().await.unwrap();
+        let broker = Arc::new(broker(&github).await);
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        let handler = tokio::spawn(handle_connection(server, broker));
+        client
+            .write_all(
+                b"GET /token/1234 HTTP/1.1\r\n\
+                  Authorization: Bearer ci-secret\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        handler.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("mock-token-1"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_unix() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir()
+            .join(format!("github-app-broker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("broker.sock");
+        let path = path.to_str().unwrap();
+
+        let listener = bind_unix(path, 0o600).unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // The socket is in use while the listener is open.
+        assert_eq!(
+            bind_unix(path, 0o600).unwrap_err().kind(),
+            std::io::ErrorKind::AddrInUse
+        );
+
+        // A socket left over from an earlier run is replaced.
+        drop(listener);
+        assert!(std::path::Path::new(path).exists());
+        let _listener = bind_unix(path, 0o660).unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+//! Command-line tool for working with GitHub app credentials.
+
+use github_app_auth::{
+    decode_jwt, diagnose_jwt, get_app, list_installations, sign_app_jwt,
+    AuthError, ConfigLoader, GithubAuthParams, InstallationAccessToken,
+    ListOptions, ParamsError, PartialParams, PermissionLevel, Permissions,
+    TokenScope, TokenState,
+};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+usage: github-app-token <command> [options]
+
+commands:
+  decode [APP OPTIONS] [JWT]
+      Print the header and claims of an app JWT and flag common
+      problems. The JWT is read from stdin if not given. If a private
+      key is configured, the signature is checked against it.
+
+  doctor [APP OPTIONS]
+      Check the app configuration step by step: validate the
+      parameters, sign a JWT, call GET /app, list installations, and
+      mint an installation token.
+
+  token [APP OPTIONS] [SCOPE OPTIONS] [--installation-repo OWNER/NAME]
+        [--format FORMAT] [--daemon PATH [--mode MODE]]
+      Mint an installation access token and print it. With
+      --installation-repo, the token is for the app's installation on
+      that repository, which is looked up instead of using the
+      installation ID. FORMAT is one of:
+        raw   the token alone (default)
+        env   an `export GITHUB_TOKEN=...` line for the shell
+        json  an object with the token, expiry, installation ID, and
+              permissions
+      With --daemon, keep running and write the token to PATH in the
+      chosen format, replacing the file atomically each time the
+      token is refreshed. The file is created with permissions MODE
+      (octal, default 600).
+
+      scope options, to mint a token with less access than the
+      installation has:
+        --repo OWNER/NAME        limit the token to this repository;
+                                 may be repeated, but all repositories
+                                 must have the same owner
+        --permission NAME=LEVEL  request this permission, for example
+                                 contents=read; may be repeated, and
+                                 permissions not listed are dropped
+
+app options:
+  --app-id ID            GitHub app ID
+  --client-id ID         GitHub app client ID
+  --installation-id ID   installation ID
+  --key PATH             path of the private key in PEM format
+  --config PATH          JSON config file
+
+Options that are not given are read from the GITHUB_APP_* environment
+variables, then from the config file.";
+
+const DEFAULT_USER_AGENT: &str = "github-app-token";
+
+/// How often the daemon checks whether the token needs refreshing.
+const DAEMON_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("decode") => decode(&args[1..]),
+        Some("doctor") => doctor(&args[1..]),
+        Some("token") => token(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Get the value following a flag.
+fn flag_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} requires a value", flag))
+}
+
+fn parse_flag<T: std::str::FromStr>(
+    args: &mut std::slice::Iter<'_, String>,
+    flag: &str,
+) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    flag_value(args, flag)?
+        .parse()
+        .map_err(|err| format!("invalid {}: {}", flag, err))
+}
+
+/// Parsed command-line arguments: app parameters plus any positional
+/// arguments.
+struct Args {
+    params: GithubAuthParams,
+    positional: Vec<String>,
+}
+
+/// Parse the app options and positional arguments. Any other flag is
+/// passed to `command_flag` along with the remaining arguments; it
+/// returns false if the flag is not one the command accepts.
+fn parse_args(
+    args: &[String],
+    mut command_flag: impl FnMut(
+        &str,
+        &mut std::slice::Iter<'_, String>,
+    ) -> Result<bool, String>,
+) -> Result<Args, String> {
+    let mut explicit = PartialParams::default();
+    let mut loader = ConfigLoader::new();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--app-id" => explicit.app_id = Some(parse_flag(&mut args, arg)?),
+            "--client-id" => {
+                explicit.client_id = Some(flag_value(&mut args, arg)?.clone());
+            }
+            "--installation-id" => {
+                explicit.installation_id = Some(parse_flag(&mut args, arg)?);
+            }
+            "--key" => {
+                explicit.private_key_path =
+                    Some(PathBuf::from(flag_value(&mut args, arg)?));
+            }
+            "--config" => {
+                loader = loader.config_file(flag_value(&mut args, arg)?);
+            }
+            _ if !arg.starts_with("--") => positional.push(arg.clone()),
+            _ if command_flag(arg, &mut args)? => {}
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    let (mut params, _) = loader
+        .explicit(explicit)
+        .load()
+        .map_err(|err| err.to_string())?;
+    if params.user_agent.is_empty() {
+        params.user_agent = DEFAULT_USER_AGENT.into();
+    }
+    Ok(Args { params, positional })
+}
+
+fn decode(args: &[String]) -> Result<ExitCode, String> {
+    let Args {
+        params,
+        mut positional,
+    } = parse_args(args, |_, _| Ok(false))?;
+    if positional.len() > 1 {
+        return Err(format!("unexpected argument: {}", positional[1]));
+    }
+
+    let jwt = match positional.pop() {
+        Some(jwt) => jwt,
+        None => {
+            let mut jwt = String::new();
+            io::stdin()
+                .read_to_string(&mut jwt)
+                .map_err(|err| format!("failed to read stdin: {}", err))?;
+            jwt
+        }
+    };
+
+    let decoded = decode_jwt(&jwt).map_err(|err| err.to_string())?;
+    let pretty = |value| serde_json::to_string_pretty(value).unwrap();
+    println!("header: {}", pretty(&decoded.header));
+    println!("claims: {}", pretty(&decoded.claims));
+
+    let problems = diagnose_jwt(&params, &jwt);
+    if problems.is_empty() {
+        println!("no problems found");
+        return Ok(ExitCode::SUCCESS);
+    }
+    for problem in &problems {
+        println!("problem: {}", problem);
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+/// Print the outcome of one doctor step, returning whether it passed.
+fn report<T>(step: &str, result: &Result<T, AuthError>) -> bool {
+    match result {
+        Ok(_) => {
+            println!("ok    {}", step);
+            true
+        }
+        Err(err) => {
+            println!("FAIL  {}: {}", step, err);
+            if let Some(hint) = err.hint() {
+                println!("      hint: {}", hint);
+            }
+            false
+        }
+    }
+}
+
+fn doctor(args: &[String]) -> Result<ExitCode, String> {
+    let Args {
+        mut params,
+        positional,
+    } = parse_args(args, |_, _| Ok(false))?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+
+    let ok = runtime()?.block_on(async {
+        // The installation ID is optional here; it is filled in below
+        // if the app has exactly one installation.
+        match params.validate() {
+            Ok(()) => println!("ok    validate parameters"),
+            Err(problems) => {
+                let problems: Vec<_> = problems
+                    .iter()
+                    .filter(|p| !matches!(p, ParamsError::ZeroInstallationId))
+                    .map(|p| p.to_string())
+                    .collect();
+                if !problems.is_empty() {
+                    println!("FAIL  validate parameters");
+                    for problem in problems {
+                        println!("      {}", problem);
+                    }
+                    return false;
+                }
+                println!("ok    validate parameters");
+            }
+        }
+
+        let jwt = sign_app_jwt(&params);
+        if !report("sign JWT", &jwt) {
+            return false;
+        }
+        let problems = diagnose_jwt(&params, &jwt.unwrap());
+        for problem in &problems {
+            println!("      warning: {}", problem);
+        }
+
+        let app = get_app(&params).await;
+        if !report("GET /app", &app) {
+            return false;
+        }
+        if let Ok(app) = app {
+            println!("      app: {}", app.slug);
+        }
+
+        let installations =
+            list_installations(&params, &ListOptions::default()).await;
+        if !report("list installations", &installations) {
+            return false;
+        }
+        let installations = installations.unwrap();
+        for installation in &installations {
+            let account = installation
+                .account
+                .as_ref()
+                .map(|a| a.login.as_str())
+                .unwrap_or("unknown account");
+            println!("      {} ({})", installation.id, account);
+        }
+
+        if params.installation_id == 0 {
+            match installations.as_slice() {
+                [installation] => params.installation_id = installation.id,
+                _ => {
+                    println!(
+                        "skip  mint token: set --installation-id to pick \
+                         one of the {} installations",
+                        installations.len()
+                    );
+                    return true;
+                }
+            }
+        }
+        let token = InstallationAccessToken::new(params).await;
+        if !report("mint token", &token) {
+            return false;
+        }
+        let token = token.unwrap();
+        println!("      expires at {}", token.expires_at());
+        true
+    });
+
+    Ok(if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| format!("failed to start runtime: {}", err))
+}
+
+/// How the `token` command prints a token.
+#[derive(Clone, Copy)]
+enum Format {
+    Raw,
+    Env,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "raw" => Ok(Format::Raw),
+            "env" => Ok(Format::Env),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format {:?}", s)),
+        }
+    }
+}
+
+impl Format {
+    fn render(self, state: &TokenState) -> String {
+        match self {
+            Format::Raw => state.token.clone(),
+            // Tokens are alphanumeric, but quote anyway so the line is
+            // safe to eval whatever GitHub sends.
+            Format::Env => format!(
+                "export GITHUB_TOKEN='{}'",
+                state.token.replace('\'', "'\\''")
+            ),
+            Format::Json => serde_json::to_string(state).unwrap(),
+        }
+    }
+}
+
+fn token(args: &[String]) -> Result<ExitCode, String> {
+    let mut daemon = None;
+    let mut mode = 0o600;
+    let mut format = Format::Raw;
+    let mut repos = Vec::new();
+    let mut installation_repo = None;
+    let mut permissions: Option<Permissions> = None;
+    let Args {
+        mut params,
+        positional,
+    } = parse_args(args, |flag, args| {
+        match flag {
+            "--format" => format = parse_flag(args, flag)?,
+            "--repo" => repos.push(flag_value(args, flag)?.clone()),
+            "--installation-repo" => {
+                installation_repo = Some(flag_value(args, flag)?.clone());
+            }
+            "--permission" => {
+                let (name, level) = parse_permission(flag_value(args, flag)?)?;
+                permissions
+                    .get_or_insert_with(Permissions::default)
+                    .set(name, Some(level));
+            }
+            "--daemon" => {
+                daemon = Some(PathBuf::from(flag_value(args, flag)?));
+            }
+            "--mode" => {
+                mode = u32::from_str_radix(flag_value(args, flag)?, 8)
+                    .map_err(|err| format!("invalid {}: {}", flag, err))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    if let Some(arg) = positional.first() {
+        return Err(format!("unexpected argument: {}", arg));
+    }
+    params.scope = TokenScope {
+        repositories: repo_names(&repos)?,
+        permissions,
+        ..Default::default()
+    };
+
+    runtime()?.block_on(async {
+        let token = match &installation_repo {
+            Some(repo) => InstallationAccessToken::for_repo(params, repo).await,
+            None => InstallationAccessToken::new(params).await,
+        };
+        let mut token =
+            token.map_err(|err| format!("failed to mint token: {}", err))?;
+        let Some(path) = daemon else {
+            println!("{}", format.render(&token.to_state()));
+            return Ok(ExitCode::SUCCESS);
+        };
+
+        write_atomic(&path, &format.render(&token.to_state()), mode).map_err(
+            |err| format!("failed to write {}: {}", path.display(), err),
+        )?;
+        eprintln!(
+            "wrote token to {}, expires at {}",
+            path.display(),
+            token.expires_at()
+        );
+        loop {
+            tokio::time::sleep(DAEMON_CHECK_INTERVAL).await;
+            match token.refresh_if_needed().await {
+                Ok(false) => {}
+                Ok(true) => {
+                    match write_atomic(
+                        &path,
+                        &format.render(&token.to_state()),
+                        mode,
+                    ) {
+                        Ok(()) => eprintln!(
+                            "refreshed token, expires at {}",
+                            token.expires_at()
+                        ),
+                        Err(err) => eprintln!(
+                            "failed to write {}: {}",
+                            path.display(),
+                            err
+                        ),
+                    }
+                }
+                // The file still holds the previous token, which may
+                // be valid for a while yet; try again on the next
+                // check.
+                Err(err) => eprintln!("failed to refresh token: {}", err),
+            }
+        }
+    })
+}
+
+/// Parse a `--permission NAME=LEVEL` value. Unknown names are
+/// rejected rather than sent to GitHub, so that a misspelled
+/// permission doesn't silently give a token with less access than
+/// intended.
+fn parse_permission(value: &str) -> Result<(&str, PermissionLevel), String> {
+    let (name, level) = value.split_once('=').ok_or_else(|| {
+        format!("invalid --permission {}: expected NAME=LEVEL", value)
+    })?;
+    if !Permissions::is_known(name) {
+        return Err(format!(
+            "invalid --permission {}: unknown permission {:?}",
+            value, name
+        ));
+    }
+    let level = level
+        .parse()
+        .map_err(|err| format!("invalid --permission {}: {}", value, err))?;
+    Ok((name, level))
+}
+
+/// Get the repository names for a token scope from `OWNER/NAME`
+/// arguments. GitHub only takes the names, since a token is always
+/// for the repositories of a single account.
+fn repo_names(repos: &[String]) -> Result<Vec<String>, String> {
+    let mut owner = None;
+    repos
+        .iter()
+        .map(|repo| {
+            let (repo_owner, name) = repo
+                .split_once('/')
+                .filter(|(o, n)| !o.is_empty() && !n.is_empty())
+                .ok_or_else(|| {
+                    format!("invalid --repo {}: expected OWNER/NAME", repo)
+                })?;
+            if *owner.get_or_insert(repo_owner) != repo_owner {
+                return Err(format!(
+                    "invalid --repo {}: all repositories must belong to {}",
+                    repo,
+                    owner.unwrap()
+                ));
+            }
+            Ok(name.to_string())
+        })
+        .collect()
+}
+
+/// Replace the contents of `path` so that readers see either the old
+/// contents or the new ones, never a partial write. The new contents are
+/// written to a temporary file in the same directory, which is then
+/// renamed over `path`.
+fn write_atomic(path: &Path, contents: &str, mode: u32) -> io::Result<()> {
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    let mut file = options.open(&tmp)?;
+    // The mode passed to open only applies to new files and is
+    // subject to the umask, so set it again explicitly.
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(mode))?;
+    #[cfg(not(unix))]
+    let _ = mode;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_permission() {
+        assert_eq!(
+            parse_permission("contents=read"),
+            Ok(("contents", PermissionLevel::Read))
+        );
+        assert_eq!(
+            parse_permission("pull_requests=write"),
+            Ok(("pull_requests", PermissionLevel::Write))
+        );
+        let err = parse_permission("contnets=read").unwrap_err();
+        assert!(err.contains("unknown permission \"contnets\""), "{}", err);
+        assert!(parse_permission("contents").is_err());
+        assert!(parse_permission("contents=owner").is_err());
+    }
+
+    #[test]
+    fn test_format_render() {
+        let state = TokenState {
+            token: "ghs_abc".into(),
+            expires_at: "2030-01-01T00:00:00Z".parse().unwrap(),
+            installation_id: 5678,
+            permissions: Permissions {
+                contents: Some(PermissionLevel::Read),
+                ..Default::default()
+            },
+            repositories: Vec::new(),
+            repository_selection: None,
+        };
+        assert_eq!(Format::Raw.render(&state), "ghs_abc");
+        assert_eq!(Format::Env.render(&state), "export GITHUB_TOKEN='ghs_abc'");
+        let json: serde_json::Value =
+            serde_json::from_str(&Format::Json.render(&state)).unwrap();
+        assert_eq!(json["token"], "ghs_abc");
+        assert_eq!(json["expires_at"], "2030-01-01T00:00:00Z");
+        assert_eq!(json["installation_id"], 5678);
+        assert_eq!(json["permissions"]["contents"], "read");
+
+        // A quote in the token can't break out of the shell string.
+        let state = TokenState {
+            token: "a'b".into(),
+            ..state
+        };
+        assert_eq!(Format::Env.render(&state), "export GITHUB_TOKEN='a'\\''b'");
+
+        assert!(matches!("env".parse(), Ok(Format::Env)));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_repo_names() {
+        let repos = ["octo/hello-world".to_string(), "octo/spoon".into()];
+        assert_eq!(repo_names(&repos).unwrap(), ["hello-world", "spoon"]);
+        assert_eq!(repo_names(&[]).unwrap(), Vec::<String>::new());
+
+        // A bare name, or one with an empty owner or name, is rejected.
+        for repo in ["hello-world", "/hello-world", "octo/"] {
+            let err = repo_names(&[repo.to_string()]).unwrap_err();
+            assert!(err.contains("expected OWNER/NAME"), "{}", err);
+        }
+
+        let repos = ["octo/hello-world".to_string(), "other/spoon".into()];
+        let err = repo_names(&repos).unwrap_err();
+        assert!(err.contains("must belong to octo"), "{}", err);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = env::temp_dir()
+            .join(format!("github-app-token-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("token");
+
+        write_atomic(&path, "first", 0o600).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+        write_atomic(&path, "second", 0o640).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        // The temporary file is renamed over the target.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
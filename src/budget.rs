@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A limit on how often failed token requests may be retried, shared
+/// by every token that uses it.
+///
+/// When GitHub is degraded, each token whose refresh fails will try
+/// again on the next call to
+/// [`header`](crate::InstallationAccessToken::header). With many
+/// installations this multiplies into a flood of requests against an
+/// API that is already struggling. Setting the same budget on all of
+/// their [`GithubAuthParams`](crate::GithubAuthParams::retry_budget)
+/// caps the combined retry rate; once it is used up, retries fail
+/// immediately with [`AuthError::RetryBudgetExhausted`] without
+/// making a request.
+///
+/// Only retries are counted: the first refresh attempt after a
+/// success is always allowed. The budget refills continuously, at
+/// `max_retries` per `period`.
+///
+/// [`AuthError::RetryBudgetExhausted`]: crate::AuthError::RetryBudgetExhausted
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl RetryBudget {
+    /// Allow up to `max_retries` retries per `period`, across all
+    /// tokens sharing this budget.
+    pub fn new(max_retries: u32, period: Duration) -> Self {
+        let capacity = f64::from(max_retries);
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                capacity,
+                available: capacity,
+                refill_per_sec: capacity / period.as_secs_f64().max(1e-3),
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Take one retry from the budget, returning false if none are
+    /// left.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.available = (bucket.available + elapsed * bucket.refill_per_sec)
+            .min(bucket.capacity);
+        bucket.last_refill = now;
+        if bucket.available >= 1.0 {
+            bucket.available -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(2, Duration::from_secs(3600));
+        let shared = budget.clone();
+        assert!(budget.try_acquire());
+        assert!(shared.try_acquire());
+        assert!(!budget.try_acquire());
+        assert!(!shared.try_acquire());
+
+        let budget = RetryBudget::new(1, Duration::from_millis(10));
+        assert!(budget.try_acquire());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(budget.try_acquire());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    AuthError, ClientOptions, Clock, GithubAuthParams, InstallationAccessToken,
+    Permissions, PrivateKeyProvider, ProxyOptions, RetryPolicy, TokenScope,
+};
+use chrono::Duration;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Builder for an [`InstallationAccessToken`], created with
+/// [`InstallationAccessToken::builder`].
+///
+/// This is an alternative to filling in [`GithubAuthParams`] directly.
+/// Only the settings that differ from the defaults need to be given,
+/// and new settings can be added without breaking existing code.
+///
+/// ```no_run
+/// # use github_app_auth::InstallationAccessToken;
+/// # async fn wrapper(pem: Vec<u8>) -> Result<(), github_app_auth::AuthError> {
+/// let token = InstallationAccessToken::builder()
+///     .user_agent("my-app")
+///     .app_id(1234)
+///     .installation_id(5678)
+///     .private_key_pem(pem)
+///     .fetch()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// To start from existing parameters, convert them with
+/// `InstallationAccessTokenBuilder::from(params)`.
+#[derive(Debug, Default)]
+#[must_use]
+pub struct InstallationAccessTokenBuilder {
+    params: GithubAuthParams,
+    refresh_margin: Option<Duration>,
+    refresh_jitter: Option<Duration>,
+}
+
+impl From<GithubAuthParams> for InstallationAccessTokenBuilder {
+    fn from(params: GithubAuthParams) -> Self {
+        Self {
+            params,
+            refresh_margin: None,
+            refresh_jitter: None,
+        }
+    }
+}
+
+impl InstallationAccessTokenBuilder {
+    /// Set [`GithubAuthParams::user_agent`].
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.params.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set [`GithubAuthParams::app_id`].
+    pub fn app_id(mut self, app_id: u64) -> Self {
+        self.params.app_id = app_id;
+        self
+    }
+
+    /// Set [`GithubAuthParams::client_id`].
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.params.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Set [`GithubAuthParams::installation_id`].
+    pub fn installation_id(mut self, installation_id: u64) -> Self {
+        self.params.installation_id = installation_id;
+        self
+    }
+
+    /// Set [`GithubAuthParams::private_key`].
+    pub fn private_key_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.params.private_key = pem.into();
+        self
+    }
+
+    /// Set [`GithubAuthParams::private_key_path`].
+    pub fn private_key_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.params.private_key_path = Some(path.into());
+        self
+    }
+
+    /// Set [`GithubAuthParams::private_key_provider`].
+    pub fn private_key_provider(
+        mut self,
+        provider: impl PrivateKeyProvider + 'static,
+    ) -> Self {
+        self.params.private_key_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set [`GithubAuthParams::base_url`].
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.params.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Set [`GithubAuthParams::api_version`].
+    pub fn api_version(mut self, api_version: impl Into<String>) -> Self {
+        self.params.api_version = Some(api_version.into());
+        self
+    }
+
+    /// Set [`GithubAuthParams::scope`].
+    pub fn scope(mut self, scope: TokenScope) -> Self {
+        self.params.scope = scope;
+        self
+    }
+
+    /// Set [`GithubAuthParams::required_permissions`].
+    pub fn required_permissions(mut self, permissions: Permissions) -> Self {
+        self.params.required_permissions = permissions;
+        self
+    }
+
+    /// Set [`GithubAuthParams::retry_policy`].
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.params.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set [`GithubAuthParams::clock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.params.clock = Some(clock);
+        self
+    }
+
+    /// Set [`GithubAuthParams::client_options`], replacing any client
+    /// settings made so far.
+    pub fn client_options(mut self, options: ClientOptions) -> Self {
+        self.params.client_options = options;
+        self
+    }
+
+    /// Set
+    /// [`ClientOptions::pool_max_idle_per_host`](crate::ClientOptions::pool_max_idle_per_host),
+    /// the number of idle connections kept per host.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.params.client_options.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set
+    /// [`ClientOptions::pool_idle_timeout`](crate::ClientOptions::pool_idle_timeout),
+    /// how long an idle connection is kept open.
+    pub fn pool_idle_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.params.client_options.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set
+    /// [`ClientOptions::tcp_keepalive`](crate::ClientOptions::tcp_keepalive),
+    /// the interval of TCP keep-alive probes.
+    pub fn tcp_keepalive(mut self, interval: std::time::Duration) -> Self {
+        self.params.client_options.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Set
+    /// [`ClientOptions::http2_prior_knowledge`](crate::ClientOptions::http2_prior_knowledge),
+    /// to use HTTP/2 without negotiating it.
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.params.client_options.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Set [`ClientOptions::timeout`](crate::ClientOptions::timeout),
+    /// the time limit for each request to GitHub.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.params.client_options.timeout = Some(timeout);
+        self
+    }
+
+    /// Set
+    /// [`ClientOptions::connect_timeout`](crate::ClientOptions::connect_timeout),
+    /// the time limit for connecting to GitHub.
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.params.client_options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set [`ClientOptions::proxy`](crate::ClientOptions::proxy), a
+    /// proxy to send requests to GitHub through.
+    pub fn proxy(mut self, proxy: ProxyOptions) -> Self {
+        self.params.client_options.proxy = Some(proxy);
+        self
+    }
+
+    /// Set [`GithubAuthParams::http_client`].
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.params.http_client = Some(client);
+        self
+    }
+
+    /// Set [`InstallationAccessToken::refresh_safety_margin`] on the
+    /// fetched token.
+    pub fn refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = Some(margin);
+        self
+    }
+
+    /// Set [`InstallationAccessToken::refresh_jitter`] on the fetched
+    /// token.
+    pub fn refresh_jitter(mut self, jitter: Duration) -> Self {
+        self.refresh_jitter = Some(jitter);
+        self
+    }
+
+    /// Get the parameters, for example to pass to
+    /// [`InstallationAccessToken::for_repo`]. The refresh margin and
+    /// jitter are not part of the parameters and are dropped.
+    pub fn into_params(self) -> GithubAuthParams {
+        self.params
+    }
+
+    /// Fetch the token, see [`InstallationAccessToken::new`].
+    pub async fn fetch(self) -> Result<InstallationAccessToken, AuthError> {
+        let mut token = InstallationAccessToken::new(self.params).await?;
+        if let Some(margin) = self.refresh_margin {
+            token.refresh_safety_margin = margin;
+        }
+        if let Some(jitter) = self.refresh_jitter {
+            token.refresh_jitter = jitter;
+        }
+        Ok(token)
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+
+    #[tokio::test]
+    async fn test_builder() {
+        let github = MockGithub::start().await.unwrap();
+        let params = github.params();
+        let token = InstallationAccessToken::builder()
+            .user_agent(params.user_agent.clone())
+            .app_id(params.app_id)
+            .installation_id(params.installation_id)
+            .private_key_pem(params.private_key.clone())
+            .base_url(github.base_url())
+            .refresh_margin(Duration::minutes(5))
+            .refresh_jitter(Duration::minutes(2))
+            .fetch()
+            .await
+            .unwrap();
+        assert_eq!(token.refresh_safety_margin, Duration::minutes(5));
+        assert_eq!(token.refresh_jitter, Duration::minutes(2));
+        assert_eq!(github.token_requests(), 1);
+
+        let builder = InstallationAccessTokenBuilder::from(params);
+        let params = builder.client_id("Iv1.abc").into_params();
+        assert_eq!(params.client_id.as_deref(), Some("Iv1.abc"));
+
+        let params = InstallationAccessToken::builder()
+            .client_options(ClientOptions::keep_alive())
+            .pool_max_idle_per_host(4)
+            .into_params();
+        assert_eq!(params.client_options.pool_max_idle_per_host, Some(4));
+        assert!(params.client_options.tcp_keepalive.is_some());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::AuthError;
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Settings for the HTTP client that the crate builds internally, see
+/// [`GithubAuthParams::client_options`](crate::GithubAuthParams::client_options).
+///
+/// Fields that are not set keep `reqwest`'s defaults.
+#[derive(Clone, Debug, Default)]
+pub struct ClientOptions {
+    /// Maximum number of idle connections kept per host. Bots that
+    /// send many requests in parallel with the token's client may want
+    /// to raise this.
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// How long an idle connection is kept open. `reqwest` closes idle
+    /// connections after 90 seconds by default, so the hourly token
+    /// refresh always has to reconnect; a longer timeout avoids that.
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// Interval of TCP keep-alive probes on open connections. This
+    /// stops NAT gateways and load balancers from silently dropping
+    /// connections that are idle between refreshes.
+    pub tcp_keepalive: Option<Duration>,
+
+    /// Interval of HTTP/2 PING frames. If set, pings are also sent on
+    /// idle connections, which keeps them warm for the next refresh.
+    /// GitHub negotiates HTTP/2 over TLS automatically.
+    pub http2_keep_alive_interval: Option<Duration>,
+
+    /// How long to wait for an HTTP/2 PING to be acknowledged before
+    /// closing the connection. Only used if
+    /// [`http2_keep_alive_interval`](Self::http2_keep_alive_interval)
+    /// is set.
+    pub http2_keep_alive_timeout: Option<Duration>,
+
+    /// Only use HTTP/1.1, for example behind a proxy that mishandles
+    /// HTTP/2.
+    pub http1_only: bool,
+
+    /// Use HTTP/2 without negotiating it first. Over TLS, HTTP/2 is
+    /// already used whenever the server supports it, so this is only
+    /// needed for a plain `http://` base URL, such as a GitHub
+    /// Enterprise Server behind an HTTP/2 load balancer inside a
+    /// private network. Requests fail if the server does not speak
+    /// HTTP/2.
+    pub http2_prior_knowledge: bool,
+
+    /// Fixed addresses for host names, bypassing DNS. The URL, `Host`
+    /// header, and TLS server name are unchanged, so this can be used
+    /// to reach `api.github.com` through a transparent forwarder in an
+    /// air-gapped network:
+    ///
+    /// ```
+    /// # use github_app_auth::ClientOptions;
+    /// let options = ClientOptions {
+    ///     resolve: vec![("api.github.com".into(), "10.0.0.5:443".parse().unwrap())],
+    ///     ..Default::default()
+    /// };
+    /// ```
+    ///
+    /// The port of each address is ignored; the port from the URL is
+    /// used.
+    pub resolve: Vec<(String, SocketAddr)>,
+
+    /// Time limit for establishing a connection, including the TLS
+    /// handshake.
+    pub connect_timeout: Option<Duration>,
+
+    /// Time limit for each request, from connecting until the
+    /// response body has been read. Without it, a connection that
+    /// stops responding can hold up
+    /// [`header`](crate::InstallationAccessToken::header) indefinitely.
+    /// A request that runs out of time fails with
+    /// [`AuthError::Timeout`](crate::AuthError::Timeout).
+    pub timeout: Option<Duration>,
+
+    /// Proxy to send all requests through. If not set, the
+    /// `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY`, and `NO_PROXY`
+    /// environment variables (or their lowercase forms) are honored,
+    /// unless [`no_system_proxy`](Self::no_system_proxy) is set.
+    pub proxy: Option<ProxyOptions>,
+
+    /// Ignore the proxy environment variables and connect to GitHub
+    /// directly. Has no effect if [`proxy`](Self::proxy) is set.
+    pub no_system_proxy: bool,
+}
+
+/// An HTTP proxy for requests to GitHub, see [`ClientOptions::proxy`].
+///
+/// ```
+/// # use github_app_auth::{ClientOptions, ProxyOptions};
+/// let options = ClientOptions {
+///     proxy: Some(ProxyOptions {
+///         basic_auth: Some(("ci".into(), "hunter2".into())),
+///         no_proxy: Some("localhost,.corp.example.com".into()),
+///         ..ProxyOptions::new("http://proxy.corp.example.com:3128")
+///     }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Default)]
+pub struct ProxyOptions {
+    /// URL of the proxy, for example `http://proxy.example.com:3128`.
+    /// It is used for both HTTP and HTTPS requests; HTTPS requests are
+    /// tunneled with `CONNECT`.
+    pub url: String,
+
+    /// Username and password for basic authentication with the proxy.
+    pub basic_auth: Option<(String, String)>,
+
+    /// Hosts to connect to directly, in the format of the `NO_PROXY`
+    /// environment variable: a comma-separated list of host names,
+    /// domains with a leading `.`, IP addresses, and CIDR blocks.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyOptions {
+    /// Send requests through the proxy at `url`, without
+    /// authentication or exceptions.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    fn to_proxy(&self) -> Result<Proxy, AuthError> {
+        let mut proxy = Proxy::all(&self.url)?;
+        if let Some((username, password)) = &self.basic_auth {
+            proxy = proxy.basic_auth(username, password);
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            proxy = proxy.no_proxy(NoProxy::from_string(no_proxy));
+        }
+        Ok(proxy)
+    }
+}
+
+/// The password is redacted.
+impl fmt::Debug for ProxyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyOptions")
+            .field("url", &self.url)
+            .field(
+                "basic_auth",
+                &self
+                    .basic_auth
+                    .as_ref()
+                    .map(|(username, _)| (username, "[redacted]")),
+            )
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+impl ClientOptions {
+    /// Options that keep a connection to GitHub open between hourly
+    /// token refreshes, so that refreshing does not need a new TLS
+    /// handshake.
+    ///
+    /// Idle connections are kept for two hours, and TCP keep-alive
+    /// probes and HTTP/2 pings stop them from being dropped by
+    /// intermediaries in the meantime. GitHub may still close an idle
+    /// connection from its side, in which case the next refresh
+    /// simply reconnects.
+    pub fn keep_alive() -> Self {
+        Self {
+            pool_idle_timeout: Some(Duration::from_secs(2 * 60 * 60)),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            http2_keep_alive_interval: Some(Duration::from_secs(30)),
+            http2_keep_alive_timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn apply(
+        &self,
+        mut builder: ClientBuilder,
+    ) -> Result<ClientBuilder, AuthError> {
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+            if let Some(timeout) = self.http2_keep_alive_timeout {
+                builder = builder.http2_keep_alive_timeout(timeout);
+            }
+        }
+        if self.http1_only {
+            builder = builder.http1_only();
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        for (domain, addr) in &self.resolve {
+            builder = builder.resolve(domain, *addr);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.to_proxy()?);
+        } else if self.no_system_proxy {
+            builder = builder.no_proxy();
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockFailure, MockGithub};
+    use crate::{AuthError, GithubAuthParams, InstallationAccessToken};
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let github = MockGithub::start().await.unwrap();
+        let port = github.base_url().rsplit(':').next().unwrap().to_string();
+        let params = GithubAuthParams {
+            base_url: Some(format!("http://github.invalid:{}", port)),
+            client_options: ClientOptions {
+                resolve: vec![(
+                    "github.invalid".into(),
+                    "127.0.0.1:0".parse().unwrap(),
+                )],
+                ..Default::default()
+            },
+            ..github.params()
+        };
+        InstallationAccessToken::new(params).await.unwrap();
+        assert_eq!(github.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_reuse() {
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(chrono::Duration::seconds(30));
+        let params = GithubAuthParams {
+            client_options: ClientOptions::keep_alive(),
+            ..github.params()
+        };
+
+        // Refreshes use the token's client, so they stay on one
+        // connection.
+        let mut token = InstallationAccessToken::new(params).await.unwrap();
+        assert!(token.refresh_if_needed().await.unwrap());
+        assert!(token.refresh_if_needed().await.unwrap());
+        assert_eq!(github.connections(), 1);
+
+        // Tokens for other installations can share the same pool.
+        let params = GithubAuthParams {
+            installation_id: 3,
+            ..github.params()
+        };
+        InstallationAccessToken::with_client(token.client.clone(), params)
+            .await
+            .unwrap();
+        assert_eq!(github.token_requests(), 4);
+        assert_eq!(github.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        // The mock server also accepts requests in the form sent to a
+        // proxy, so it can stand in for one.
+        let github = MockGithub::start().await.unwrap();
+        let params = GithubAuthParams {
+            base_url: Some("http://github.invalid".into()),
+            client_options: ClientOptions {
+                proxy: Some(ProxyOptions {
+                    basic_auth: Some(("user".into(), "hunter2".into())),
+                    ..ProxyOptions::new(github.base_url())
+                }),
+                ..Default::default()
+            },
+            ..github.params()
+        };
+        let debug = format!("{:?}", params);
+        assert!(debug.contains(r#"basic_auth: Some(("user", "[redacted]"))"#));
+        assert!(!debug.contains("hunter2"));
+        InstallationAccessToken::new(params).await.unwrap();
+        assert_eq!(github.token_requests(), 1);
+
+        let params = GithubAuthParams {
+            client_options: ClientOptions {
+                proxy: Some(ProxyOptions::new("not a url")),
+                ..Default::default()
+            },
+            ..github.params()
+        };
+        assert!(InstallationAccessToken::new(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let github = MockGithub::start().await.unwrap();
+        let params = GithubAuthParams {
+            client_options: ClientOptions {
+                timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+            ..github.params()
+        };
+        github.fail_next_token_request(MockFailure::Delay(
+            Duration::from_secs(5),
+        ));
+        let err = InstallationAccessToken::new(params.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AuthError::Timeout(_)));
+        assert!(err.is_retryable());
+
+        InstallationAccessToken::new(params).await.unwrap();
+    }
+}
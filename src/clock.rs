@@ -0,0 +1,112 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// Source of the current time, used when signing JWTs and when
+/// checking whether a token needs refreshing.
+///
+/// Set [`GithubAuthParams::clock`](crate::GithubAuthParams::clock) to
+/// replace the system clock, for example with
+/// `test_util::MockClock` (requires the `test-util` feature) to test
+/// refresh behavior without waiting for tokens to expire.
+pub trait Clock: Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock. This is used if no other clock is set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Get the current time from `clock`, or the system clock if `None`,
+/// corrected by `skew` if set.
+pub(crate) fn current_time(
+    clock: Option<&dyn Clock>,
+    skew: Option<&ClockSkew>,
+) -> DateTime<Utc> {
+    let now = match clock {
+        Some(clock) => clock.now(),
+        None => Utc::now(),
+    };
+    match skew {
+        Some(skew) => now + skew.offset(),
+        None => now,
+    }
+}
+
+/// Offset between the local clock and GitHub's clock, learned from
+/// the `Date` header of API responses.
+///
+/// GitHub rejects JWTs whose `iat` is in the future or whose `exp` is
+/// too far out, so a host with a badly drifting clock fails to
+/// authenticate. Setting
+/// [`GithubAuthParams::clock_skew`](crate::GithubAuthParams::clock_skew)
+/// makes the crate record the offset from every response and apply it
+/// when signing JWTs and when checking whether a token has expired.
+///
+/// Clones share the same offset, so one `ClockSkew` can be set on the
+/// parameters of many tokens. The first JWT is signed with the local
+/// clock, since no response has been seen yet; if it is rejected, the
+/// next attempt uses the corrected time.
+#[derive(Clone, Debug, Default)]
+pub struct ClockSkew {
+    offset_secs: Arc<AtomicI64>,
+}
+
+impl ClockSkew {
+    /// Create a skew with no offset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How far GitHub's clock is ahead of the local clock. Negative
+    /// if the local clock is ahead.
+    pub fn offset(&self) -> Duration {
+        Duration::seconds(self.offset_secs.load(Ordering::Relaxed))
+    }
+
+    /// Get the current time according to GitHub.
+    pub fn now(&self) -> DateTime<Utc> {
+        Utc::now() + self.offset()
+    }
+
+    /// Record the value of a `Date` header received at the current
+    /// local time.
+    #[cfg(feature = "client")]
+    pub(crate) fn record_date_header(&self, date: &str) {
+        let Ok(server) = DateTime::parse_from_rfc2822(date) else {
+            return;
+        };
+        let offset = server.with_timezone(&Utc) - Utc::now();
+        // The header has one second resolution, so round towards zero
+        // rather than chase sub-second noise.
+        self.offset_secs
+            .store(offset.num_seconds(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_date_header() {
+        let skew = ClockSkew::new();
+        assert_eq!(skew.offset(), Duration::zero());
+
+        let server = Utc::now() + Duration::minutes(10);
+        skew.record_date_header(&server.to_rfc2822());
+        let offset = skew.offset().num_seconds();
+        assert!((599..=600).contains(&offset), "offset: {}", offset);
+        assert!(skew.clone().now() > Utc::now() + Duration::minutes(9));
+
+        skew.record_date_header("not a date");
+        assert_eq!(skew.offset().num_seconds(), offset);
+    }
+}
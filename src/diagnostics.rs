@@ -0,0 +1,334 @@
+use crate::{GithubAuthParams, JwtClaims, JwtIssuer, MAX_JWT_LIFETIME};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use ring::signature::{self, KeyPair, RsaKeyPair};
+use std::fmt;
+
+/// A problem found in a JWT by [`diagnose_jwt`].
+#[derive(Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum JwtProblem {
+    /// The JWT is not three base64url-encoded segments, or the claims
+    /// could not be parsed.
+    Malformed(String),
+
+    /// The `alg` in the header is not `RS256` (or `HS256` if
+    /// [`insecure_hs256_secret`](GithubAuthParams::insecure_hs256_secret)
+    /// is set).
+    WrongAlgorithm(String),
+
+    /// The signature does not match the public half of the private
+    /// key.
+    InvalidSignature,
+
+    /// The private key could not be parsed, so the signature was not
+    /// checked.
+    UnusableKey,
+
+    /// The `iat` claim is in the future.
+    IssuedInFuture {
+        /// Seconds between now and `iat`.
+        secs: u64,
+    },
+
+    /// The `exp` claim is not after the `iat` claim.
+    ExpiresBeforeIssued,
+
+    /// The `exp` claim is more than ten minutes after the `iat` claim.
+    LifetimeTooLong {
+        /// Seconds between `iat` and `exp`.
+        secs: u64,
+    },
+
+    /// The `exp` claim is in the past.
+    Expired,
+
+    /// The `iss` claim does not identify the app in the parameters.
+    IssuerMismatch {
+        /// The `iss` claim of the JWT.
+        actual: String,
+        /// The app ID or client ID from the parameters.
+        expected: String,
+    },
+}
+
+impl fmt::Display for JwtProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(msg) => write!(f, "malformed JWT: {}", msg),
+            Self::WrongAlgorithm(alg) => write!(f, "unexpected alg {}", alg),
+            Self::InvalidSignature => write!(
+                f,
+                "signature does not match the public half of the private key"
+            ),
+            Self::UnusableKey => {
+                write!(f, "private key is unusable, signature not checked")
+            }
+            Self::IssuedInFuture { secs } => {
+                write!(f, "iat is {}s in the future", secs)
+            }
+            Self::ExpiresBeforeIssued => write!(f, "exp is not after iat"),
+            Self::LifetimeTooLong { secs } => write!(
+                f,
+                "exp is {}s after iat, beyond the 10 minute limit",
+                secs
+            ),
+            Self::Expired => write!(f, "exp is in the past"),
+            Self::IssuerMismatch { actual, expected } => {
+                write!(f, "iss mismatch: got {}, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+/// The header and claims of a JWT, decoded without verification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedJwt {
+    /// The JOSE header, for example `{"typ": "JWT", "alg": "RS256"}`.
+    pub header: serde_json::Value,
+
+    /// The claims, for example `{"iat": ..., "exp": ..., "iss": ...}`.
+    pub claims: serde_json::Value,
+}
+
+/// Decode the header and claims of a JWT without verifying the
+/// signature.
+pub fn decode_jwt(jwt: &str) -> Result<DecodedJwt, JwtProblem> {
+    let segments = split(jwt)?;
+    Ok(DecodedJwt {
+        header: decode_segment(segments[0]).map_err(JwtProblem::Malformed)?,
+        claims: decode_segment(segments[1]).map_err(JwtProblem::Malformed)?,
+    })
+}
+
+/// Check a JWT for common mistakes before sending it to GitHub.
+///
+/// The claims are checked against the limits GitHub enforces and
+/// against the app ID or client ID in `params`. The issuer check is
+/// skipped if neither is set. If the primary private key is available
+/// in PEM form (that is, [`encoding_key`](GithubAuthParams::encoding_key)
+/// is not set and the key is not empty), the signature is also
+/// verified against its public half.
+///
+/// GitHub's own error for a bad JWT is usually just "401 A JSON web
+/// token could not be decoded", so this is useful for finding out
+/// what is actually wrong. An empty list means no problems were found.
+pub fn diagnose_jwt(params: &GithubAuthParams, jwt: &str) -> Vec<JwtProblem> {
+    match params.primary_private_key_pem() {
+        Ok(pem) => {
+            let pem = pem.as_deref().filter(|pem| !pem.is_empty());
+            diagnose(params, jwt, pem)
+        }
+        Err(_) => {
+            let mut problems = diagnose(params, jwt, None);
+            problems.push(JwtProblem::UnusableKey);
+            problems
+        }
+    }
+}
+
+/// Check a JWT, verifying the signature against `pem` if provided.
+pub(crate) fn diagnose(
+    params: &GithubAuthParams,
+    jwt: &str,
+    pem: Option<&[u8]>,
+) -> Vec<JwtProblem> {
+    let mut problems = Vec::new();
+
+    let segments = match split(jwt) {
+        Ok(segments) => segments,
+        Err(problem) => return vec![problem],
+    };
+
+    #[derive(serde::Deserialize)]
+    struct Header {
+        alg: String,
+    }
+    let expected_alg = format!("{:?}", params.jwt_algorithm());
+    match decode_segment::<Header>(segments[0]) {
+        Ok(header) if header.alg != expected_alg => {
+            problems.push(JwtProblem::WrongAlgorithm(header.alg))
+        }
+        Ok(_) => {}
+        Err(msg) => problems.push(JwtProblem::Malformed(msg)),
+    }
+
+    match decode_segment::<JwtClaims>(segments[1]) {
+        Ok(claims) => check_claims(params, &claims, &mut problems),
+        Err(msg) => problems.push(JwtProblem::Malformed(msg)),
+    }
+
+    if let Some(pem) = pem {
+        match public_key(pem) {
+            Some(public_key) => {
+                let message = format!("{}.{}", segments[0], segments[1]);
+                let valid = URL_SAFE_NO_PAD
+                    .decode(segments[2])
+                    .ok()
+                    .and_then(|sig| {
+                        signature::UnparsedPublicKey::new(
+                            &signature::RSA_PKCS1_2048_8192_SHA256,
+                            public_key,
+                        )
+                        .verify(message.as_bytes(), &sig)
+                        .ok()
+                    })
+                    .is_some();
+                if !valid {
+                    problems.push(JwtProblem::InvalidSignature);
+                }
+            }
+            None => problems.push(JwtProblem::UnusableKey),
+        }
+    }
+
+    problems
+}
+
+fn check_claims(
+    params: &GithubAuthParams,
+    claims: &JwtClaims,
+    problems: &mut Vec<JwtProblem>,
+) {
+    let now = params.now().timestamp().try_into().unwrap_or_default();
+
+    if claims.iat > now {
+        problems.push(JwtProblem::IssuedInFuture {
+            secs: claims.iat - now,
+        });
+    }
+    // A backdated iat is allowed on top of the lifetime limit, since
+    // GitHub only limits how far in the future exp is.
+    let max_lifetime =
+        MAX_JWT_LIFETIME + params.jwt_backdate.unwrap_or_default();
+    if claims.exp <= claims.iat {
+        problems.push(JwtProblem::ExpiresBeforeIssued);
+    } else if claims.exp - claims.iat > max_lifetime.as_secs() {
+        problems.push(JwtProblem::LifetimeTooLong {
+            secs: claims.exp - claims.iat,
+        });
+    }
+    if claims.exp <= now {
+        problems.push(JwtProblem::Expired);
+    }
+
+    let expected = params.issuer();
+    if expected != JwtIssuer::AppId(0) && claims.iss != expected {
+        problems.push(JwtProblem::IssuerMismatch {
+            actual: claims.iss.to_string(),
+            expected: expected.to_string(),
+        });
+    }
+}
+
+fn split(jwt: &str) -> Result<Vec<&str>, JwtProblem> {
+    let segments: Vec<&str> = jwt.trim().split('.').collect();
+    if segments.len() != 3 {
+        return Err(JwtProblem::Malformed(format!(
+            "expected 3 segments, got {}",
+            segments.len()
+        )));
+    }
+    Ok(segments)
+}
+
+fn decode_segment<T: serde::de::DeserializeOwned>(
+    segment: &str,
+) -> Result<T, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|err| format!("invalid base64url: {}", err))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|err| format!("invalid JSON: {}", err))
+}
+
+/// Get the DER-encoded RSA public key from a PKCS#1 or PKCS#8 private
+/// key in PEM format.
+fn public_key(pem: &[u8]) -> Option<Vec<u8>> {
+    let pem = std::str::from_utf8(pem).ok()?;
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    let der = STANDARD.decode(body).ok()?;
+    let key_pair = if pem.contains("BEGIN RSA PRIVATE KEY") {
+        RsaKeyPair::from_der(&der).ok()?
+    } else {
+        RsaKeyPair::from_pkcs8(&der).ok()?
+    };
+    Some(key_pair.public_key().as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign_app_jwt;
+    use std::time;
+
+    const TEST_KEY: &[u8] =
+        include_bytes!("../tests/data/test_private_key.pem");
+
+    fn test_params() -> GithubAuthParams {
+        GithubAuthParams {
+            private_key: TEST_KEY.to_vec(),
+            app_id: 1234,
+            ..Default::default()
+        }
+    }
+
+    fn encode(claims: &serde_json::Value) -> String {
+        let header = jsonwebtoken::Header {
+            alg: jsonwebtoken::Algorithm::RS256,
+            ..Default::default()
+        };
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(TEST_KEY).unwrap();
+        jsonwebtoken::encode(&header, claims, &key).unwrap()
+    }
+
+    #[test]
+    fn test_diagnose_jwt() {
+        let params = test_params();
+
+        let jwt = sign_app_jwt(&params).unwrap();
+        assert_eq!(diagnose_jwt(&params, &jwt), vec![]);
+
+        let mut tampered = sign_app_jwt(&params).unwrap().into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert_eq!(
+            diagnose_jwt(&params, &tampered),
+            vec![JwtProblem::InvalidSignature]
+        );
+
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let jwt = encode(&serde_json::json!({
+            "iat": now,
+            "exp": now + 3600,
+            "iss": 5678,
+        }));
+        assert_eq!(
+            diagnose_jwt(&params, &jwt),
+            vec![
+                JwtProblem::LifetimeTooLong { secs: 3600 },
+                JwtProblem::IssuerMismatch {
+                    actual: "5678".into(),
+                    expected: "1234".into(),
+                },
+            ]
+        );
+
+        let decoded = decode_jwt(&jwt).unwrap();
+        assert_eq!(decoded.header["alg"], "RS256");
+        assert_eq!(decoded.claims["iss"], 5678);
+
+        assert_eq!(
+            diagnose_jwt(&params, "not a jwt"),
+            vec![JwtProblem::Malformed("expected 3 segments, got 1".into())]
+        );
+    }
+}
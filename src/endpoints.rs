@@ -0,0 +1,167 @@
+use crate::{PermissionLevel, Permissions};
+use PermissionLevel::{Read, Write};
+
+/// Error returned by [`required_permissions`] for an endpoint that is
+/// not in the built-in table.
+#[derive(Debug, thiserror::Error, Eq, PartialEq)]
+#[error("no known permission requirement for {0}")]
+pub struct UnknownEndpoint(pub String);
+
+/// Permission required by common repository endpoints, relative to
+/// `/repos/{owner}/{repo}`. In paths, a `{name}` segment matches any
+/// single segment, except that a final `{path}` matches one or more
+/// segments.
+const REPO_ENDPOINTS: &[(&str, &str, PermissionLevel)] = &[
+    ("GET /", "metadata", Read),
+    ("GET /branches", "contents", Read),
+    ("GET /commits", "contents", Read),
+    ("GET /commits/{ref}", "contents", Read),
+    ("GET /contents/{path}", "contents", Read),
+    ("PUT /contents/{path}", "contents", Write),
+    ("DELETE /contents/{path}", "contents", Write),
+    ("POST /git/refs", "contents", Write),
+    ("GET /releases", "contents", Read),
+    ("POST /releases", "contents", Write),
+    ("GET /issues", "issues", Read),
+    ("POST /issues", "issues", Write),
+    ("GET /issues/{number}", "issues", Read),
+    ("PATCH /issues/{number}", "issues", Write),
+    ("POST /issues/{number}/comments", "issues", Write),
+    ("POST /issues/{number}/labels", "issues", Write),
+    ("GET /pulls", "pull_requests", Read),
+    ("POST /pulls", "pull_requests", Write),
+    ("GET /pulls/{number}", "pull_requests", Read),
+    ("PATCH /pulls/{number}", "pull_requests", Write),
+    ("PUT /pulls/{number}/merge", "contents", Write),
+    ("POST /pulls/{number}/reviews", "pull_requests", Write),
+    ("POST /check-runs", "checks", Write),
+    ("PATCH /check-runs/{id}", "checks", Write),
+    ("GET /commits/{ref}/check-runs", "checks", Read),
+    ("GET /commits/{ref}/status", "statuses", Read),
+    ("POST /statuses/{sha}", "statuses", Write),
+    ("GET /deployments", "deployments", Read),
+    ("POST /deployments", "deployments", Write),
+    ("POST /deployments/{id}/statuses", "deployments", Write),
+    ("GET /actions/runs", "actions", Read),
+    ("GET /actions/artifacts", "actions", Read),
+    ("POST /actions/workflows/{id}/dispatches", "actions", Write),
+    ("GET /actions/secrets", "secrets", Read),
+    ("PUT /actions/secrets/{name}", "secrets", Write),
+    ("GET /hooks", "repository_hooks", Read),
+    ("POST /hooks", "repository_hooks", Write),
+    ("PUT /collaborators/{username}", "administration", Write),
+    ("GET /code-scanning/alerts", "security_events", Read),
+    ("GET /pages", "pages", Read),
+];
+
+/// Permission required by common organization endpoints, relative to
+/// `/orgs/{org}`.
+const ORG_ENDPOINTS: &[(&str, &str, PermissionLevel)] = &[
+    ("GET /members", "members", Read),
+    ("PUT /memberships/{username}", "members", Write),
+    ("PATCH /", "organization_administration", Write),
+];
+
+/// Compute the minimal permissions needed to call a set of REST API
+/// endpoints.
+///
+/// Each endpoint is a method and path, for example
+/// `"GET /repos/{owner}/{repo}/issues"` or
+/// `"PUT /repos/octo/hello/contents/docs/README.md"`; both the
+/// templated form from GitHub's documentation and concrete paths are
+/// accepted, and any query string is ignored. When several endpoints
+/// need the same permission, the highest level is used.
+///
+/// The result can be used for
+/// [`GithubAuthParams::required_permissions`](crate::GithubAuthParams::required_permissions)
+/// or to request a token with reduced permissions. The table only
+/// covers common endpoints; an error is returned for any endpoint not
+/// in it, rather than silently under-reporting what is needed.
+pub fn required_permissions(
+    endpoints: &[&str],
+) -> Result<Permissions, UnknownEndpoint> {
+    let mut permissions = Permissions::default();
+    for endpoint in endpoints {
+        let (name, level) = lookup(endpoint)
+            .ok_or_else(|| UnknownEndpoint(endpoint.to_string()))?;
+        if !matches!(permissions.get(name), Some(current) if current >= level) {
+            permissions.set(name, Some(level));
+        }
+    }
+    Ok(permissions)
+}
+
+fn lookup(endpoint: &str) -> Option<(&'static str, PermissionLevel)> {
+    let (method, path) = endpoint.trim().split_once(' ')?;
+    let path = path.trim();
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    [
+        ("/repos/{owner}/{repo}", REPO_ENDPOINTS),
+        ("/orgs/{org}", ORG_ENDPOINTS),
+    ]
+    .iter()
+    .flat_map(|(prefix, table)| table.iter().map(move |entry| (prefix, entry)))
+    .find_map(|(prefix, (pattern, name, level))| {
+        let (m, rest) = pattern.split_once(' ')?;
+        let pattern = format!("{}{}", prefix, rest);
+        (m.eq_ignore_ascii_case(method) && path_matches(&pattern, path))
+            .then_some((*name, *level))
+    })
+}
+
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut path = path.trim_matches('/').split('/');
+    let mut pattern = pattern.trim_matches('/').split('/').peekable();
+    while let Some(expected) = pattern.next() {
+        if expected == "{path}" && pattern.peek().is_none() {
+            return path.next().is_some_and(|s| !s.is_empty());
+        }
+        match path.next() {
+            Some(actual) if expected.starts_with('{') => {
+                if actual.is_empty() {
+                    return false;
+                }
+            }
+            Some(actual) if actual == expected => {}
+            _ => return false,
+        }
+    }
+    path.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_permissions() {
+        let permissions = required_permissions(&[
+            "GET /repos/{owner}/{repo}/contents/{path}",
+            "PUT /repos/octo/hello/contents/docs/README.md",
+            "GET /repos/octo/hello/issues?state=open",
+            "post /repos/octo/hello/check-runs",
+        ])
+        .unwrap();
+        assert_eq!(
+            permissions,
+            Permissions {
+                contents: Some(Write),
+                issues: Some(Read),
+                checks: Some(Write),
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            required_permissions(&["GET /repos/octo/hello/unknown"]),
+            Err(UnknownEndpoint("GET /repos/octo/hello/unknown".into()))
+        );
+        assert!(required_permissions(&["GET /repos/octo"]).is_err());
+        assert_eq!(
+            required_permissions(&["GET /repos/octo/hello"])
+                .unwrap()
+                .metadata,
+            Some(Read)
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Load the app's private key from Google Cloud Secret Manager.
+//!
+//! Requests are authorized with an access token for the ambient
+//! service account, fetched from the metadata server as the Google
+//! Cloud client libraries do. The metadata server is available on
+//! Compute Engine, Cloud Run, and Cloud Functions, and on GKE with
+//! Workload Identity. Its address can be changed with
+//! `GCE_METADATA_HOST`. The access token is fetched again shortly
+//! before it expires. The project is read from `GOOGLE_CLOUD_PROJECT`
+//! or `GCLOUD_PROJECT`.
+//!
+//! ```no_run
+//! use github_app_auth::gcp::GcpKeySource;
+//! use github_app_auth::GithubAuthParams;
+//!
+//! # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+//! let source = GcpKeySource::from_env();
+//! let params = GithubAuthParams {
+//!     private_key: source.secret_manager("github-app-key").await?,
+//!     ..GithubAuthParams::new("my-app", Vec::new(), 1234, 5678)
+//! };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The key is fetched once. To pick up a rotated key, fetch it again
+//! and create new parameters.
+//!
+//! This module requires the `gcp` feature.
+
+use crate::response::check_status;
+use crate::AuthError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default address of the metadata server.
+const METADATA_HOST: &str = "metadata.google.internal";
+
+/// The metadata server is local, so give up quickly if it is not
+/// there rather than stall startup off Google Cloud.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address of the Secret Manager API.
+const SECRET_MANAGER_URL: &str = "https://secretmanager.googleapis.com";
+
+/// How long before it expires the access token is replaced.
+const TOKEN_REFRESH_MARGIN: chrono::Duration = chrono::Duration::minutes(5);
+
+/// An OAuth access token for Google Cloud APIs.
+#[derive(Clone)]
+struct AccessToken {
+    token: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("token", &"[redacted]")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl AccessToken {
+    fn needs_refresh(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| {
+            expires_at - TOKEN_REFRESH_MARGIN <= Utc::now()
+        })
+    }
+}
+
+/// Access token as returned by the metadata server.
+#[derive(Deserialize)]
+struct MetadataToken {
+    access_token: String,
+    expires_in: i64,
+}
+
+#[derive(Deserialize)]
+struct AccessSecretVersionResponse {
+    payload: SecretPayload,
+}
+
+#[derive(Deserialize)]
+struct SecretPayload {
+    data: String,
+}
+
+/// Fetches secrets from Google Cloud Secret Manager.
+#[derive(Clone, Debug)]
+pub struct GcpKeySource {
+    /// Project that short secret names are looked up in.
+    pub project: Option<String>,
+
+    /// Endpoint to send requests to instead of the global Secret
+    /// Manager endpoint, for example a regional or Private Service
+    /// Connect endpoint.
+    pub endpoint_url: Option<String>,
+
+    /// Base URL of the metadata server, or `None` if the access token
+    /// was given explicitly.
+    metadata_url: Option<String>,
+    token: Arc<Mutex<Option<AccessToken>>>,
+    client: reqwest::Client,
+}
+
+impl GcpKeySource {
+    /// Create a source from the ambient configuration: the project
+    /// from `GOOGLE_CLOUD_PROJECT` or `GCLOUD_PROJECT`, and access
+    /// tokens from the metadata server as described in the
+    /// [module documentation](self). No request is made until a
+    /// secret is fetched.
+    pub fn from_env() -> GcpKeySource {
+        let host = env::var("GCE_METADATA_HOST")
+            .unwrap_or_else(|_| METADATA_HOST.into());
+        GcpKeySource {
+            project: env::var("GOOGLE_CLOUD_PROJECT")
+                .or_else(|_| env::var("GCLOUD_PROJECT"))
+                .ok(),
+            endpoint_url: None,
+            metadata_url: Some(format!("http://{}", host)),
+            token: Arc::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Create a source that uses an explicit access token, for
+    /// example one from `gcloud auth print-access-token`. The token is
+    /// used as it is, even after it expires.
+    pub fn new(access_token: impl Into<String>) -> GcpKeySource {
+        GcpKeySource {
+            project: None,
+            endpoint_url: None,
+            metadata_url: None,
+            token: Arc::new(Mutex::new(Some(AccessToken {
+                token: access_token.into(),
+                expires_at: None,
+            }))),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Get the value of a secret version.
+    ///
+    /// `secret` is either a full resource name, such as
+    /// `projects/my-project/secrets/github-app-key/versions/3`, or the
+    /// name of a secret in [`project`](Self::project). The latest
+    /// version is used if none is given.
+    pub async fn secret_manager(
+        &self,
+        secret: &str,
+    ) -> Result<Vec<u8>, AuthError> {
+        let mut name = if secret.starts_with("projects/") {
+            secret.to_string()
+        } else {
+            let project = self.project.as_deref().ok_or_else(|| {
+                AuthError::InvalidConfig(format!(
+                    "no project set for secret {}",
+                    secret
+                ))
+            })?;
+            format!("projects/{}/secrets/{}", project, secret)
+        };
+        if !name.contains("/versions/") {
+            name += "/versions/latest";
+        }
+        let base_url =
+            self.endpoint_url.as_deref().unwrap_or(SECRET_MANAGER_URL);
+        let url =
+            format!("{}/v1/{}:access", base_url.trim_end_matches('/'), name);
+        let request =
+            self.client.get(url).bearer_auth(self.access_token().await?);
+        let resp = check_status(request.send().await?).await?;
+        let resp: AccessSecretVersionResponse = resp.json().await?;
+        STANDARD.decode(resp.payload.data).map_err(|err| {
+            AuthError::InvalidConfig(format!(
+                "invalid secret payload {}: {}",
+                name, err
+            ))
+        })
+    }
+
+    /// Get the access token, fetching a new one from the metadata
+    /// server first if there is none or it is about to expire.
+    async fn access_token(&self) -> Result<String, AuthError> {
+        let token = self.token.lock().unwrap().clone();
+        let metadata_url = match (token, &self.metadata_url) {
+            (Some(token), Some(_)) if !token.needs_refresh() => {
+                return Ok(token.token)
+            }
+            (Some(token), None) => return Ok(token.token),
+            (_, Some(url)) => url,
+            (None, None) => unreachable!("explicit sources have a token"),
+        };
+        debug!("fetching a Google Cloud access token from the metadata server");
+        let url = format!(
+            "{}/computeMetadata/v1/instance/service-accounts/default/token",
+            metadata_url
+        );
+        let request = self
+            .client
+            .get(url)
+            .header("Metadata-Flavor", "Google")
+            .timeout(METADATA_TIMEOUT);
+        let resp = check_status(request.send().await?).await?;
+        let resp: MetadataToken = resp.json().await?;
+        let token = AccessToken {
+            token: resp.access_token,
+            expires_at: Some(
+                Utc::now() + chrono::Duration::seconds(resp.expires_in),
+            ),
+        };
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_once;
+
+    #[tokio::test]
+    async fn test_secret_manager() {
+        let (metadata_url, metadata_request) = serve_once(
+            r#"{"access_token": "ya29.mock", "expires_in": 3599,
+                "token_type": "Bearer"}"#,
+        )
+        .await;
+        let (endpoint_url, secret_request) = serve_once(
+            r#"{"name": "projects/1/secrets/github-app-key/versions/2",
+                "payload": {"data": "bXkgcHJpdmF0ZSBrZXk=",
+                            "dataCrc32c": "1"}}"#,
+        )
+        .await;
+        let source = GcpKeySource {
+            project: Some("my-project".into()),
+            endpoint_url: Some(endpoint_url),
+            metadata_url: Some(metadata_url),
+            token: Arc::default(),
+            client: reqwest::Client::new(),
+        };
+        let key = source.secret_manager("github-app-key").await.unwrap();
+        assert_eq!(key, b"my private key");
+
+        let request = metadata_request.await.unwrap().to_lowercase();
+        assert!(request.starts_with(
+            "get /computemetadata/v1/instance/service-accounts/default/token "
+        ));
+        assert!(request.contains("metadata-flavor: google"));
+        let request = secret_request.await.unwrap();
+        assert!(request.starts_with(
+            "GET /v1/projects/my-project/secrets/github-app-key/versions/latest:access "
+        ));
+        assert!(request.contains("authorization: Bearer ya29.mock"));
+        assert!(!format!("{:?}", source).contains("ya29"));
+    }
+
+    #[tokio::test]
+    async fn test_secret_name() {
+        let source = GcpKeySource::new("ya29.explicit");
+        assert!(matches!(
+            source.secret_manager("github-app-key").await,
+            Err(AuthError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::response::{check_status, send};
+use crate::{AuthError, GithubAuthParams, InstallationAccessToken};
+use std::ops::{Deref, DerefMut};
+
+/// Revoke an installation access token with `DELETE /installation/token`.
+pub(crate) async fn revoke_token(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    token: &str,
+) -> Result<(), AuthError> {
+    let request = params.api_headers(
+        client
+            .delete(params.api_url("/installation/token"))
+            .header("Authorization", format!("token {}", token)),
+    );
+    let resp = send(params, request).await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// An installation access token that is revoked when dropped.
+///
+/// This is intended for short-lived jobs that should not leave valid
+/// credentials behind. The guard dereferences to the
+/// [`InstallationAccessToken`], so it can be used in the same way.
+///
+/// Revoking requires a network request, which can't be awaited in
+/// `drop`. If the guard is dropped inside a Tokio runtime, the
+/// revocation is spawned as a background task and any error is
+/// logged; otherwise a warning is logged and the token is left to
+/// expire on its own. Call [`revoke`](Self::revoke) to revoke the
+/// token deterministically and observe errors.
+///
+/// Only the current token is revoked. If the token was refreshed,
+/// earlier tokens remain valid until they expire.
+pub struct ScopedTokenGuard {
+    token: Option<InstallationAccessToken>,
+}
+
+impl ScopedTokenGuard {
+    /// Fetch a new installation access token, see
+    /// [`InstallationAccessToken::new`].
+    pub async fn new(
+        params: GithubAuthParams,
+    ) -> Result<ScopedTokenGuard, AuthError> {
+        Ok(ScopedTokenGuard::from(
+            InstallationAccessToken::new(params).await?,
+        ))
+    }
+
+    /// Revoke the token now, rather than when the guard is dropped.
+    pub async fn revoke(mut self) -> Result<(), AuthError> {
+        let token = self.token.take().expect("token already revoked");
+        token.revoke().await
+    }
+}
+
+impl From<InstallationAccessToken> for ScopedTokenGuard {
+    fn from(token: InstallationAccessToken) -> ScopedTokenGuard {
+        ScopedTokenGuard { token: Some(token) }
+    }
+}
+
+impl Deref for ScopedTokenGuard {
+    type Target = InstallationAccessToken;
+
+    fn deref(&self) -> &InstallationAccessToken {
+        self.token.as_ref().expect("token already revoked")
+    }
+}
+
+impl DerefMut for ScopedTokenGuard {
+    fn deref_mut(&mut self) -> &mut InstallationAccessToken {
+        self.token.as_mut().expect("token already revoked")
+    }
+}
+
+impl Drop for ScopedTokenGuard {
+    fn drop(&mut self) {
+        let Some(token) = self.token.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(err) = token.revoke().await {
+                        warn!("failed to revoke installation token: {}", err);
+                    }
+                });
+            }
+            Err(_) => {
+                warn!("no Tokio runtime, installation token not revoked");
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+    use crate::TokenValidity;
+
+    #[tokio::test]
+    async fn test_scoped_token_guard() {
+        let github = MockGithub::start().await.unwrap();
+
+        let mut guard = ScopedTokenGuard::new(github.params()).await.unwrap();
+        assert_eq!(guard.verify().await.unwrap(), TokenValidity::Valid);
+        guard.revoke().await.unwrap();
+        assert_eq!(github.revocations(), 1);
+
+        let guard = ScopedTokenGuard::new(github.params()).await.unwrap();
+        drop(guard);
+        for _ in 0..100 {
+            if github.revocations() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(github.revocations(), 2);
+    }
+}
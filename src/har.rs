@@ -0,0 +1,267 @@
+use crate::response::SENSITIVE_HEADERS;
+use crate::AuthError;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{Method, Url};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// JSON fields whose values are replaced with `[redacted]` in
+/// captured bodies.
+const SENSITIVE_FIELDS: &[&str] =
+    &["token", "access_token", "refresh_token", "client_secret"];
+
+/// Records the HTTP exchanges made by this crate in
+/// [HAR](https://w3c.github.io/web-performance/specs/HAR/Overview.html)
+/// format, for attaching to bug reports and support tickets.
+///
+/// Set [`GithubAuthParams::har_recorder`](crate::GithubAuthParams::har_recorder)
+/// to start recording. The recorder is cheap to clone, and clones
+/// share the same entries, so keep a clone to write out the capture
+/// later.
+///
+/// Secrets are stripped before anything is recorded: credential
+/// headers such as `Authorization` are redacted, as are token fields
+/// in JSON bodies. The capture still contains URLs, installation and
+/// repository names, so review it before sharing.
+#[derive(Clone, Debug, Default)]
+pub struct HarRecorder {
+    entries: Arc<Mutex<Vec<Value>>>,
+}
+
+impl HarRecorder {
+    /// Create an empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of exchanges recorded so far.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no exchanges have been recorded yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the capture as a HAR document.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": *self.entries.lock().unwrap(),
+            }
+        })
+    }
+
+    /// Write the capture to a HAR file.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), AuthError> {
+        let har = serde_json::to_vec_pretty(&self.to_json())
+            .map_err(std::io::Error::from)?;
+        std::fs::write(path, har)?;
+        Ok(())
+    }
+
+    /// Record one exchange. `response` is `None` if the request
+    /// failed without a response.
+    pub(crate) fn record(
+        &self,
+        started: DateTime<Utc>,
+        elapsed: Duration,
+        request: HarRequest<'_>,
+        response: Option<HarResponse<'_>>,
+    ) {
+        let time = elapsed.as_secs_f64() * 1000.0;
+        let mut har_request = json!({
+            "method": request.method.as_str(),
+            "url": request.url.as_str(),
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": headers(request.headers),
+            "queryString": request
+                .url
+                .query_pairs()
+                .map(|(name, value)| json!({"name": name, "value": value}))
+                .collect::<Vec<_>>(),
+            "headersSize": -1,
+            "bodySize": request.body.map_or(0, |body| body.len()),
+        });
+        if let Some(body) = request.body {
+            har_request["postData"] = json!({
+                "mimeType": mime_type(request.headers),
+                "text": redact_body(body),
+            });
+        }
+        let har_response = match response {
+            Some(response) => json!({
+                "status": response.status.as_u16(),
+                "statusText":
+                    response.status.canonical_reason().unwrap_or_default(),
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": headers(response.headers),
+                "content": {
+                    "size": response.body.len(),
+                    "mimeType": mime_type(response.headers),
+                    "text": redact_body(response.body),
+                },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": response.body.len(),
+            }),
+            None => json!({
+                "status": 0,
+                "statusText": "",
+                "httpVersion": "",
+                "cookies": [],
+                "headers": [],
+                "content": {"size": 0, "mimeType": ""},
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": -1,
+            }),
+        };
+        self.entries.lock().unwrap().push(json!({
+            "startedDateTime": started.to_rfc3339(),
+            "time": time,
+            "request": har_request,
+            "response": har_response,
+            "cache": {},
+            "timings": {"send": 0, "wait": time, "receive": 0},
+        }));
+    }
+}
+
+/// The parts of a request that are recorded.
+pub(crate) struct HarRequest<'a> {
+    pub method: &'a Method,
+    pub url: &'a Url,
+    pub headers: &'a HeaderMap,
+    pub body: Option<&'a [u8]>,
+}
+
+/// The parts of a response that are recorded.
+pub(crate) struct HarResponse<'a> {
+    pub status: reqwest::StatusCode,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(name) {
+                "[redacted]"
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+            json!({"name": name.as_str(), "value": value})
+        })
+        .collect()
+}
+
+fn mime_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Get a body as text, with token fields redacted if it is JSON.
+fn redact_body(body: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_FIELDS.contains(&key.as_str()) {
+                    *value = Value::from("[redacted]");
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::AUTHORIZATION;
+
+    #[test]
+    fn test_record() {
+        let recorder = HarRecorder::new();
+        let url = Url::parse(
+            "https://api.github.com/app/installations/2/access_tokens",
+        )
+        .unwrap();
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(AUTHORIZATION, "Bearer jwt".parse().unwrap());
+        let response_headers = HeaderMap::new();
+        recorder.record(
+            Utc::now(),
+            Duration::from_millis(5),
+            HarRequest {
+                method: &Method::POST,
+                url: &url,
+                headers: &request_headers,
+                body: None,
+            },
+            Some(HarResponse {
+                status: reqwest::StatusCode::CREATED,
+                headers: &response_headers,
+                body: br#"{"token":"ghs_secret","expires_at":"x"}"#,
+            }),
+        );
+        assert_eq!(recorder.len(), 1);
+
+        let har = recorder.to_json();
+        let text = har.to_string();
+        assert!(!text.contains("ghs_secret"));
+        assert!(!text.contains("Bearer jwt"));
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["request"]["method"], "POST");
+        assert_eq!(entry["response"]["status"], 201);
+        assert_eq!(
+            entry["response"]["content"]["text"],
+            r#"{"expires_at":"x","token":"[redacted]"}"#
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_capture_token_request() {
+        use crate::test_util::MockGithub;
+        use crate::{GithubAuthParams, InstallationAccessToken};
+
+        let github = MockGithub::start().await.unwrap();
+        let recorder = HarRecorder::new();
+        let params = GithubAuthParams {
+            har_recorder: Some(recorder.clone()),
+            ..github.params()
+        };
+        let token = InstallationAccessToken::new(params).await.unwrap();
+        assert_eq!(recorder.len(), 1);
+        let har = recorder.to_json().to_string();
+        assert!(!har.contains(&token.token));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::api_error::error_from_response;
+use crate::signer::app_jwt;
+use crate::{
+    AuthError, GithubAuthParams, Permissions, Repository, RepositorySelection,
+    TokenState, GITHUB_JSON,
+};
+use chrono::{DateTime, Utc};
+use http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`HttpClient::send`].
+pub type HttpFuture<'a> = Pin<
+    Box<
+        dyn Future<Output = Result<http::Response<Vec<u8>>, AuthError>>
+            + Send
+            + 'a,
+    >,
+>;
+
+/// Sends HTTP requests for [`fetch_installation_token`], so that an
+/// HTTP stack other than reqwest can be used.
+///
+/// With the `client` feature, this is implemented for
+/// `reqwest::Client`. Without it, the crate doesn't depend on
+/// reqwest or a TLS implementation at all, and an implementation for
+/// ureq, hyper, or a platform HTTP API can be supplied instead.
+pub trait HttpClient: Send + Sync {
+    /// Send `request` and return the response, whatever its status.
+    ///
+    /// Failures to get a response at all should be returned as
+    /// [`AuthError::HttpClientError`].
+    fn send(&self, request: http::Request<Vec<u8>>) -> HttpFuture<'_>;
+}
+
+/// JSON object returned when requesting an installation access token.
+#[derive(Deserialize)]
+struct RawToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+    #[serde(default)]
+    permissions: Permissions,
+    #[serde(default)]
+    repositories: Vec<Repository>,
+    #[serde(default)]
+    repository_selection: Option<RepositorySelection>,
+}
+
+/// Get an installation access token using `client` to make the
+/// request.
+///
+/// This signs the app JWT and calls
+/// `POST /app/installations/{id}/access_tokens` once, honoring the
+/// [`base_url`](GithubAuthParams::base_url),
+/// [`api_version`](GithubAuthParams::api_version), and
+/// [`scope`](GithubAuthParams::scope) of `params`. None of the
+/// reqwest-based machinery of
+/// `InstallationAccessToken`, such
+/// as retries and fallback keys, is involved. The returned state can
+/// be stored, or passed to
+/// `InstallationAccessToken::from_state`
+/// if the `client` feature is enabled.
+pub async fn fetch_installation_token(
+    client: &dyn HttpClient,
+    params: &GithubAuthParams,
+) -> Result<TokenState, AuthError> {
+    let jwt = app_jwt(params).await?;
+    let url = params.api_url(&format!(
+        "/app/installations/{}/access_tokens",
+        params.installation_id
+    ));
+    let body = if params.scope.is_empty() {
+        Vec::new()
+    } else {
+        serde_json::to_vec(&params.scope)
+            .map_err(|err| AuthError::InvalidConfig(err.to_string()))?
+    };
+    let request = http::Request::post(url)
+        .header(AUTHORIZATION, format!("Bearer {}", jwt))
+        .header(ACCEPT, GITHUB_JSON)
+        .header("X-GitHub-Api-Version", params.api_version_or_default())
+        .header(USER_AGENT, &params.user_agent)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .map_err(|err| AuthError::InvalidConfig(err.to_string()))?;
+
+    let (parts, body) = client.send(request).await?.into_parts();
+    if !parts.status.is_success() {
+        let text = String::from_utf8_lossy(&body);
+        return Err(error_from_response(parts.status, &parts.headers, &text));
+    }
+    let raw: RawToken = serde_json::from_slice(&body)
+        .map_err(|err| AuthError::HttpClientError(err.into()))?;
+    Ok(TokenState {
+        token: raw.token,
+        expires_at: raw.expires_at,
+        installation_id: params.installation_id,
+        permissions: raw.permissions,
+        repositories: raw.repositories,
+        repository_selection: raw.repository_selection,
+    })
+}
+
+#[cfg(feature = "client")]
+impl HttpClient for reqwest::Client {
+    fn send(&self, request: http::Request<Vec<u8>>) -> HttpFuture<'_> {
+        Box::pin(async move {
+            let resp =
+                self.execute(reqwest::Request::try_from(request)?).await?;
+            let mut builder = http::Response::builder().status(resp.status());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = resp.headers().clone();
+            }
+            let body = resp.bytes().await?.to_vec();
+            builder
+                .body(body)
+                .map_err(|err| AuthError::HttpClientError(err.into()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Returns a canned response and keeps the request.
+    struct FakeClient {
+        status: u16,
+        body: &'static str,
+        request: Mutex<Option<http::Request<Vec<u8>>>>,
+    }
+
+    impl HttpClient for FakeClient {
+        fn send(&self, request: http::Request<Vec<u8>>) -> HttpFuture<'_> {
+            *self.request.lock().unwrap() = Some(request);
+            let resp = http::Response::builder()
+                .status(self.status)
+                .body(self.body.as_bytes().to_vec())
+                .unwrap();
+            Box::pin(async move { Ok(resp) })
+        }
+    }
+
+    fn fake(status: u16, body: &'static str) -> FakeClient {
+        FakeClient {
+            status,
+            body,
+            request: Mutex::new(None),
+        }
+    }
+
+    fn params() -> GithubAuthParams {
+        GithubAuthParams {
+            user_agent: "test".into(),
+            installation_id: 1234,
+            base_url: Some("https://ghe.example.com/api/v3/".into()),
+            private_key: include_bytes!("../tests/data/test_private_key.pem")
+                .to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_installation_token() {
+        let client = fake(
+            201,
+            r#"{"token": "ghs_abc", "expires_at": "2030-01-01T00:00:00Z"}"#,
+        );
+        let state = fetch_installation_token(&client, &params()).await.unwrap();
+        assert_eq!(state.token, "ghs_abc");
+        assert_eq!(state.installation_id, 1234);
+
+        let request = client.request.lock().unwrap().take().unwrap();
+        assert_eq!(
+            request.uri(),
+            "https://ghe.example.com/api/v3/app/installations/1234/access_tokens"
+        );
+        assert_eq!(request.method(), http::Method::POST);
+        assert!(request.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .starts_with("Bearer "));
+        assert!(request.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_installation_token_error() {
+        let client = fake(404, r#"{"message": "Not Found"}"#);
+        let err = fetch_installation_token(&client, &params())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), Some(http::StatusCode::NOT_FOUND));
+
+        let client = fake(201, "not json");
+        let err = fetch_installation_token(&client, &params())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AuthError::HttpClientError(_)));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_reqwest_client() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let client = reqwest::Client::new();
+        let state = fetch_installation_token(&client, &github.params())
+            .await
+            .unwrap();
+        assert_eq!(state.token, "mock-token-1");
+    }
+}
@@ -0,0 +1,353 @@
+use crate::response::{check_status, send};
+use crate::signer::app_jwt;
+use crate::{AuthError, GithubAuthParams, PermissionLevel, Permissions};
+use chrono::{DateTime, Utc};
+use futures_core::Stream;
+use futures_util::{stream, StreamExt};
+use reqwest::header::{HeaderMap, LINK};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::pin::pin;
+
+/// Account (user or organization) that an app is installed on.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Account {
+    /// Account ID.
+    pub id: u64,
+
+    /// Login name of the user or organization.
+    pub login: String,
+
+    /// Account type, for example "User" or "Organization".
+    #[serde(rename = "type")]
+    pub account_type: String,
+}
+
+/// An installation of the app.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Installation {
+    /// Installation ID.
+    pub id: u64,
+
+    /// Account the app is installed on.
+    pub account: Option<Account>,
+
+    /// Time at which the installation was suspended, if it is
+    /// suspended.
+    pub suspended_at: Option<DateTime<Utc>>,
+
+    /// Permissions granted to the installation.
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+/// A pending request to install the app, made by a user who can't
+/// install it themselves.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct InstallationRequest {
+    /// Installation request ID.
+    pub id: u64,
+
+    /// Account the app would be installed on.
+    pub account: Account,
+
+    /// User who requested the installation.
+    pub requester: Account,
+
+    /// Time at which the installation was requested.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Options for [`list_installations`] and
+/// [`list_installation_requests`].
+#[derive(Clone, Debug, Default)]
+pub struct ListOptions {
+    /// Number of results requested per page. GitHub allows at most 100,
+    /// which is also the default.
+    pub per_page: Option<u8>,
+
+    /// Stop after this many results have been collected.
+    pub limit: Option<usize>,
+}
+
+/// Criteria for selecting installations in [`installations_stream`].
+///
+/// Each criterion that is set must match; the default filter matches
+/// all installations.
+#[derive(Clone, Debug, Default)]
+pub struct InstallationFilter {
+    /// Only match installations on this type of account, for example
+    /// "Organization".
+    pub account_type: Option<String>,
+
+    /// Only match installations that are (`true`) or are not
+    /// (`false`) suspended.
+    pub suspended: Option<bool>,
+
+    /// Only match installations that have been granted this
+    /// permission at (at least) the given level.
+    pub permission: Option<(String, PermissionLevel)>,
+}
+
+impl InstallationFilter {
+    /// Check whether an installation matches the filter.
+    pub fn matches(&self, installation: &Installation) -> bool {
+        if let Some(account_type) = &self.account_type {
+            match &installation.account {
+                Some(account) if &account.account_type == account_type => {}
+                _ => return false,
+            }
+        }
+        if let Some(suspended) = self.suspended {
+            if installation.suspended_at.is_some() != suspended {
+                return false;
+            }
+        }
+        if let Some((name, level)) = &self.permission {
+            if installation.permissions.get(name) < Some(*level) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// List the app's installations.
+///
+/// This authenticates with the app JWT and follows pagination links,
+/// so all installations are returned (up to
+/// [`ListOptions::limit`]), not just the first page.
+pub async fn list_installations(
+    params: &GithubAuthParams,
+    options: &ListOptions,
+) -> Result<Vec<Installation>, AuthError> {
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let mut stream = pin!(installations_stream(
+        params,
+        options.per_page,
+        InstallationFilter::default(),
+    ));
+    let mut installations = Vec::new();
+    while installations.len() < limit {
+        match stream.next().await {
+            Some(installation) => installations.push(installation?),
+            None => break,
+        }
+    }
+    Ok(installations)
+}
+
+/// Lazily iterate over the app's installations that match `filter`.
+///
+/// Pages of `per_page` installations (default and maximum 100) are
+/// only requested as the stream is consumed, so large apps do not
+/// need to hold every installation in memory. If a request fails, the
+/// error is yielded and the stream ends.
+pub fn installations_stream(
+    params: &GithubAuthParams,
+    per_page: Option<u8>,
+    filter: InstallationFilter,
+) -> impl Stream<Item = Result<Installation, AuthError>> {
+    let state = PageState {
+        params: params.clone(),
+        filter,
+        client: None,
+        next: Some(params.api_url(&format!(
+            "/app/installations?per_page={}",
+            per_page.unwrap_or(100)
+        ))),
+        buffer: VecDeque::new(),
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            while let Some(installation) = state.buffer.pop_front() {
+                if state.filter.matches(&installation) {
+                    return Some((Ok(installation), state));
+                }
+            }
+            let url = state.next.take()?;
+            if let Err(err) = state.fetch_page(&url).await {
+                return Some((Err(err), state));
+            }
+        }
+    })
+}
+
+struct PageState {
+    params: GithubAuthParams,
+    filter: InstallationFilter,
+    client: Option<reqwest::Client>,
+    next: Option<String>,
+    buffer: VecDeque<Installation>,
+}
+
+impl PageState {
+    async fn fetch_page(&mut self, url: &str) -> Result<(), AuthError> {
+        let client = match &self.client {
+            Some(client) => client,
+            None => self.client.insert(self.params.build_client()?),
+        };
+        // Sign a new JWT for each page, since the stream may be
+        // consumed slowly enough for an earlier JWT to expire.
+        let jwt = app_jwt(&self.params).await?;
+        let request = self.params.api_headers(client.get(url).bearer_auth(jwt));
+        let resp = check_status(send(&self.params, request).await?).await?;
+        self.next = next_page_url(resp.headers());
+        self.buffer.extend(resp.json::<Vec<Installation>>().await?);
+        Ok(())
+    }
+}
+
+/// List pending requests to install the app.
+///
+/// This authenticates with the app JWT and follows pagination links,
+/// like [`list_installations`]. It can be used to build a dashboard
+/// for approving installation requests.
+pub async fn list_installation_requests(
+    params: &GithubAuthParams,
+    options: &ListOptions,
+) -> Result<Vec<InstallationRequest>, AuthError> {
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let client = params.build_client()?;
+    let mut next = Some(params.api_url(&format!(
+        "/app/installation-requests?per_page={}",
+        options.per_page.unwrap_or(100)
+    )));
+    let mut requests = Vec::new();
+    while let Some(url) = next.take() {
+        if requests.len() >= limit {
+            break;
+        }
+        let jwt = app_jwt(params).await?;
+        let request = params.api_headers(client.get(url).bearer_auth(jwt));
+        let resp = check_status(send(params, request).await?).await?;
+        next = next_page_url(resp.headers());
+        requests.extend(resp.json::<Vec<InstallationRequest>>().await?);
+    }
+    requests.truncate(limit);
+    Ok(requests)
+}
+
+/// Get one of the app's installations by ID.
+pub(crate) async fn get_installation(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    installation_id: u64,
+) -> Result<Installation, AuthError> {
+    let jwt = app_jwt(params).await?;
+    let url =
+        params.api_url(&format!("/app/installations/{}", installation_id));
+    let request = params.api_headers(client.get(url).bearer_auth(jwt));
+    let resp = send(params, request).await?;
+    Ok(check_status(resp).await?.json().await?)
+}
+
+/// Get an installation from an API path such as
+/// `/orgs/{org}/installation`.
+pub(crate) async fn get_installation_at(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    path: &str,
+) -> Result<Installation, AuthError> {
+    let jwt = app_jwt(params).await?;
+    let request =
+        params.api_headers(client.get(params.api_url(path)).bearer_auth(jwt));
+    let resp = send(params, request).await?;
+    Ok(check_status(resp).await?.json().await?)
+}
+
+/// Get the URL of the next page from a `Link` header, if there is one.
+pub(crate) fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get(LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (url, rel) = part.split_once(';')?;
+        if rel.trim() == r#"rel="next""# {
+            let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+            Some(url.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_installation_filter() {
+        let installation = Installation {
+            id: 1,
+            account: Some(Account {
+                id: 2,
+                login: "mycoolorg".into(),
+                account_type: "Organization".into(),
+            }),
+            suspended_at: None,
+            permissions: Permissions {
+                checks: Some(PermissionLevel::Write),
+                ..Default::default()
+            },
+        };
+        assert!(InstallationFilter::default().matches(&installation));
+
+        let mut filter = InstallationFilter {
+            account_type: Some("Organization".into()),
+            suspended: Some(false),
+            permission: Some(("checks".into(), PermissionLevel::Read)),
+        };
+        assert!(filter.matches(&installation));
+
+        filter.permission = Some(("checks".into(), PermissionLevel::Admin));
+        assert!(!filter.matches(&installation));
+
+        filter.permission = None;
+        filter.account_type = Some("User".into());
+        assert!(!filter.matches(&installation));
+
+        filter.account_type = None;
+        filter.suspended = Some(true);
+        assert!(!filter.matches(&installation));
+    }
+
+    #[test]
+    fn test_installation_request_parse() {
+        let request: InstallationRequest = serde_json::from_str(
+            r#"{
+                "id": 1,
+                "node_id": "MDExOkludGVncmF0aW9uMQ==",
+                "account": {"id": 2, "login": "mycoolorg", "type": "Organization"},
+                "requester": {"id": 3, "login": "octocat", "type": "User"},
+                "created_at": "2022-07-08T16:18:44-04:00"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.account.login, "mycoolorg");
+        assert_eq!(request.requester.login, "octocat");
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(next_page_url(&headers), None);
+
+        headers.insert(
+            LINK,
+            r#"<https://api.github.com/app/installations?page=2>; rel="next", <https://api.github.com/app/installations?page=5>; rel="last""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://api.github.com/app/installations?page=2")
+        );
+
+        headers.insert(
+            LINK,
+            r#"<https://api.github.com/app/installations?page=4>; rel="prev""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(next_page_url(&headers), None);
+    }
+}
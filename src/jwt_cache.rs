@@ -0,0 +1,150 @@
+use crate::{sign_jwt, GithubAuthParams, DEFAULT_JWT_LIFETIME};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::EncodingKey;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Reuses the app JWT across installation token requests, shared by
+/// every set of parameters that holds a clone of it.
+///
+/// Without a cache, each token request signs a new JWT, and reads and
+/// parses the private key to do so. When minting tokens for many
+/// installations at once, that is an RSA signature per installation.
+/// With a cache set on
+/// [`GithubAuthParams`](crate::GithubAuthParams::jwt_cache), the
+/// private key is loaded once, and a JWT is reused until half of its
+/// [lifetime](crate::GithubAuthParams::jwt_lifetime) has passed, like
+/// [`AppToken`](crate::AppToken). JWTs from a
+/// [`JwtSigner`](crate::JwtSigner) are reused the same way.
+///
+/// A cache must only be shared by parameters for the same app and
+/// private key. If GitHub rejects a cached JWT, the cache is cleared,
+/// so a rotated key file is picked up on the next attempt.
+/// [`InstallationTokenStore`](crate::InstallationTokenStore) sets up
+/// a cache automatically.
+#[derive(Clone, Default)]
+pub struct JwtCache {
+    entry: Arc<Mutex<Option<Entry>>>,
+}
+
+struct Entry {
+    jwt: String,
+    pem: Option<Vec<u8>>,
+    key: Option<EncodingKey>,
+    renew_at: DateTime<Utc>,
+}
+
+/// A JWT from the cache, along with the PEM of the key that signed
+/// it, if known, for local verification.
+pub(crate) struct CachedJwt {
+    pub(crate) jwt: String,
+    pub(crate) pem: Option<Vec<u8>>,
+}
+
+impl JwtCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the cached JWT and private key.
+    pub fn clear(&self) {
+        *self.entry.lock().unwrap() = None;
+    }
+
+    /// Get the cached JWT, signing a new one with the cached key if it
+    /// is due for renewal. Returns `None` if nothing usable is cached.
+    pub(crate) fn get(&self, params: &GithubAuthParams) -> Option<CachedJwt> {
+        let mut entry = self.entry.lock().unwrap();
+        let cached = entry.as_mut()?;
+        if params.now() >= cached.renew_at {
+            let key = cached.key.as_ref()?;
+            cached.jwt = sign_jwt(params, key).ok()?;
+            cached.renew_at = renew_at(params);
+        }
+        Some(CachedJwt {
+            jwt: cached.jwt.clone(),
+            pem: cached.pem.clone(),
+        })
+    }
+
+    /// Store a newly signed JWT. `key` is the key that signed it, or
+    /// `None` if it came from a [`JwtSigner`](crate::JwtSigner).
+    pub(crate) fn insert(
+        &self,
+        params: &GithubAuthParams,
+        jwt: &CachedJwt,
+        key: Option<EncodingKey>,
+    ) {
+        *self.entry.lock().unwrap() = Some(Entry {
+            jwt: jwt.jwt.clone(),
+            pem: jwt.pem.clone(),
+            key,
+            renew_at: renew_at(params),
+        });
+    }
+}
+
+fn renew_at(params: &GithubAuthParams) -> DateTime<Utc> {
+    let lifetime = params.jwt_lifetime.unwrap_or(DEFAULT_JWT_LIFETIME);
+    params.now() + chrono::Duration::from_std(lifetime / 2).unwrap_or_default()
+}
+
+/// The JWT and key are not shown.
+impl fmt::Debug for JwtCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entry = self.entry.lock().unwrap();
+        f.debug_struct("JwtCache")
+            .field("renew_at", &entry.as_ref().map(|entry| entry.renew_at))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::signer::tests::TestSigner;
+    use crate::test_util::{MockClock, MockFailure, MockGithub};
+    use crate::InstallationAccessToken;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_jwt_cache() {
+        let github = MockGithub::start().await.unwrap();
+        let signer = Arc::new(TestSigner::new());
+        let cache = JwtCache::new();
+        let params = GithubAuthParams {
+            signer: Some(signer.clone()),
+            jwt_cache: Some(cache.clone()),
+            ..github.params()
+        };
+        for installation_id in 1..=3 {
+            InstallationAccessToken::new(GithubAuthParams {
+                installation_id,
+                ..params.clone()
+            })
+            .await
+            .unwrap();
+        }
+        assert_eq!(signer.calls.load(Ordering::SeqCst), 1);
+
+        // A rejected JWT is not reused.
+        github.fail_next_token_request(MockFailure::Status(401));
+        assert!(InstallationAccessToken::new(params).await.is_err());
+        assert!(cache.get(&github.params()).is_none());
+
+        // With a local key, the key is kept and the JWT is re-signed
+        // once it is due for renewal.
+        let clock = Arc::new(MockClock::new());
+        let params = GithubAuthParams {
+            clock: Some(clock.clone()),
+            jwt_cache: Some(cache.clone()),
+            ..github.params()
+        };
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        let first = cache.get(&params).unwrap().jwt;
+        assert_eq!(cache.get(&params).unwrap().jwt, first);
+        clock.advance(chrono::Duration::seconds(30));
+        assert_ne!(cache.get(&params).unwrap().jwt, first);
+    }
+}
@@ -0,0 +1,283 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::signature::RsaKeyPair;
+use std::borrow::Cow;
+use std::mem;
+
+const PEM_HEADER: &[u8] = b"-----BEGIN";
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+fn is_pem(key: &[u8]) -> bool {
+    contains(key, PEM_HEADER)
+}
+
+/// If `key` is an RSA private key in DER format, get the PEM label
+/// for it.
+fn der_label(key: &[u8]) -> Option<&'static str> {
+    // Both formats are an ASN.1 SEQUENCE.
+    if key.first() != Some(&0x30) {
+        return None;
+    }
+    if RsaKeyPair::from_pkcs8(key).is_ok() {
+        Some("PRIVATE KEY")
+    } else if RsaKeyPair::from_der(key).is_ok() {
+        Some("RSA PRIVATE KEY")
+    } else {
+        None
+    }
+}
+
+/// Wrap a DER key in PEM armor.
+fn der_to_pem(der: &[u8], label: &str) -> Vec<u8> {
+    let body = STANDARD.encode(der);
+    // Allocate enough up front that growing the PEM doesn't leave
+    // partial copies of the key behind.
+    let mut pem = String::with_capacity(
+        body.len() + body.len() / 64 + 2 * label.len() + 40,
+    );
+    pem += &format!("-----BEGIN {}-----\n", label);
+    for line in body.as_bytes().chunks(64) {
+        // Base64 output is ASCII.
+        pem += std::str::from_utf8(line).unwrap();
+        pem.push('\n');
+    }
+    pem += &format!("-----END {}-----\n", label);
+    wipe(body.into_bytes());
+    pem.into_bytes()
+}
+
+/// Fix up line endings that commonly get mangled when a PEM key is
+/// pasted into an environment variable or secret store.
+fn normalize_pem(pem: &[u8]) -> Cow<'_, [u8]> {
+    let mut pem = Cow::Borrowed(pem);
+    if contains(&pem, b"\\n") {
+        warn!(
+            "private key contains literal \\n sequences, \
+             treating them as newlines"
+        );
+        let mut fixed = Vec::with_capacity(pem.len());
+        let mut rest = &pem[..];
+        while !rest.is_empty() {
+            if rest.starts_with(b"\\n") {
+                fixed.push(b'\n');
+                rest = &rest[2..];
+            } else {
+                fixed.push(rest[0]);
+                rest = &rest[1..];
+            }
+        }
+        pem = Cow::Owned(fixed);
+    }
+    if pem.contains(&b'\r') {
+        warn!("private key has CRLF line endings, converting to LF");
+        let mut fixed = Vec::with_capacity(pem.len());
+        fixed.extend(pem.iter().copied().filter(|b| *b != b'\r'));
+        wipe_decoded(mem::replace(&mut pem, Cow::Owned(fixed)));
+    }
+    pem
+}
+
+/// Get a private key in PEM format.
+///
+/// Keys in DER format (PKCS#1 or PKCS#8), as handed out by some
+/// secret managers, are converted to PEM.
+///
+/// CI systems often require secrets to fit on a single line, so keys
+/// are commonly stored as base64-encoded PEM. If `key` has no PEM
+/// header but decodes to something that does, or to a DER key, the
+/// decoded key is used.
+///
+/// Literal `\n` sequences and CRLF line endings, which often appear
+/// when a key is pasted into an environment variable, are converted
+/// to plain newlines with a warning.
+///
+/// If the key is not recognized it is returned unchanged, and any
+/// parse error is left to the caller.
+pub(crate) fn decode_private_key(key: &[u8]) -> Cow<'_, [u8]> {
+    if is_pem(key) {
+        return normalize_pem(key);
+    }
+    if let Some(label) = der_label(key) {
+        debug!("converting DER private key to PEM");
+        return Cow::Owned(der_to_pem(key, label));
+    }
+    let mut compact = Vec::with_capacity(key.len());
+    compact.extend(key.iter().copied().filter(|b| !b.is_ascii_whitespace()));
+    let decoded = STANDARD.decode(&compact);
+    wipe(compact);
+    let Ok(decoded) = decoded else {
+        return Cow::Borrowed(key);
+    };
+    let pem = if is_pem(&decoded) {
+        info!("decoded base64-encoded private key");
+        Some(normalize_pem(&decoded).into_owned())
+    } else if let Some(label) = der_label(&decoded) {
+        info!("decoded base64-encoded DER private key");
+        Some(der_to_pem(&decoded, label))
+    } else {
+        None
+    };
+    wipe(decoded);
+    match pem {
+        Some(pem) => Cow::Owned(pem),
+        None => Cow::Borrowed(key),
+    }
+}
+
+/// Like [`decode_private_key`], but takes ownership of `key`, which
+/// is wiped if a decoded copy is returned instead.
+pub(crate) fn decode_owned_private_key(key: Vec<u8>) -> Vec<u8> {
+    let pem = match decode_private_key(&key) {
+        Cow::Owned(pem) => Some(pem),
+        Cow::Borrowed(_) => None,
+    };
+    match pem {
+        Some(pem) => {
+            wipe(key);
+            pem
+        }
+        None => key,
+    }
+}
+
+/// Parse a private key in any of the formats accepted by
+/// [`decode_private_key`].
+pub(crate) fn parse_private_key(
+    key: &[u8],
+) -> Result<jsonwebtoken::EncodingKey, jsonwebtoken::errors::Error> {
+    let pem = decode_private_key(key);
+    let result = jsonwebtoken::EncodingKey::from_rsa_pem(&pem);
+    wipe_decoded(pem);
+    result
+}
+
+/// Check that `key` is an RSA key that can sign JWTs, and get its size
+/// in bits. Unlike [`parse_private_key`], this parses the key
+/// structure, so it catches truncated keys and keys that are too
+/// small, which otherwise only fail when the first JWT is signed.
+pub(crate) fn rsa_key_bits(key: &[u8]) -> Result<usize, String> {
+    let pem = decode_private_key(key);
+    let mut body = Vec::with_capacity(pem.len());
+    body.extend(
+        pem.split(|b| *b == b'\n')
+            .filter(|line| !line.starts_with(b"-----"))
+            .flatten()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace()),
+    );
+    wipe_decoded(pem);
+    let der = STANDARD.decode(&body);
+    wipe(body);
+    let der = der.map_err(|err| err.to_string())?;
+    let bits = RsaKeyPair::from_pkcs8(&der)
+        .or_else(|_| RsaKeyPair::from_der(&der))
+        .map(|pair| pair.public_modulus_len() * 8)
+        .map_err(|err| err.to_string());
+    wipe(der);
+    bits
+}
+
+/// Overwrite `bytes` with zeros, including any spare capacity, before
+/// freeing them, so that secrets don't linger in freed memory. This is
+/// best effort: copies made elsewhere, for example by a reallocation
+/// or in a response buffer, are not cleared.
+pub(crate) fn wipe(mut bytes: Vec<u8>) {
+    zero(&mut bytes);
+}
+
+/// Overwrite `bytes` with zeros in place, including any spare
+/// capacity, keeping its length. See [`wipe`].
+pub(crate) fn zero(bytes: &mut Vec<u8>) {
+    let len = bytes.len();
+    // Growing to the capacity never reallocates.
+    bytes.resize(bytes.capacity(), 0);
+    bytes.fill(0);
+    // Keep the writes from being optimized away as dead stores.
+    std::hint::black_box(&*bytes);
+    bytes.truncate(len);
+}
+
+/// Wipe the output of [`decode_private_key`] if it is a copy rather
+/// than the caller's own key.
+pub(crate) fn wipe_decoded(pem: Cow<'_, [u8]>) {
+    if let Cow::Owned(pem) = pem {
+        wipe(pem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rsa_key_bits() {
+        let pem = include_bytes!("../tests/data/test_private_key.pem");
+        assert_eq!(rsa_key_bits(pem), Ok(2048));
+        let pkcs8 = include_bytes!("../tests/data/test_private_key_pkcs8.pem");
+        assert_eq!(rsa_key_bits(pkcs8), Ok(2048));
+        let small = include_bytes!("../tests/data/test_private_key_1024.pem");
+        assert!(rsa_key_bits(small).is_err());
+        assert!(rsa_key_bits(&pem[..pem.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_decode_private_key() {
+        let pem = include_bytes!("../tests/data/test_private_key.pem");
+        assert_eq!(decode_private_key(pem), &pem[..]);
+
+        let encoded = STANDARD.encode(pem);
+        assert_eq!(decode_private_key(encoded.as_bytes()), &pem[..]);
+        parse_private_key(encoded.as_bytes()).unwrap();
+
+        // Trailing newlines are common when the secret comes from a file.
+        let encoded = format!("{}\n", encoded);
+        assert_eq!(decode_private_key(encoded.as_bytes()), &pem[..]);
+
+        let escaped = String::from_utf8(pem.to_vec())
+            .unwrap()
+            .replace('\n', "\\n");
+        assert_eq!(decode_private_key(escaped.as_bytes()), &pem[..]);
+
+        let crlf = String::from_utf8(pem.to_vec())
+            .unwrap()
+            .replace('\n', "\r\n");
+        assert_eq!(decode_private_key(crlf.as_bytes()), &pem[..]);
+        parse_private_key(crlf.as_bytes()).unwrap();
+
+        // PKCS#8 PEM, and both kinds of DER.
+        let pkcs8 = include_bytes!("../tests/data/test_private_key_pkcs8.pem");
+        parse_private_key(pkcs8).unwrap();
+        for pem in [&pem[..], &pkcs8[..]] {
+            let der = pem_body(pem);
+            let converted = decode_private_key(&der);
+            assert_eq!(converted, pem);
+            parse_private_key(&der).unwrap();
+            let params = crate::GithubAuthParams {
+                private_key: der.clone(),
+                app_id: 1,
+                ..Default::default()
+            };
+            crate::sign_app_jwt(&params).unwrap();
+            let encoded = STANDARD.encode(&der);
+            assert_eq!(decode_private_key(encoded.as_bytes()), pem);
+        }
+
+        // Valid base64, but not a PEM key.
+        assert_eq!(decode_private_key(b"aGVsbG8="), &b"aGVsbG8="[..]);
+        assert!(parse_private_key(b"not a key").is_err());
+        assert!(parse_private_key(&[0x30, 0x03, 0x02, 0x01, 0x00]).is_err());
+    }
+
+    /// Decode the base64 body of a PEM file.
+    fn pem_body(pem: &[u8]) -> Vec<u8> {
+        let body: String = std::str::from_utf8(pem)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        STANDARD.decode(body).unwrap()
+    }
+}
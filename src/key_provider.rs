@@ -0,0 +1,94 @@
+use crate::AuthError;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`PrivateKeyProvider::private_key_async`].
+pub type PrivateKeyFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, AuthError>> + Send + 'a>>;
+
+/// Supplies the app's private key each time a JWT is signed, so that
+/// the key can be loaded lazily from a secret store such as Vault or
+/// AWS Secrets Manager, and rotated without restarting the process.
+///
+/// Set
+/// [`GithubAuthParams::private_key_provider`](crate::GithubAuthParams::private_key_provider)
+/// to use it instead
+/// of [`private_key`](crate::GithubAuthParams::private_key) and
+/// [`private_key_path`](crate::GithubAuthParams::private_key_path).
+/// The key may be in any of the formats accepted by `private_key`.
+/// It is requested every time a JWT is signed, so implementations
+/// should cache it if fetching it is expensive.
+///
+/// Implement [`private_key`](Self::private_key) for a provider that
+/// can answer without blocking for long, such as one reading a local
+/// file or an in-memory value updated by a background task. Implement
+/// [`private_key_async`](Self::private_key_async) for one that makes
+/// a network request. Async providers only work with async functions
+/// such as
+/// `InstallationAccessToken::new`;
+/// the synchronous [`sign_app_jwt`](crate::sign_app_jwt) and
+/// [`AppToken`](crate::AppToken) fail with
+/// [`AuthError::InvalidConfig`].
+pub trait PrivateKeyProvider: Send + Sync {
+    /// Get the current private key synchronously.
+    ///
+    /// The default implementation returns
+    /// [`AuthError::InvalidConfig`].
+    fn private_key(&self) -> Result<Vec<u8>, AuthError> {
+        Err(AuthError::InvalidConfig(
+            "the PrivateKeyProvider can only be used by async functions".into(),
+        ))
+    }
+
+    /// Get the current private key.
+    ///
+    /// The default implementation calls
+    /// [`private_key`](Self::private_key).
+    fn private_key_async(&self) -> PrivateKeyFuture<'_> {
+        let key = self.private_key();
+        Box::pin(async move { key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GithubAuthParams;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Async provider that counts how often the key is requested.
+    #[derive(Default)]
+    struct CountingProvider(AtomicUsize);
+
+    impl PrivateKeyProvider for CountingProvider {
+        fn private_key_async(&self) -> PrivateKeyFuture<'_> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Ok(include_bytes!("../tests/data/test_private_key.pem")
+                    .to_vec())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_key_provider() {
+        let provider = Arc::new(CountingProvider::default());
+        let params = GithubAuthParams {
+            app_id: 1234,
+            installation_id: 5678,
+            user_agent: "test".into(),
+            private_key_provider: Some(provider.clone()),
+            ..Default::default()
+        };
+        assert!(params.validate().is_ok());
+        crate::signer::app_jwt(&params).await.unwrap();
+        crate::signer::app_jwt(&params).await.unwrap();
+        assert_eq!(provider.0.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(
+            crate::sign_app_jwt(&params),
+            Err(AuthError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+//! Logging macros that forward to the `tracing` crate when the
+//! `tracing` feature is enabled, to the `log` crate when only the
+//! `log` feature is enabled, and compile to nothing otherwise.
+//!
+//! `instrument!` and `record!` add spans and span fields, and only do
+//! anything with the `tracing` feature.
+
+#![allow(unused_macros)]
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => { tracing::debug!($($arg)*) };
+}
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!($($arg)*) };
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! info {
+    ($($arg:tt)*) => { tracing::info!($($arg)*) };
+}
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! info {
+    ($($arg:tt)*) => { log::info!($($arg)*) };
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! warn {
+    ($($arg:tt)*) => { tracing::warn!($($arg)*) };
+}
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! warn {
+    ($($arg:tt)*) => { log::warn!($($arg)*) };
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+/// Run `future` in an info-level span named `name`, with the given
+/// fields. Fields listed without a value can be filled in later with
+/// `record!`.
+#[cfg(feature = "tracing")]
+macro_rules! instrument {
+    ($future:expr, $name:literal $(, $field:ident $(= $value:expr)?)*) => {
+        tracing::Instrument::instrument(
+            $future,
+            tracing::info_span!(
+                $name,
+                $($field = instrument!(@value $($value)?)),*
+            ),
+        )
+    };
+    (@value $value:expr) => { tracing::field::display(&$value) };
+    (@value) => { tracing::field::Empty };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! instrument {
+    ($future:expr, $name:literal $(, $field:ident $(= $value:expr)?)*) => {
+        $future
+    };
+}
+
+/// Set fields of the current span, which must have been declared
+/// when the span was created.
+#[cfg(feature = "tracing")]
+macro_rules! record {
+    ($($field:ident = $value:expr),*) => {{
+        let span = tracing::Span::current();
+        $(span.record(stringify!($field), tracing::field::display(&$value));)*
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! record {
+    ($($field:ident = $value:expr),*) => {{
+        $(let _ = &$value;)*
+    }};
+}
@@ -0,0 +1,373 @@
+use crate::{AuthError, MissingPermission};
+#[cfg(feature = "client")]
+use crate::{DeprecationNotice, RateLimit};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Credential lifecycle event passed to an [`AuthObserver`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AuthEvent<'a> {
+    /// An installation access token was fetched for the first time.
+    TokenMinted {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Expiration time of the new token.
+        expires_at: DateTime<Utc>,
+    },
+
+    /// An installation access token was replaced with a new one.
+    TokenRefreshed {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Expiration time of the new token.
+        expires_at: DateTime<Utc>,
+    },
+
+    /// Refreshing an installation access token failed, and the
+    /// current token, which is about to expire, is still being used
+    /// because of its
+    /// `stale_token_policy`.
+    /// Requests start failing once it expires, unless a later refresh
+    /// succeeds. This is sent after each such failure, along with
+    /// [`MintFailed`](Self::MintFailed).
+    TokenExpiring {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Expiration time of the current token.
+        expires_at: DateTime<Utc>,
+    },
+
+    /// An installation access token was revoked before it expired.
+    TokenRevoked {
+        /// GitHub application installation ID.
+        installation_id: u64,
+    },
+
+    /// A refreshed token has fewer permissions than the token it
+    /// replaced, for example because an organization admin changed
+    /// the installation's settings.
+    PermissionsReduced {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Permissions that were removed or downgraded. In each entry,
+        /// `required` is the level the previous token had and
+        /// `granted` is the level of the new token.
+        reduced: &'a [MissingPermission],
+    },
+
+    /// GitHub issued a token with a
+    /// [scope](crate::GithubAuthParams::scope) but granted less than
+    /// was requested: some permissions are lower or missing, or some
+    /// repositories are not accessible.
+    ScopeNotGranted {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Requested permissions that were not granted. In each entry,
+        /// `required` is the requested level and `granted` is the
+        /// level of the token.
+        missing_permissions: &'a [MissingPermission],
+        /// Requested repository names that the token can't access.
+        missing_repositories: &'a [String],
+        /// Requested repository IDs that the token can't access.
+        missing_repository_ids: &'a [u64],
+    },
+
+    /// GitHub responded to a token request with `Deprecation` or
+    /// `Sunset` headers, meaning the endpoint's behavior is scheduled
+    /// to change.
+    #[cfg(feature = "client")]
+    DeprecationNotice {
+        /// The deprecation details.
+        notice: &'a DeprecationNotice,
+    },
+
+    /// The remaining rate limit of an installation access token
+    /// dropped below
+    /// `rate_limit_threshold`.
+    /// This is sent once per rate limit window.
+    #[cfg(feature = "client")]
+    RateLimitLow {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// The rate limit status that crossed the threshold.
+        rate_limit: &'a RateLimit,
+    },
+
+    /// A token that was due to be refreshed was left for a later call
+    /// to
+    /// [`InstallationTokenStore::refresh_due`](crate::InstallationTokenStore::refresh_due),
+    /// because more tokens were due than it refreshes at once.
+    #[cfg(feature = "client")]
+    RefreshDeferred {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// Time at which the token became due to be refreshed.
+        next_refresh_at: DateTime<Utc>,
+    },
+
+    /// Fetching an installation access token failed.
+    MintFailed {
+        /// GitHub application installation ID.
+        installation_id: u64,
+        /// The cause of the failure.
+        error: &'a AuthError,
+    },
+}
+
+impl AuthEvent<'_> {
+    /// Name of the event in snake case, for example `token_minted`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TokenMinted { .. } => "token_minted",
+            Self::TokenRefreshed { .. } => "token_refreshed",
+            Self::TokenExpiring { .. } => "token_expiring",
+            Self::TokenRevoked { .. } => "token_revoked",
+            Self::PermissionsReduced { .. } => "permissions_reduced",
+            Self::ScopeNotGranted { .. } => "scope_not_granted",
+            #[cfg(feature = "client")]
+            Self::DeprecationNotice { .. } => "deprecation_notice",
+            #[cfg(feature = "client")]
+            Self::RateLimitLow { .. } => "rate_limit_low",
+            #[cfg(feature = "client")]
+            Self::RefreshDeferred { .. } => "refresh_deferred",
+            Self::MintFailed { .. } => "mint_failed",
+        }
+    }
+
+    /// Convert the event to a JSON object.
+    ///
+    /// The `event` field holds the [name](Self::name) of the event.
+    /// The other fields depend on the event, and use the same names
+    /// as the variant's fields. Times are RFC 3339 strings, errors
+    /// are formatted as strings, and a `retryable` field is added to
+    /// failures. Field names are stable across releases.
+    pub fn to_json(&self) -> Value {
+        let mut value = match self {
+            Self::TokenMinted {
+                installation_id,
+                expires_at,
+            }
+            | Self::TokenRefreshed {
+                installation_id,
+                expires_at,
+            }
+            | Self::TokenExpiring {
+                installation_id,
+                expires_at,
+            } => json!({
+                "installation_id": installation_id,
+                "expires_at": format_time(expires_at),
+            }),
+            Self::TokenRevoked { installation_id } => json!({
+                "installation_id": installation_id,
+            }),
+            Self::PermissionsReduced {
+                installation_id,
+                reduced,
+            } => json!({
+                "installation_id": installation_id,
+                "reduced": reduced
+                    .iter()
+                    .map(|perm| json!({
+                        "name": perm.name,
+                        "previous": perm.required,
+                        "current": perm.granted,
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            Self::ScopeNotGranted {
+                installation_id,
+                missing_permissions,
+                missing_repositories,
+                missing_repository_ids,
+            } => json!({
+                "installation_id": installation_id,
+                "missing_permissions": missing_permissions
+                    .iter()
+                    .map(|perm| json!({
+                        "name": perm.name,
+                        "requested": perm.required,
+                        "granted": perm.granted,
+                    }))
+                    .collect::<Vec<_>>(),
+                "missing_repositories": missing_repositories,
+                "missing_repository_ids": missing_repository_ids,
+            }),
+            #[cfg(feature = "client")]
+            Self::DeprecationNotice { notice } => json!({
+                "url": notice.url,
+                "deprecation": notice.deprecation,
+                "sunset": notice.sunset,
+                "link": notice.link,
+            }),
+            #[cfg(feature = "client")]
+            Self::RateLimitLow {
+                installation_id,
+                rate_limit,
+            } => json!({
+                "installation_id": installation_id,
+                "limit": rate_limit.limit,
+                "remaining": rate_limit.remaining,
+                "reset": format_time(&rate_limit.reset),
+                "resource": rate_limit.resource,
+            }),
+            #[cfg(feature = "client")]
+            Self::RefreshDeferred {
+                installation_id,
+                next_refresh_at,
+            } => json!({
+                "installation_id": installation_id,
+                "next_refresh_at": format_time(next_refresh_at),
+            }),
+            Self::MintFailed {
+                installation_id,
+                error,
+            } => json!({
+                "installation_id": installation_id,
+                "error": error.to_string(),
+                "retryable": error.is_retryable(),
+            }),
+        };
+        value["event"] = Value::from(self.name());
+        value
+    }
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Receives structured events about credential activity, for example
+/// to build an audit trail.
+///
+/// Observers are called synchronously from the code path that
+/// triggered the event, so they should not block.
+pub trait AuthObserver: Send + Sync {
+    /// Called once for each event.
+    fn on_event(&self, event: &AuthEvent);
+}
+
+/// An [`AuthObserver`] that writes each event as a line of JSON, for
+/// log pipelines that should not have to parse free-form messages.
+///
+/// Each line is the output of [`AuthEvent::to_json`] with an added
+/// `timestamp` field:
+///
+/// ```text
+/// {"event":"token_minted","expires_at":"2024-01-01T01:00:00Z","installation_id":5678,"timestamp":"2024-01-01T00:00:00Z"}
+/// ```
+///
+/// Write errors are ignored, since an observer has no way to report
+/// them.
+pub struct JsonLinesObserver {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesObserver {
+    /// Create an observer that writes to `writer`.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Create an observer that writes to stderr.
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl AuthObserver for JsonLinesObserver {
+    fn on_event(&self, event: &AuthEvent) {
+        let mut value = event.to_json();
+        value["timestamp"] = Value::from(format_time(&Utc::now()));
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writeln!(writer, "{}", value);
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::PermissionLevel;
+    use std::sync::Arc;
+
+    /// Observer that keeps the [JSON form](AuthEvent::to_json) of each
+    /// event, for checking which events a test triggered.
+    #[cfg(feature = "client")]
+    #[derive(Default)]
+    pub(crate) struct RecordingObserver(Mutex<Vec<Value>>);
+
+    #[cfg(feature = "client")]
+    impl AuthObserver for RecordingObserver {
+        fn on_event(&self, event: &AuthEvent) {
+            self.0.lock().unwrap().push(event.to_json());
+        }
+    }
+
+    #[cfg(feature = "client")]
+    impl RecordingObserver {
+        /// The recorded events with the given [name](AuthEvent::name).
+        pub(crate) fn events(&self, name: &str) -> Vec<Value> {
+            let events = self.0.lock().unwrap();
+            events
+                .iter()
+                .filter(|event| event["event"] == name)
+                .cloned()
+                .collect()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_observer() {
+        let buf = SharedBuf::default();
+        let observer = JsonLinesObserver::new(buf.clone());
+        let expires_at = DateTime::parse_from_rfc3339("2024-01-01T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        observer.on_event(&AuthEvent::TokenMinted {
+            installation_id: 5678,
+            expires_at,
+        });
+        observer.on_event(&AuthEvent::PermissionsReduced {
+            installation_id: 5678,
+            reduced: &[MissingPermission {
+                name: "contents".into(),
+                required: PermissionLevel::Write,
+                granted: None,
+            }],
+        });
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "token_minted");
+        assert_eq!(lines[0]["installation_id"], 5678);
+        assert_eq!(lines[0]["expires_at"], "2024-01-01T01:00:00Z");
+        assert!(lines[0]["timestamp"].is_string());
+        assert_eq!(
+            lines[1]["reduced"],
+            json!([{"name": "contents", "previous": "write", "current": null}])
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Access level of an app permission.
+///
+/// Levels are ordered, so that a granted `Write` permission satisfies
+/// a required `Read` permission.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionLevel {
+    /// Read-only access.
+    Read,
+    /// Read and write access.
+    Write,
+    /// Administrative access.
+    Admin,
+}
+
+impl fmt::Display for PermissionLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PermissionLevel::Read => "read",
+            PermissionLevel::Write => "write",
+            PermissionLevel::Admin => "admin",
+        })
+    }
+}
+
+/// Error returned when parsing an invalid [`PermissionLevel`].
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+#[error("invalid permission level {0:?}, expected read, write, or admin")]
+pub struct InvalidPermissionLevel(String);
+
+impl FromStr for PermissionLevel {
+    type Err = InvalidPermissionLevel;
+
+    fn from_str(s: &str) -> Result<PermissionLevel, InvalidPermissionLevel> {
+        match s {
+            "read" => Ok(PermissionLevel::Read),
+            "write" => Ok(PermissionLevel::Write),
+            "admin" => Ok(PermissionLevel::Admin),
+            _ => Err(InvalidPermissionLevel(s.to_string())),
+        }
+    }
+}
+
+/// A required permission that the installation has not been granted
+/// at a sufficient level.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingPermission {
+    /// Name of the permission, for example "contents".
+    pub name: String,
+
+    /// Level required by the application.
+    pub required: PermissionLevel,
+
+    /// Level granted to the installation, if any.
+    pub granted: Option<PermissionLevel>,
+}
+
+impl fmt::Display for MissingPermission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.granted {
+            Some(granted) => write!(
+                f,
+                "{}: requires {}, granted {}",
+                self.name, self.required, granted
+            ),
+            None => write!(
+                f,
+                "{}: requires {}, not granted",
+                self.name, self.required
+            ),
+        }
+    }
+}
+
+macro_rules! permissions {
+    ($($(#[$meta:meta])* $name:ident,)*) => {
+        /// Permissions of an app installation or token.
+        ///
+        /// Used both to describe the permissions an application
+        /// requires and to parse the permissions GitHub reports as
+        /// granted. Permissions without a dedicated field are kept in
+        /// [`other`](Self::other), keyed by their API name.
+        #[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+        pub struct Permissions {
+            $(
+                $(#[$meta])*
+                #[serde(default, skip_serializing_if = "Option::is_none")]
+                pub $name: Option<PermissionLevel>,
+            )*
+
+            /// Permissions without a dedicated field.
+            #[serde(flatten)]
+            pub other: BTreeMap<String, PermissionLevel>,
+        }
+
+        impl Permissions {
+            /// Get the level of a permission by its API name, for
+            /// example "contents".
+            pub fn get(&self, name: &str) -> Option<PermissionLevel> {
+                match name {
+                    $(stringify!($name) => self.$name,)*
+                    _ => self.other.get(name).copied(),
+                }
+            }
+
+            /// Whether `name` is the API name of a permission with a
+            /// dedicated field. Other names, such as a misspelling or
+            /// a permission added to GitHub after this release, go in
+            /// [`other`](Self::other).
+            pub fn is_known(name: &str) -> bool {
+                matches!(name, $(stringify!($name))|*)
+            }
+
+            /// Set the level of a permission by its API name, or clear
+            /// it if `level` is `None`.
+            pub fn set(&mut self, name: &str, level: Option<PermissionLevel>) {
+                match name {
+                    $(stringify!($name) => self.$name = level,)*
+                    _ => match level {
+                        Some(level) => {
+                            self.other.insert(name.to_string(), level);
+                        }
+                        None => {
+                            self.other.remove(name);
+                        }
+                    },
+                }
+            }
+
+            /// Iterate over the permissions that are set, as pairs of
+            /// API name and level.
+            pub fn iter(
+                &self,
+            ) -> impl Iterator<Item = (&str, PermissionLevel)> + '_ {
+                let known = [$((stringify!($name), self.$name),)*];
+                known
+                    .into_iter()
+                    .filter_map(|(name, level)| Some((name, level?)))
+                    .chain(
+                        self.other
+                            .iter()
+                            .map(|(name, level)| (name.as_str(), *level)),
+                    )
+            }
+
+            /// Whether no permissions are set.
+            pub fn is_empty(&self) -> bool {
+                self.iter().next().is_none()
+            }
+        }
+    };
+}
+
+permissions! {
+    /// Workflows, workflow runs, and artifacts.
+    actions,
+    /// Repository settings, teams, and collaborators.
+    administration,
+    /// Check runs and check suites.
+    checks,
+    /// Repository contents, commits, branches, and releases.
+    contents,
+    /// Deployments and deployment statuses.
+    deployments,
+    /// Repository environments.
+    environments,
+    /// Issues and related comments, assignees, labels, and milestones.
+    issues,
+    /// Repository metadata (always granted read access).
+    metadata,
+    /// Packages published to GitHub Packages.
+    packages,
+    /// GitHub Pages.
+    pages,
+    /// Pull requests and related comments, assignees, and labels.
+    pull_requests,
+    /// Repository webhooks.
+    repository_hooks,
+    /// Repository secrets.
+    secrets,
+    /// Code scanning alerts.
+    security_events,
+    /// Commit statuses.
+    statuses,
+    /// GitHub Actions workflow files.
+    workflows,
+    /// Organization members and teams.
+    members,
+    /// Organization settings.
+    organization_administration,
+}
+
+/// Compare required permissions against the permissions granted to
+/// an installation.
+#[cfg(feature = "client")]
+pub(crate) fn missing_permissions(
+    required: &Permissions,
+    granted: &Permissions,
+) -> Vec<MissingPermission> {
+    required
+        .iter()
+        .filter_map(|(name, required)| {
+            let granted = granted.get(name);
+            if granted.is_some_and(|granted| granted >= required) {
+                None
+            } else {
+                Some(MissingPermission {
+                    name: name.to_string(),
+                    required,
+                    granted,
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions_serde() {
+        let permissions: Permissions = serde_json::from_str(
+            r#"{"contents": "read", "checks": "write", "codespaces": "admin"}"#,
+        )
+        .unwrap();
+        assert_eq!(permissions.contents, Some(PermissionLevel::Read));
+        assert_eq!(permissions.get("checks"), Some(PermissionLevel::Write));
+        assert_eq!(permissions.get("codespaces"), Some(PermissionLevel::Admin));
+        assert_eq!(permissions.get("issues"), None);
+        assert_eq!(
+            permissions.iter().collect::<Vec<_>>(),
+            [
+                ("checks", PermissionLevel::Write),
+                ("contents", PermissionLevel::Read),
+                ("codespaces", PermissionLevel::Admin),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&permissions).unwrap(),
+            serde_json::json!({
+                "contents": "read",
+                "checks": "write",
+                "codespaces": "admin",
+            })
+        );
+        assert!(Permissions::default().is_empty());
+    }
+
+    #[test]
+    fn test_parse_permission_level() {
+        assert_eq!("write".parse(), Ok(PermissionLevel::Write));
+        assert_eq!(
+            "owner".parse::<PermissionLevel>(),
+            Err(InvalidPermissionLevel("owner".into()))
+        );
+    }
+
+    #[test]
+    fn test_is_known() {
+        assert!(Permissions::is_known("contents"));
+        assert!(Permissions::is_known("organization_administration"));
+        assert!(!Permissions::is_known("contnets"));
+        assert!(!Permissions::is_known("other"));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_missing_permissions() {
+        let required = Permissions {
+            checks: Some(PermissionLevel::Write),
+            contents: Some(PermissionLevel::Read),
+            issues: Some(PermissionLevel::Write),
+            ..Default::default()
+        };
+        let granted = Permissions {
+            checks: Some(PermissionLevel::Read),
+            contents: Some(PermissionLevel::Write),
+            ..Default::default()
+        };
+        assert_eq!(
+            missing_permissions(&required, &granted),
+            [
+                MissingPermission {
+                    name: "checks".into(),
+                    required: PermissionLevel::Write,
+                    granted: Some(PermissionLevel::Read),
+                },
+                MissingPermission {
+                    name: "issues".into(),
+                    required: PermissionLevel::Write,
+                    granted: None,
+                },
+            ]
+        );
+    }
+}
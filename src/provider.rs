@@ -0,0 +1,151 @@
+use crate::user_auth::UserAccessToken;
+use crate::{AuthError, InstallationAccessToken};
+use futures_util::lock::Mutex;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`AuthProvider::auth_header`].
+pub type AuthHeaderFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HeaderMap, AuthError>> + Send + 'a>>;
+
+/// Source of authentication headers for GitHub API requests.
+///
+/// The trait is object safe, so different kinds of credentials can be
+/// stored behind one `Arc<dyn AuthProvider>`:
+///
+/// ```no_run
+/// # use github_app_auth::{AuthProvider, GithubAuthParams,
+/// #     InstallationAccessToken, StaticToken};
+/// # use futures_util::lock::Mutex;
+/// # use std::sync::Arc;
+/// # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+/// let token = InstallationAccessToken::new(GithubAuthParams {
+///     user_agent: "my-cool-user-agent".into(),
+///     private_key: b"my private key".to_vec(),
+///     app_id: 1234,
+///     installation_id: 5678,
+///     ..Default::default()
+/// })
+/// .await?;
+/// let providers: Vec<Arc<dyn AuthProvider>> = vec![
+///     Arc::new(Mutex::new(token)),
+///     Arc::new(StaticToken::new("ghp_personal_access_token")),
+/// ];
+/// for provider in &providers {
+///     let header = provider.auth_header().await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// An [`InstallationAccessToken`] needs `&mut self` to refresh, so it
+/// implements the trait when wrapped in a
+/// `futures_util::lock::Mutex`, as does a [`UserAccessToken`]. Concurrent callers wait for a single
+/// refresh rather than each starting their own.
+/// [`SharedInstallationAccessToken`](crate::SharedInstallationAccessToken)
+/// also implements it, without serializing callers while the token
+/// is fresh.
+pub trait AuthProvider: Send + Sync {
+    /// Get the headers to authenticate a request, refreshing the
+    /// credential first if needed.
+    fn auth_header(&self) -> AuthHeaderFuture<'_>;
+}
+
+impl AuthProvider for Mutex<InstallationAccessToken> {
+    fn auth_header(&self) -> AuthHeaderFuture<'_> {
+        Box::pin(async move { self.lock().await.header().await })
+    }
+}
+
+impl AuthProvider for Mutex<UserAccessToken> {
+    fn auth_header(&self) -> AuthHeaderFuture<'_> {
+        Box::pin(async move { self.lock().await.header().await })
+    }
+}
+
+/// A token that never needs refreshing, such as a personal access
+/// token, the `GITHUB_TOKEN` of a GitHub Actions workflow, or a user
+/// access token that doesn't expire.
+#[derive(Clone)]
+pub struct StaticToken {
+    token: String,
+}
+
+impl StaticToken {
+    /// Create a provider that always sends `token`.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+
+    /// Create a provider from the token in the environment variable
+    /// `name`, for example a personal access token passed to a job.
+    pub fn from_env(name: &str) -> Result<Self, AuthError> {
+        match env::var(name) {
+            Ok(token) if !token.is_empty() => Ok(Self::new(token)),
+            _ => Err(AuthError::MissingEnvVar(name.into())),
+        }
+    }
+
+    /// Create a provider from `GITHUB_TOKEN`, the token that GitHub
+    /// Actions provides to workflows that pass it to the step's
+    /// environment.
+    pub fn github_actions() -> Result<Self, AuthError> {
+        Self::from_env("GITHUB_TOKEN")
+    }
+}
+
+impl fmt::Debug for StaticToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticToken")
+            .field("token", &"[redacted]")
+            .finish()
+    }
+}
+
+impl AuthProvider for StaticToken {
+    fn auth_header(&self) -> AuthHeaderFuture<'_> {
+        Box::pin(async move {
+            let mut value =
+                HeaderValue::try_from(format!("token {}", self.token))?;
+            value.set_sensitive(true);
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value);
+            Ok(headers)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_dyn_provider() {
+        let providers: Vec<Arc<dyn AuthProvider>> = vec![
+            Arc::new(Mutex::new(InstallationAccessToken::for_tests(
+                "ghs_a",
+                Utc::now() + Duration::hours(1),
+            ))),
+            Arc::new(StaticToken::new("ghp_b")),
+        ];
+        let mut values = Vec::new();
+        for provider in &providers {
+            let headers = provider.auth_header().await.unwrap();
+            values.push(headers[AUTHORIZATION].to_str().unwrap().to_string());
+        }
+        assert_eq!(values, ["token ghs_a", "token ghp_b"]);
+        assert!(!format!("{:?}", StaticToken::new("ghp_b")).contains("ghp_b"));
+
+        assert!(matches!(
+            StaticToken::from_env("GITHUB_APP_AUTH_TEST_UNSET_TOKEN"),
+            Err(AuthError::MissingEnvVar(_))
+        ));
+    }
+}
@@ -0,0 +1,256 @@
+use crate::token::is_unavailable;
+use crate::AuthError;
+use futures_util::future::{self, Either};
+use futures_util::lock::Mutex as AsyncMutex;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Holds token requests while GitHub is unavailable, instead of
+/// failing every caller at once.
+///
+/// Set the same queue on the
+/// [`GithubAuthParams`](crate::GithubAuthParams::outage_queue) of every
+/// token that should share it. When a token request fails because the
+/// API is down (a connection failure, timeout, or 5xx response), the
+/// queue enters outage mode. From then on, callers that need a token
+/// wait in the queue and the requests are retried one at a time, with
+/// exponential backoff between failures. Once a request succeeds the
+/// outage is over and the waiting callers are released to fetch their
+/// tokens.
+///
+/// The queue is bounded in two ways: a caller that would make more
+/// than `capacity` waiters fails immediately with
+/// [`AuthError::OutageQueueFull`], and a caller that has waited
+/// `max_wait` gets [`AuthError::ServiceUnavailable`] (or
+/// [`AuthError::DeadlineExceeded`] if its own deadline passes first).
+///
+/// Waiting uses Tokio timers, so the runtime must have time enabled.
+#[derive(Clone, Debug)]
+pub struct OutageQueue {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    max_wait: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    // Held by the one caller that is retrying during an outage.
+    probe: AsyncMutex<()>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    outage: bool,
+    backoff: Duration,
+    waiting: usize,
+}
+
+impl OutageQueue {
+    /// Create a queue that holds up to `capacity` callers, each for at
+    /// most `max_wait`.
+    pub fn new(capacity: usize, max_wait: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                capacity,
+                max_wait,
+                initial_backoff: DEFAULT_INITIAL_BACKOFF,
+                max_backoff: DEFAULT_MAX_BACKOFF,
+                probe: AsyncMutex::new(()),
+                state: Mutex::new(State {
+                    outage: false,
+                    backoff: DEFAULT_INITIAL_BACKOFF,
+                    waiting: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Set the delay before the first retry during an outage, and the
+    /// limit that it doubles up to after each failure. Defaults to one
+    /// second and one minute.
+    ///
+    /// This must be called before the queue is cloned.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        let inner = Arc::get_mut(&mut self.inner)
+            .expect("backoff must be set before the queue is cloned");
+        inner.initial_backoff = initial;
+        inner.max_backoff = max;
+        inner.state.get_mut().unwrap().backoff = initial;
+        self
+    }
+
+    /// Whether the last token request failed because GitHub was
+    /// unavailable.
+    pub fn is_outage(&self) -> bool {
+        self.state().outage
+    }
+
+    /// Number of callers waiting for GitHub to recover.
+    pub fn waiting(&self) -> usize {
+        self.state().waiting
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner.state.lock().unwrap()
+    }
+
+    fn record(&self, result: &Result<impl Sized, AuthError>) {
+        let mut state = self.state();
+        match result {
+            Err(err) if is_unavailable(err) => {
+                if state.outage {
+                    state.backoff =
+                        (state.backoff * 2).min(self.inner.max_backoff);
+                } else {
+                    warn!("GitHub is unavailable, queueing token requests");
+                    state.outage = true;
+                }
+            }
+            // Any response that isn't an outage means the API is
+            // answering again.
+            _ => {
+                if state.outage {
+                    info!("GitHub is available again");
+                }
+                state.outage = false;
+                state.backoff = self.inner.initial_backoff;
+            }
+        }
+    }
+
+    /// Run `attempt`, queueing and retrying it as described above if
+    /// GitHub is unavailable.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        deadline: Option<Instant>,
+        mut attempt: F,
+    ) -> Result<T, AuthError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AuthError>>,
+    {
+        if !self.is_outage() {
+            let result = attempt().await;
+            self.record(&result);
+            match result {
+                Err(err) if is_unavailable(&err) => {}
+                result => return result,
+            }
+        }
+
+        let _slot = Slot::enter(self)?;
+        let give_up = Instant::now() + self.inner.max_wait;
+        let (give_up, on_timeout): (_, fn(Duration) -> AuthError) =
+            match deadline {
+                Some(deadline) if deadline < give_up => {
+                    (deadline, |_| AuthError::DeadlineExceeded)
+                }
+                _ => (give_up, |backoff| AuthError::ServiceUnavailable {
+                    message: "still unavailable after waiting in the \
+                              outage queue"
+                        .into(),
+                    retry_after: Some(backoff),
+                }),
+            };
+
+        loop {
+            let lock = pin!(self.inner.probe.lock());
+            let timeout = pin!(tokio::time::sleep_until(give_up.into()));
+            let probe = match future::select(lock, timeout).await {
+                Either::Left((probe, _)) => probe,
+                Either::Right(_) => {
+                    return Err(on_timeout(self.state().backoff));
+                }
+            };
+            if !self.is_outage() {
+                // Another caller saw GitHub recover.
+                drop(probe);
+                let result = attempt().await;
+                self.record(&result);
+                return result;
+            }
+            let backoff = self.state().backoff;
+            if Instant::now() + backoff >= give_up {
+                return Err(on_timeout(backoff));
+            }
+            tokio::time::sleep(backoff).await;
+            let result = attempt().await;
+            self.record(&result);
+            match result {
+                Err(err) if is_unavailable(&err) => {
+                    debug!("still unavailable: {}", err);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A place in the queue, released on drop.
+struct Slot<'a>(&'a OutageQueue);
+
+impl<'a> Slot<'a> {
+    fn enter(queue: &'a OutageQueue) -> Result<Self, AuthError> {
+        let mut state = queue.state();
+        if state.waiting >= queue.inner.capacity {
+            return Err(AuthError::OutageQueueFull);
+        }
+        state.waiting += 1;
+        Ok(Slot(queue))
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.state().waiting -= 1;
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockFailure, MockGithub};
+    use crate::{GithubAuthParams, InstallationAccessToken};
+
+    #[tokio::test]
+    async fn test_outage_queue() {
+        let github = MockGithub::start().await.unwrap();
+        let queue = OutageQueue::new(10, Duration::from_secs(5))
+            .backoff(Duration::from_millis(10), Duration::from_millis(50));
+        let params = GithubAuthParams {
+            outage_queue: Some(queue.clone()),
+            ..github.params()
+        };
+
+        github.fail_next_token_request(MockFailure::Status(503));
+        github.fail_next_token_request(MockFailure::Status(502));
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        assert!(!queue.is_outage());
+        assert_eq!(queue.waiting(), 0);
+
+        // Errors that aren't outages are returned straight away.
+        github.fail_next_token_request(MockFailure::Status(401));
+        assert!(InstallationAccessToken::new(params).await.is_err());
+        assert!(!queue.is_outage());
+
+        let queue = OutageQueue::new(0, Duration::from_secs(5));
+        let params = GithubAuthParams {
+            outage_queue: Some(queue.clone()),
+            ..github.params()
+        };
+        github.fail_next_token_request(MockFailure::Status(503));
+        assert!(matches!(
+            InstallationAccessToken::new(params).await,
+            Err(AuthError::OutageQueueFull)
+        ));
+        assert!(queue.is_outage());
+    }
+}
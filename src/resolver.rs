@@ -0,0 +1,119 @@
+use crate::installations::get_installation_at;
+use crate::{AuthError, GithubAuthParams, Installation};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default for [`InstallationResolver::negative_ttl`].
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// Finds the app's installation on a repository or organization,
+/// for example to pick the installation to use for a webhook.
+///
+/// Lookups that find no installation are remembered for
+/// [`negative_ttl`](Self::negative_ttl), and repeated lookups during
+/// that time fail with [`AuthError::InstallationNotFound`] without a
+/// request. This way, a burst of webhooks for repositories that the
+/// app was removed from does not turn into a burst of API calls.
+/// Successful lookups are not cached, since the installation ID is
+/// normally used once to create a long-lived
+/// [`InstallationAccessToken`](crate::InstallationAccessToken).
+pub struct InstallationResolver {
+    /// How long to remember that an installation was not found.
+    /// Defaults to one minute. Set to zero to disable the cache.
+    pub negative_ttl: Duration,
+
+    client: reqwest::Client,
+    params: GithubAuthParams,
+    not_found: Mutex<HashMap<String, Instant>>,
+}
+
+impl InstallationResolver {
+    /// Create a resolver that authenticates with the app JWT from
+    /// `params`. The installation ID in `params` is not used.
+    pub fn new(params: GithubAuthParams) -> Result<Self, AuthError> {
+        Ok(Self {
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            client: params.build_client()?,
+            params,
+            not_found: Mutex::default(),
+        })
+    }
+
+    /// Get the installation for a repository, given as "owner/name".
+    pub async fn repo_installation(
+        &self,
+        repo: &str,
+    ) -> Result<Installation, AuthError> {
+        self.lookup(repo, &format!("/repos/{}/installation", repo))
+            .await
+    }
+
+    /// Get the installation for an organization.
+    pub async fn org_installation(
+        &self,
+        org: &str,
+    ) -> Result<Installation, AuthError> {
+        self.lookup(org, &format!("/orgs/{}/installation", org))
+            .await
+    }
+
+    async fn lookup(
+        &self,
+        name: &str,
+        path: &str,
+    ) -> Result<Installation, AuthError> {
+        // Account and repository names are case insensitive.
+        let key = path.to_ascii_lowercase();
+        if let Some(until) = self.not_found.lock().unwrap().get(&key) {
+            if Instant::now() < *until {
+                debug!("installation for {} not found (cached)", name);
+                return Err(AuthError::InstallationNotFound(name.to_string()));
+            }
+        }
+
+        match get_installation_at(&self.client, &self.params, path).await {
+            Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                let now = Instant::now();
+                let mut not_found = self.not_found.lock().unwrap();
+                not_found.retain(|_, until| *until > now);
+                not_found.insert(key, now + self.negative_ttl);
+                Err(AuthError::InstallationNotFound(name.to_string()))
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+
+    #[tokio::test]
+    async fn test_negative_cache() {
+        let github = MockGithub::start().await.unwrap();
+        let resolver = InstallationResolver::new(github.params()).unwrap();
+
+        let installation =
+            resolver.repo_installation("mock-org/hello").await.unwrap();
+        assert_eq!(installation.id, 2);
+        resolver.org_installation("mock-org").await.unwrap();
+        assert_eq!(github.installation_lookups(), 2);
+
+        for _ in 0..3 {
+            let err = resolver.repo_installation("other/hello").await;
+            assert!(matches!(err, Err(AuthError::InstallationNotFound(_))));
+        }
+        let err = resolver.repo_installation("OTHER/hello").await;
+        assert!(matches!(err, Err(AuthError::InstallationNotFound(_))));
+        assert_eq!(github.installation_lookups(), 3);
+
+        let mut resolver = resolver;
+        resolver.negative_ttl = Duration::ZERO;
+        assert!(resolver.org_installation("other").await.is_err());
+        assert!(resolver.org_installation("other").await.is_err());
+        assert_eq!(github.installation_lookups(), 5);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::api_error::error_from_response;
+use crate::har::{HarRequest, HarResponse};
+use crate::{AuthError, GithubAuthParams};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::header::{
+    HeaderMap, AUTHORIZATION, COOKIE, DATE, LINK, PROXY_AUTHORIZATION,
+    SET_COOKIE,
+};
+use reqwest::{RequestBuilder, Response, ResponseBuilderExt};
+use std::fmt;
+use std::time::Instant;
+
+/// Headers whose values are replaced with `[redacted]` in debug logs.
+pub(crate) const SENSITIVE_HEADERS: &[reqwest::header::HeaderName] =
+    &[AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+
+/// Advance warning that an API endpoint is deprecated, taken from the
+/// `Deprecation` and `Sunset` response headers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeprecationNotice {
+    /// URL of the request that returned the headers.
+    pub url: String,
+
+    /// Value of the `Deprecation` header: when the endpoint was or
+    /// will be deprecated, or `true`.
+    pub deprecation: Option<String>,
+
+    /// Value of the `Sunset` header: when the endpoint is expected to
+    /// stop working.
+    pub sunset: Option<String>,
+
+    /// Value of the `Link` header, which may point to documentation
+    /// about the change.
+    pub link: Option<String>,
+}
+
+impl DeprecationNotice {
+    /// Check a response for deprecation headers.
+    ///
+    /// Requests made by this crate are checked automatically, and a
+    /// warning is logged. This can be used to check responses to
+    /// requests sent with the token's request helpers, such as
+    /// [`InstallationAccessToken::get`](crate::InstallationAccessToken::get).
+    pub fn from_response(resp: &Response) -> Option<DeprecationNotice> {
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let deprecation = header("deprecation");
+        let sunset = header("sunset");
+        if deprecation.is_none() && sunset.is_none() {
+            return None;
+        }
+        Some(DeprecationNotice {
+            url: resp.url().to_string(),
+            deprecation,
+            sunset,
+            link: header(LINK.as_str()),
+        })
+    }
+}
+
+impl fmt::Display for DeprecationNotice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is deprecated", self.url)?;
+        if let Some(deprecation) = &self.deprecation {
+            write!(f, " (deprecation: {})", deprecation)?;
+        }
+        if let Some(sunset) = &self.sunset {
+            write!(f, " (sunset: {})", sunset)?;
+        }
+        if let Some(link) = &self.link {
+            write!(f, ", see {}", link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rate limit status, taken from the `X-RateLimit-*` response
+/// headers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Maximum number of requests allowed in the current window.
+    pub limit: u64,
+
+    /// Number of requests remaining in the current window.
+    pub remaining: u64,
+
+    /// Time at which the current window resets.
+    pub reset: DateTime<Utc>,
+
+    /// The rate limit resource the request counted against, for
+    /// example "core" or "search".
+    pub resource: Option<String>,
+}
+
+impl RateLimit {
+    /// Read the rate limit headers of a response. Returns `None` if
+    /// they are missing or invalid.
+    pub fn from_response(resp: &Response) -> Option<RateLimit> {
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let reset: i64 = header("x-ratelimit-reset")?.parse().ok()?;
+        Some(RateLimit {
+            limit: header("x-ratelimit-limit")?.parse().ok()?,
+            remaining: header("x-ratelimit-remaining")?.parse().ok()?,
+            reset: Utc.timestamp_opt(reset, 0).single()?,
+            resource: header("x-ratelimit-resource").map(str::to_string),
+        })
+    }
+
+    /// Whether the remaining requests are below `threshold`.
+    pub fn is_below(&self, threshold: RateLimitThreshold) -> bool {
+        match threshold {
+            RateLimitThreshold::Remaining(remaining) => {
+                self.remaining < remaining
+            }
+            RateLimitThreshold::Percent(percent) => {
+                self.remaining * 100 < self.limit * u64::from(percent)
+            }
+        }
+    }
+}
+
+/// Level below which a [`RateLimit`] is considered low. See
+/// [`InstallationAccessToken::rate_limit_threshold`](crate::InstallationAccessToken::rate_limit_threshold).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RateLimitThreshold {
+    /// A number of remaining requests.
+    Remaining(u64),
+
+    /// A percentage (0 to 100) of the limit.
+    Percent(u8),
+}
+
+/// Check that a response was successful.
+///
+/// Unlike [`Response::error_for_status`], this looks at the body of
+/// 403 responses, so that failures caused by organization policies
+/// can be reported with dedicated error variants.
+///
+/// 503 responses are reported as [`AuthError::ServiceUnavailable`],
+/// and rate limit responses as [`AuthError::RateLimited`].
+///
+/// A warning is logged if the response has deprecation headers.
+/// Send a request, logging the exchange if
+/// [`debug_http`](GithubAuthParams::debug_http) is set and recording
+/// it if [`har_recorder`](GithubAuthParams::har_recorder) is set. The
+/// [`clock_skew`](GithubAuthParams::clock_skew) is updated from the
+/// response's `Date` header.
+pub(crate) async fn send(
+    params: &GithubAuthParams,
+    request: RequestBuilder,
+) -> Result<Response, AuthError> {
+    let resp = send_and_record(params, request).await.map_err(send_error)?;
+    if let Some(skew) = &params.clock_skew {
+        if let Some(date) = resp.headers().get(DATE) {
+            skew.record_date_header(date.to_str().unwrap_or_default());
+        }
+    }
+    Ok(resp)
+}
+
+/// Convert an error from sending a request, telling timeouts apart.
+pub(crate) fn send_error(err: reqwest::Error) -> AuthError {
+    if err.is_timeout() {
+        AuthError::Timeout(err)
+    } else {
+        AuthError::ReqwestError(err)
+    }
+}
+
+async fn send_and_record(
+    params: &GithubAuthParams,
+    request: RequestBuilder,
+) -> Result<Response, reqwest::Error> {
+    if !params.debug_http && params.har_recorder.is_none() {
+        return request.send().await;
+    }
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let url = request.url().clone();
+    let headers = request.headers().clone();
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(<[u8]>::to_vec);
+    if params.debug_http {
+        debug!("> {} {} {}", method, url, format_headers(&headers));
+    }
+    let started = Utc::now();
+    let start = Instant::now();
+    let result = client.execute(request).await;
+    let elapsed = start.elapsed();
+    if params.debug_http {
+        match &result {
+            Ok(resp) => debug!(
+                "< {} {} {} in {:?} {}",
+                method,
+                url,
+                resp.status(),
+                elapsed,
+                format_headers(resp.headers())
+            ),
+            Err(err) => {
+                debug!("< {} {} failed in {:?}: {}", method, url, elapsed, err)
+            }
+        }
+    }
+
+    let recorder = match &params.har_recorder {
+        Some(recorder) => recorder,
+        None => return result,
+    };
+    let har_request = HarRequest {
+        method: &method,
+        url: &url,
+        headers: &headers,
+        body: body.as_deref(),
+    };
+    let resp = match result {
+        Ok(resp) => resp,
+        Err(err) => {
+            recorder.record(started, elapsed, har_request, None);
+            return Err(err);
+        }
+    };
+    // The body has to be read to record it, so build a new response
+    // around it for the caller.
+    let status = resp.status();
+    let resp_headers = resp.headers().clone();
+    let resp_url = resp.url().clone();
+    let resp_body = resp.bytes().await?;
+    recorder.record(
+        started,
+        elapsed,
+        har_request,
+        Some(HarResponse {
+            status,
+            headers: &resp_headers,
+            body: &resp_body,
+        }),
+    );
+    let mut builder = http::Response::builder().status(status).url(resp_url);
+    if let Some(builder_headers) = builder.headers_mut() {
+        *builder_headers = resp_headers;
+    }
+    Ok(Response::from(builder.body(resp_body).unwrap()))
+}
+
+/// Format headers for logging, redacting credentials.
+fn format_headers(headers: &HeaderMap) -> String {
+    let headers: Vec<_> = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(name) {
+                "[redacted]"
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect();
+    format!("[{}]", headers.join(", "))
+}
+
+/// Turn an error response into an [`AuthError`], using the JSON error
+/// body that GitHub sends to say what went wrong.
+pub(crate) async fn check_status(
+    resp: Response,
+) -> Result<Response, AuthError> {
+    if let Some(notice) = DeprecationNotice::from_response(&resp) {
+        warn!("{}", notice);
+    }
+    let status = resp.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(resp);
+    }
+    let headers = resp.headers().clone();
+    let text = resp.text().await.unwrap_or_default();
+    Err(error_from_response(status, &headers, &text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "token secret".parse().unwrap());
+        headers.insert("accept", "application/json".parse().unwrap());
+        let formatted = format_headers(&headers);
+        assert!(!formatted.contains("secret"));
+        assert_eq!(
+            formatted,
+            "[authorization: [redacted], accept: application/json]"
+        );
+    }
+
+    #[test]
+    fn test_deprecation_notice() {
+        let resp = Response::from(http::Response::new(""));
+        assert_eq!(DeprecationNotice::from_response(&resp), None);
+
+        let resp = Response::from(
+            http::Response::builder()
+                .header("Deprecation", "@1688169599")
+                .header("Sunset", "Sun, 30 Jun 2024 23:59:59 GMT")
+                .body("")
+                .unwrap(),
+        );
+        let notice = DeprecationNotice::from_response(&resp).unwrap();
+        assert_eq!(notice.deprecation.as_deref(), Some("@1688169599"));
+        assert_eq!(
+            notice.sunset.as_deref(),
+            Some("Sun, 30 Jun 2024 23:59:59 GMT")
+        );
+        assert_eq!(notice.link, None);
+    }
+
+    #[tokio::test]
+    async fn test_check_status() {
+        let resp = |status: u16, body: &'static str| {
+            Response::from(
+                http::Response::builder().status(status).body(body).unwrap(),
+            )
+        };
+        assert!(check_status(resp(200, "")).await.is_ok());
+
+        let body = r#"{
+            "message": "A JSON web token could not be decoded",
+            "documentation_url": "https://docs.github.com/rest"
+            }"#;
+        let err = check_status(resp(401, body)).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "GitHub API error (401 Unauthorized): A JSON web token could \
+             not be decoded (see https://docs.github.com/rest)"
+        );
+
+        let err = check_status(resp(404, "")).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AuthError::GithubApi { message, documentation_url: None, .. }
+                if message == "Not Found"
+        ));
+
+        let limited = Response::from(
+            http::Response::builder()
+                .status(403)
+                .header("x-ratelimit-remaining", "0")
+                .header("x-ratelimit-reset", "1700000000")
+                .body(r#"{"message": "API rate limit exceeded"}"#)
+                .unwrap(),
+        );
+        let err = check_status(limited).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AuthError::RateLimited { reset_at: Some(reset_at), .. }
+                if reset_at.timestamp() == 1700000000
+        ));
+        assert!(err.is_rate_limited());
+        assert_eq!(err.retry_after(), Some(Duration::ZERO));
+
+        let body = r#"{"message": "You have exceeded a secondary rate limit"}"#;
+        let err = check_status(resp(403, body)).await.unwrap_err();
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(60)));
+    }
+}
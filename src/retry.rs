@@ -0,0 +1,262 @@
+use crate::{AuthError, RetryBudget};
+use http::StatusCode;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+const DEFAULT_RETRYABLE_STATUSES: [StatusCode; 4] = [
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// How a failed installation token request is retried before the
+/// error is returned to the caller.
+///
+/// Set it on [`GithubAuthParams`](crate::GithubAuthParams::retry_policy)
+/// to enable retries. The default policy makes up to three attempts,
+/// waiting 500 milliseconds before the first retry and doubling the
+/// delay after each failure up to ten seconds. Connection failures,
+/// timeouts, and responses with status 500, 502, 503, or 504 are
+/// retried; anything else, such as a rejected JWT, is returned
+/// immediately.
+///
+/// With jitter enabled (the default), each delay is a random duration
+/// between half and all of the backoff, so that many tokens failing
+/// together don't retry in lockstep. A `Retry-After` header is
+/// honored if it is longer than the backoff, as is the rate limit
+/// reset time of a [`RateLimited`](AuthError::RateLimited) error; if
+/// either is longer than the maximum backoff, the error is returned
+/// instead of waiting. Rate limit responses are only retried if their
+/// status is in the retryable statuses.
+///
+/// Retries stop early if the caller's deadline (see
+/// [`header_with_deadline`]) would pass while waiting, or if a
+/// [`RetryBudget`] is set and has run out. Each retry takes one from
+/// the budget.
+///
+/// Waiting uses Tokio timers, so the runtime must have time enabled.
+///
+/// [`header_with_deadline`]: crate::InstallationAccessToken::header_with_deadline
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            jitter: true,
+            retryable_statuses: DEFAULT_RETRYABLE_STATUSES.to_vec(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Make up to `max_attempts` attempts in total, including the
+    /// first, with the default backoff and retryable statuses.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Self::default()
+        }
+    }
+
+    /// A policy that never retries. This is the same as leaving
+    /// [`retry_policy`](crate::GithubAuthParams::retry_policy) unset.
+    pub fn disabled() -> Self {
+        Self::new(1)
+    }
+
+    /// Set the delay before the first retry, and the limit that it
+    /// doubles up to after each failure.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Enable or disable random jitter in the delays.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Replace the HTTP status codes that are retried. Connection
+    /// failures and timeouts are always retried.
+    pub fn retryable_statuses(
+        mut self,
+        statuses: impl IntoIterator<Item = StatusCode>,
+    ) -> Self {
+        self.retryable_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    fn is_retryable(&self, err: &AuthError) -> bool {
+        err.is_connection_error()
+            || err
+                .status()
+                .is_some_and(|status| self.retryable_statuses.contains(&status))
+    }
+
+    /// How long to wait before retrying after `err`, or `None` to give
+    /// up.
+    fn delay(&self, err: &AuthError, backoff: Duration) -> Option<Duration> {
+        let mut delay = if self.jitter {
+            backoff.mul_f64(0.5 + random_fraction() / 2.0)
+        } else {
+            backoff
+        };
+        let wait = match err {
+            AuthError::ServiceUnavailable { retry_after, .. } => *retry_after,
+            AuthError::RateLimited { .. } => err.retry_after(),
+            _ => None,
+        };
+        if let Some(wait) = wait {
+            if wait > self.max_backoff {
+                return None;
+            }
+            delay = delay.max(wait);
+        }
+        Some(delay)
+    }
+
+    /// Run `attempt`, retrying it as described above.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        deadline: Option<Instant>,
+        budget: Option<&RetryBudget>,
+        mut attempt: F,
+    ) -> Result<T, AuthError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AuthError>>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempts = 1;
+        loop {
+            let err = match attempt().await {
+                Err(err) if self.is_retryable(&err) => err,
+                result => return result,
+            };
+            if attempts >= self.max_attempts {
+                return Err(err);
+            }
+            let Some(delay) = self.delay(&err, backoff) else {
+                return Err(err);
+            };
+            if deadline.is_some_and(|d| Instant::now() + delay >= d) {
+                return Err(err);
+            }
+            if budget.is_some_and(|budget| !budget.try_acquire()) {
+                debug!("retry budget exhausted, not retrying");
+                return Err(err);
+            }
+            debug!("token request failed ({}), retrying in {:?}", err, delay);
+            tokio::time::sleep(delay).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+            attempts += 1;
+            record!(attempts = attempts);
+        }
+    }
+}
+
+/// A random number in `[0, 1)`.
+pub(crate) fn random_fraction() -> f64 {
+    let mut bytes = [0; 4];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return 0.5;
+    }
+    f64::from(u32::from_le_bytes(bytes)) / (f64::from(u32::MAX) + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn unavailable() -> AuthError {
+        AuthError::ServiceUnavailable {
+            message: "down".into(),
+            retry_after: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        let policy = RetryPolicy::new(3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(2));
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run(None, None, || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(unavailable()),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Gives up after max_attempts.
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), _> = policy
+            .run(None, None, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(unavailable())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Errors that aren't retryable are returned straight away,
+        // as are retries over budget.
+        for (policy, budget) in [
+            (policy.clone().retryable_statuses([]), None),
+            (policy, Some(RetryBudget::new(0, Duration::from_secs(60)))),
+        ] {
+            calls.store(0, Ordering::SeqCst);
+            let result: Result<(), _> = policy
+                .run(None, budget.as_ref(), || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(unavailable())
+                })
+                .await;
+            assert!(result.is_err());
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        let backoff = Duration::from_secs(2);
+        for _ in 0..20 {
+            let delay = policy.delay(&unavailable(), backoff).unwrap();
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+        let policy = policy.jitter(false);
+        assert_eq!(policy.delay(&unavailable(), backoff), Some(backoff));
+
+        let retry_after = |secs| AuthError::ServiceUnavailable {
+            message: "down".into(),
+            retry_after: Some(Duration::from_secs(secs)),
+        };
+        assert_eq!(
+            policy.delay(&retry_after(5), backoff),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(policy.delay(&retry_after(60), backoff), None);
+    }
+}
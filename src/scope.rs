@@ -0,0 +1,117 @@
+use crate::Permissions;
+use serde::{Deserialize, Serialize};
+
+/// Restrictions on what an installation access token can access.
+///
+/// By default a token has every permission the installation has been
+/// granted, on every repository it can access. Setting
+/// [`GithubAuthParams::scope`](crate::GithubAuthParams::scope) asks
+/// GitHub for a token limited to fewer repositories or lower
+/// permissions, which limits the damage if the token leaks.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct TokenScope {
+    /// Names of the repositories the token can access, without the
+    /// owner (for example `hello-world`, not `octo/hello-world`). The
+    /// repositories must belong to the installation's account. If
+    /// empty, the token can access all of the installation's
+    /// repositories.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repositories: Vec<String>,
+
+    /// IDs of the repositories the token can access. This can be
+    /// combined with [`repositories`](Self::repositories); IDs are
+    /// useful because they don't change when a repository is renamed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repository_ids: Vec<u64>,
+
+    /// Permissions to request. Each must be at or below the level
+    /// granted to the installation. If `None`, the token has all of
+    /// the installation's permissions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+}
+
+impl TokenScope {
+    /// Whether the scope has no restrictions, in which case no request
+    /// body is sent.
+    pub fn is_empty(&self) -> bool {
+        self.repositories.is_empty()
+            && self.repository_ids.is_empty()
+            && self.permissions.is_none()
+    }
+}
+
+/// Whether a token can access all of the installation's repositories
+/// or only selected ones, see
+/// `InstallationAccessToken::repository_selection`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositorySelection {
+    /// All repositories, including ones added to the installation
+    /// later.
+    All,
+
+    /// Only the repositories listed in
+    /// `InstallationAccessToken::repositories`,
+    /// or, for an unscoped token, the repositories selected when the
+    /// app was installed.
+    Selected,
+}
+
+/// A repository that a token can access, see
+/// `InstallationAccessToken::repositories`
+/// and
+/// `InstallationAccessToken::list_repositories`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Repository {
+    /// Repository ID.
+    pub id: u64,
+
+    /// Repository name, for example `hello-world`.
+    pub name: String,
+
+    /// Repository name with the owner, for example
+    /// `octo/hello-world`.
+    pub full_name: String,
+
+    /// Whether the repository is private.
+    #[serde(default)]
+    pub private: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PermissionLevel;
+
+    #[test]
+    fn test_serialize() {
+        let scope = TokenScope::default();
+        assert!(scope.is_empty());
+        assert_eq!(serde_json::to_string(&scope).unwrap(), "{}");
+
+        let scope = TokenScope {
+            repositories: vec!["hello-world".into()],
+            permissions: Some(Permissions {
+                contents: Some(PermissionLevel::Read),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(!scope.is_empty());
+        assert_eq!(
+            serde_json::to_string(&scope).unwrap(),
+            r#"{"repositories":["hello-world"],"permissions":{"contents":"read"}}"#
+        );
+
+        let scope = TokenScope {
+            repository_ids: vec![1296269],
+            ..Default::default()
+        };
+        assert!(!scope.is_empty());
+        assert_eq!(
+            serde_json::to_string(&scope).unwrap(),
+            r#"{"repository_ids":[1296269]}"#
+        );
+    }
+}
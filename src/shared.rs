@@ -0,0 +1,197 @@
+use crate::clock::current_time;
+use crate::provider::{AuthHeaderFuture, AuthProvider};
+use crate::response::send_error;
+use crate::{AuthError, Clock, ClockSkew, InstallationAccessToken};
+use chrono::{DateTime, Utc};
+use futures_util::lock::Mutex as AsyncMutex;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::sync::{Arc, RwLock};
+
+/// An [`InstallationAccessToken`] that can be used from many tasks or
+/// threads at once without wrapping it in a mutex.
+///
+/// [`header`](Self::header) takes `&self`. While the token is fresh,
+/// callers only take a read lock to copy the cached headers, so they
+/// never wait for each other. Once the token needs refreshing, one
+/// caller performs the refresh and the others wait for it, rather
+/// than each starting their own.
+///
+/// ```no_run
+/// # use github_app_auth::{GithubAuthParams, InstallationAccessToken,
+/// #     SharedInstallationAccessToken};
+/// # use std::sync::Arc;
+/// # async fn wrapper(params: GithubAuthParams) -> Result<(), github_app_auth::AuthError> {
+/// let token = InstallationAccessToken::new(params).await?;
+/// let token = Arc::new(SharedInstallationAccessToken::new(token));
+/// for _ in 0..4 {
+///     let token = token.clone();
+///     tokio::spawn(async move { token.header().await });
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedInstallationAccessToken {
+    cached: RwLock<Cached>,
+    token: AsyncMutex<InstallationAccessToken>,
+    clock: Option<Arc<dyn Clock>>,
+    clock_skew: Option<ClockSkew>,
+}
+
+struct Cached {
+    headers: Option<HeaderMap>,
+    refresh_at: DateTime<Utc>,
+}
+
+impl SharedInstallationAccessToken {
+    /// Wrap a token for shared use.
+    pub fn new(token: InstallationAccessToken) -> Self {
+        Self {
+            cached: RwLock::new(Cached {
+                headers: None,
+                refresh_at: token.next_refresh_at(),
+            }),
+            clock: token.params.clock.clone(),
+            clock_skew: token.params.clock_skew.clone(),
+            token: AsyncMutex::new(token),
+        }
+    }
+
+    /// Get an HTTP authentication header for the token, refreshing it
+    /// first if necessary. See [`InstallationAccessToken::header`].
+    pub async fn header(&self) -> Result<HeaderMap, AuthError> {
+        if let Some(headers) = self.fresh_headers() {
+            return Ok(headers);
+        }
+        let mut token = self.token.lock().await;
+        // Another caller may have refreshed while this one waited.
+        if let Some(headers) = self.fresh_headers() {
+            return Ok(headers);
+        }
+        let headers = token.header().await?;
+        *self.cached.write().unwrap() = Cached {
+            headers: Some(headers.clone()),
+            refresh_at: token.next_refresh_at(),
+        };
+        Ok(headers)
+    }
+
+    /// Mark the token as expired, so that the next call to
+    /// [`header`](Self::header) fetches a new one. See
+    /// [`InstallationAccessToken::invalidate`].
+    pub async fn invalidate(&self) {
+        let mut token = self.token.lock().await;
+        token.invalidate();
+        self.cached.write().unwrap().headers = None;
+    }
+
+    /// Send `request` with the token's headers, and if GitHub responds
+    /// with 401, fetch a new token and send the request once more.
+    /// See [`InstallationAccessToken::execute`].
+    ///
+    /// If several callers get a 401 for the same token at once, only
+    /// one new token is fetched.
+    pub async fn execute(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<Response, AuthError> {
+        let retry = request.try_clone();
+        let headers = self.header().await?;
+        let resp = request
+            .headers(headers.clone())
+            .send()
+            .await
+            .map_err(send_error)?;
+        let Some(retry) = retry else {
+            return Ok(resp);
+        };
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+        {
+            let mut token = self.token.lock().await;
+            let mut cached = self.cached.write().unwrap();
+            // Another caller may already have replaced the token.
+            if cached.headers.as_ref() == Some(&headers) {
+                token.invalidate();
+                cached.headers = None;
+            }
+        }
+        let headers = self.header().await?;
+        retry.headers(headers).send().await.map_err(send_error)
+    }
+
+    /// Unwrap the token, for example to call methods that need
+    /// `&mut self`.
+    pub fn into_inner(self) -> InstallationAccessToken {
+        self.token.into_inner()
+    }
+
+    fn fresh_headers(&self) -> Option<HeaderMap> {
+        let now = current_time(self.clock.as_deref(), self.clock_skew.as_ref());
+        let cached = self.cached.read().unwrap();
+        cached.headers.clone().filter(|_| now < cached.refresh_at)
+    }
+}
+
+impl AuthProvider for SharedInstallationAccessToken {
+    fn auth_header(&self) -> AuthHeaderFuture<'_> {
+        Box::pin(self.header())
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+    use futures_util::future;
+
+    #[tokio::test]
+    async fn test_shared_token() {
+        let github = MockGithub::start().await.unwrap();
+        let token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let shared = SharedInstallationAccessToken::new(token);
+
+        let headers =
+            future::try_join_all((0..10).map(|_| shared.header())).await;
+        let headers = headers.unwrap();
+        assert!(headers.iter().all(|h| h == &headers[0]));
+        assert_eq!(github.token_requests(), 1);
+
+        // Once the token is about to expire, concurrent callers share
+        // one refresh.
+        let mut token = shared.into_inner();
+        token.expires_at = Utc::now();
+        let shared = SharedInstallationAccessToken::new(token);
+        let headers =
+            future::try_join_all((0..10).map(|_| shared.header())).await;
+        let headers = headers.unwrap();
+        assert!(headers.iter().all(|h| h == &headers[0]));
+        assert_eq!(github.token_requests(), 2);
+
+        shared.invalidate().await;
+        shared.header().await.unwrap();
+        assert_eq!(github.token_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shared_execute() {
+        let github = MockGithub::start().await.unwrap();
+        let token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let client = token.client.clone();
+        let shared = SharedInstallationAccessToken::new(token);
+        let url = format!("{}/installation/repositories", github.base_url());
+
+        // The mock only accepts the newest token.
+        InstallationAccessToken::new(github.params()).await.unwrap();
+        let responses = future::try_join_all(
+            (0..5).map(|_| shared.execute(client.get(&url))),
+        )
+        .await
+        .unwrap();
+        assert!(responses.iter().all(|r| r.status() == StatusCode::OK));
+        assert_eq!(github.token_requests(), 3);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{AuthError, GithubAuthParams, JwtClaims};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`JwtSigner::sign`].
+pub type SignatureFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, AuthError>> + Send + 'a>>;
+
+/// Signs app JWTs with a private key that is held elsewhere, such as
+/// in AWS KMS, HashiCorp Vault, or an HSM.
+///
+/// Set [`GithubAuthParams::signer`] to use it instead of a local
+/// private key. The crate still builds the header and claims, so
+/// [`jwt_lifetime`](GithubAuthParams::jwt_lifetime), clock skew
+/// correction, and claims validation work as usual; the signer only
+/// has to produce the signature.
+///
+/// Signing is asynchronous, since it is usually a network call. It is
+/// used by everything that makes requests, such as
+/// `InstallationAccessToken::new`,
+/// but the synchronous [`sign_app_jwt`](crate::sign_app_jwt) and
+/// [`AppToken`](crate::AppToken) need a local key and fail with
+/// [`AuthError::InvalidConfig`] if a signer is set.
+pub trait JwtSigner: Send + Sync {
+    /// Sign `message` with RSASSA-PKCS1-v1_5 using SHA-256 (`RS256`)
+    /// and return the raw signature bytes.
+    ///
+    /// `message` is the JWT signing input: the base64url-encoded
+    /// header and claims, joined by a `.`. For AWS KMS, this is a
+    /// `Sign` request with the `RSASSA_PKCS1_V1_5_SHA_256` algorithm
+    /// and a message type of `RAW`.
+    fn sign<'a>(&'a self, message: &'a [u8]) -> SignatureFuture<'a>;
+}
+
+/// Sign an app JWT with [`GithubAuthParams::signer`] if set, or with
+/// the local private key otherwise.
+pub(crate) async fn app_jwt(
+    params: &GithubAuthParams,
+) -> Result<String, AuthError> {
+    let sign = async {
+        match &params.signer {
+            Some(signer) => sign_with(params, signer.as_ref()).await,
+            None if params.private_key_provider.is_some() => {
+                match params.load_primary_private_key_pem().await? {
+                    Some(pem) => crate::sign_jwt(
+                        params,
+                        &jsonwebtoken::EncodingKey::from_rsa_pem(&pem)?,
+                    ),
+                    None => crate::sign_app_jwt(params),
+                }
+            }
+            None => crate::sign_app_jwt(params),
+        }
+    };
+    instrument!(
+        sign,
+        "sign_app_jwt",
+        app_id = params.app_id,
+        external_signer = params.signer.is_some()
+    )
+    .await
+}
+
+/// Build an app JWT and sign it with `signer`.
+pub(crate) async fn sign_with(
+    params: &GithubAuthParams,
+    signer: &dyn JwtSigner,
+) -> Result<String, AuthError> {
+    let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+    let claims = JwtClaims::new(params)?;
+    let message =
+        format!("{}.{}", encode_json(&header)?, encode_json(&claims)?);
+    let signature = signer.sign(message.as_bytes()).await?;
+    Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+fn encode_json(value: &impl serde::Serialize) -> Result<String, AuthError> {
+    let json = serde_json::to_vec(value)
+        .map_err(|err| AuthError::InvalidConfig(err.to_string()))?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+#[cfg(all(test, feature = "client"))]
+pub(crate) mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Signer that holds the test key in memory, standing in for a
+    /// remote service.
+    pub(crate) struct TestSigner {
+        key: RsaKeyPair,
+        pub(crate) calls: AtomicUsize,
+    }
+
+    impl TestSigner {
+        pub(crate) fn new() -> Self {
+            let pem = include_str!("../tests/data/test_private_key_pkcs8.pem");
+            let body: String = pem
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect();
+            let der = base64::engine::general_purpose::STANDARD
+                .decode(body)
+                .unwrap();
+            Self {
+                key: RsaKeyPair::from_pkcs8(&der).unwrap(),
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl JwtSigner for TestSigner {
+        fn sign<'a>(&'a self, message: &'a [u8]) -> SignatureFuture<'a> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut signature = vec![0; self.key.public_modulus_len()];
+            let result = self
+                .key
+                .sign(
+                    &RSA_PKCS1_SHA256,
+                    &SystemRandom::new(),
+                    message,
+                    &mut signature,
+                )
+                .map(|_| signature)
+                .map_err(|_| AuthError::InvalidConfig("signing failed".into()));
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_with() {
+        use crate::diagnostics::diagnose;
+
+        let signer = TestSigner::new();
+        let params = GithubAuthParams {
+            app_id: 1234,
+            ..Default::default()
+        };
+        let jwt = sign_with(&params, &signer).await.unwrap();
+        let pem = include_bytes!("../tests/data/test_private_key.pem");
+        assert_eq!(diagnose(&params, &jwt, Some(pem)), vec![]);
+        assert_eq!(signer.calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(feature = "client")]
+use crate::token::RawInstallationAccessToken;
+#[cfg(feature = "client")]
+use crate::{AuthError, GithubAuthParams, InstallationAccessToken};
+use crate::{Permissions, Repository, RepositorySelection};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Serializable snapshot of an installation access token.
+///
+/// This can be used to persist a token in custom storage (for example
+/// a cache that survives serverless cold starts) and restore it with
+/// `InstallationAccessToken::from_state`, avoiding an unnecessary
+/// token fetch.
+///
+/// Note that the serialized form contains the token itself, so it
+/// must be stored as securely as the token.
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TokenState {
+    /// The installation access token.
+    pub token: String,
+
+    /// Time at which the token expires.
+    pub expires_at: DateTime<Utc>,
+
+    /// Installation that the token belongs to.
+    pub installation_id: u64,
+
+    /// Permissions granted to the token.
+    #[serde(default)]
+    pub permissions: Permissions,
+
+    /// Repositories the token is limited to, if any.
+    #[serde(default)]
+    pub repositories: Vec<Repository>,
+
+    /// Whether the token can access all of the installation's
+    /// repositories or only selected ones.
+    #[serde(default)]
+    pub repository_selection: Option<RepositorySelection>,
+}
+
+#[cfg(feature = "client")]
+impl From<TokenState> for RawInstallationAccessToken {
+    fn from(state: TokenState) -> Self {
+        RawInstallationAccessToken {
+            token: state.token,
+            expires_at: state.expires_at,
+            permissions: state.permissions,
+            repositories: state.repositories,
+            repository_selection: state.repository_selection,
+            rate_limit: None,
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl RawInstallationAccessToken {
+    pub(crate) fn to_state(&self, installation_id: u64) -> TokenState {
+        TokenState {
+            token: self.token.clone(),
+            expires_at: self.expires_at,
+            installation_id,
+            permissions: self.permissions.clone(),
+            repositories: self.repositories.clone(),
+            repository_selection: self.repository_selection,
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl InstallationAccessToken {
+    /// Get a snapshot of the current token that can be persisted.
+    pub fn to_state(&self) -> TokenState {
+        TokenState {
+            token: self.token.clone(),
+            expires_at: self.expires_at,
+            installation_id: self.params.installation_id,
+            permissions: self.permissions.clone(),
+            repositories: self.repositories.clone(),
+            repository_selection: self.repository_selection,
+        }
+    }
+
+    /// Restore a token from a snapshot created by
+    /// [`to_state`](Self::to_state).
+    ///
+    /// No request is made; the token is refreshed as usual once it is
+    /// close to expiring. Returns
+    /// [`AuthError::InstallationMismatch`] if the snapshot is for a
+    /// different installation than `params`.
+    pub fn from_state(
+        params: GithubAuthParams,
+        state: TokenState,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        if state.installation_id != params.installation_id {
+            return Err(AuthError::InstallationMismatch {
+                state: state.installation_id,
+                expected: params.installation_id,
+            });
+        }
+        let client = params.build_client()?;
+        Ok(InstallationAccessToken::from_parts(
+            client,
+            params,
+            state.into(),
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_state_round_trip() {
+        let token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        let json = serde_json::to_string(&token.to_state()).unwrap();
+        let state: TokenState = serde_json::from_str(&json).unwrap();
+        assert!(state == token.to_state());
+
+        let restored = InstallationAccessToken::from_state(
+            GithubAuthParams::default(),
+            state.clone(),
+        )
+        .unwrap();
+        assert_eq!(restored.token, "myToken");
+        assert_eq!(restored.expires_at, token.expires_at);
+
+        let params = GithubAuthParams {
+            installation_id: 1234,
+            ..Default::default()
+        };
+        assert!(matches!(
+            InstallationAccessToken::from_state(params, state),
+            Err(AuthError::InstallationMismatch {
+                state: 0,
+                expected: 1234
+            })
+        ));
+    }
+}
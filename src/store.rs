@@ -0,0 +1,500 @@
+use crate::webhook::events::{
+    InstallationEvent, InstallationRepositoriesEvent,
+};
+use crate::{
+    AuthError, AuthEvent, GithubAuthParams, InstallationAccessToken, JwtCache,
+    RateLimit, RateLimitThreshold, TokenState,
+};
+use futures_util::future;
+use futures_util::lock::Mutex as AsyncMutex;
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Slot = Arc<AsyncMutex<Option<InstallationAccessToken>>>;
+
+/// Installation access tokens for many installations of the same
+/// app, fetched on first use and refreshed as needed.
+///
+/// All tokens share one HTTP client, and so one connection pool (see
+/// [`GithubAuthParams::http_client`]), and one [`JwtCache`], so a
+/// burst of new installations doesn't sign a JWT for each. Each
+/// installation has its own lock, so a slow refresh for one
+/// installation does not hold up requests for the others, and
+/// concurrent callers for the same installation wait for a single
+/// fetch instead of each minting a token.
+///
+/// The store also keeps the latest rate limit status of each
+/// installation, from token responses and from responses passed to
+/// [`record_response`](Self::record_response), so that a multi-tenant
+/// service can share out API capacity and defer work for
+/// installations that have nearly used up theirs. See
+/// [`has_budget`](Self::has_budget).
+///
+/// ```no_run
+/// # use github_app_auth::{GithubAuthParams, InstallationTokenStore};
+/// # async fn wrapper(params: GithubAuthParams) -> Result<(), github_app_auth::AuthError> {
+/// let store = InstallationTokenStore::new(params)?;
+/// let header = store.header(1234).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct InstallationTokenStore {
+    params: GithubAuthParams,
+    tokens: Mutex<HashMap<u64, Slot>>,
+    rate_limits: Mutex<HashMap<u64, RateLimit>>,
+    rate_limit_threshold: Option<RateLimitThreshold>,
+}
+
+impl InstallationTokenStore {
+    /// Create an empty store. `params` is used for every token, except
+    /// that `installation_id` is replaced.
+    pub fn new(mut params: GithubAuthParams) -> Result<Self, AuthError> {
+        params.http_client = Some(params.build_client()?);
+        params.jwt_cache.get_or_insert_with(JwtCache::new);
+        Ok(Self {
+            params,
+            tokens: Mutex::default(),
+            rate_limits: Mutex::default(),
+            rate_limit_threshold: None,
+        })
+    }
+
+    /// Set the level below which an installation's remaining rate
+    /// limit counts as low, for [`has_budget`](Self::has_budget). It is
+    /// also set as the
+    /// [`rate_limit_threshold`](InstallationAccessToken::rate_limit_threshold)
+    /// of each token, so an [`AuthEvent::RateLimitLow`] event is sent
+    /// to the [observer](GithubAuthParams::observer) when an
+    /// installation crosses it.
+    ///
+    /// [`AuthEvent::RateLimitLow`]: crate::AuthEvent::RateLimitLow
+    pub fn rate_limit_threshold(
+        mut self,
+        threshold: RateLimitThreshold,
+    ) -> Self {
+        self.rate_limit_threshold = Some(threshold);
+        self
+    }
+
+    /// Get an HTTP authentication header for an installation, fetching
+    /// or refreshing its token first if necessary. See
+    /// [`InstallationAccessToken::header`].
+    pub async fn header(
+        &self,
+        installation_id: u64,
+    ) -> Result<HeaderMap, AuthError> {
+        let slot = self.slot(installation_id);
+        let mut guard = slot.lock().await;
+        match self.fresh_token(&mut guard, installation_id).await {
+            Ok(token) => token.header().await,
+            Err(err) => {
+                self.remove_empty_slot(installation_id, &slot, &guard);
+                Err(err)
+            }
+        }
+    }
+
+    /// Get the token for an installation, fetching or refreshing it
+    /// first if necessary, for handing it to another process.
+    pub async fn state(
+        &self,
+        installation_id: u64,
+    ) -> Result<TokenState, AuthError> {
+        let slot = self.slot(installation_id);
+        let mut guard = slot.lock().await;
+        match self.fresh_token(&mut guard, installation_id).await {
+            Ok(token) => Ok(token.to_state()),
+            Err(err) => {
+                self.remove_empty_slot(installation_id, &slot, &guard);
+                Err(err)
+            }
+        }
+    }
+
+    /// Record the rate limit headers of a response to a request made
+    /// with an installation's token. See
+    /// [`InstallationAccessToken::record_response`].
+    pub async fn record_response(
+        &self,
+        installation_id: u64,
+        resp: &reqwest::Response,
+    ) {
+        let slot = self.tokens.lock().unwrap().get(&installation_id).cloned();
+        if let Some(slot) = slot {
+            if let Some(token) = &mut *slot.lock().await {
+                token.record_response(resp);
+                self.update_rate_limit(installation_id, token);
+                return;
+            }
+        }
+        if let Some(rate_limit) = RateLimit::from_response(resp) {
+            self.rate_limits
+                .lock()
+                .unwrap()
+                .insert(installation_id, rate_limit);
+        }
+    }
+
+    /// Latest rate limit status of an installation, or `None` if no
+    /// response for it has been seen.
+    pub fn rate_limit(&self, installation_id: u64) -> Option<RateLimit> {
+        self.rate_limits
+            .lock()
+            .unwrap()
+            .get(&installation_id)
+            .cloned()
+    }
+
+    /// Latest rate limit status of every installation that has one.
+    pub fn rate_limits(&self) -> HashMap<u64, RateLimit> {
+        self.rate_limits.lock().unwrap().clone()
+    }
+
+    /// Whether an installation has enough of its rate limit left for
+    /// deferrable work: false if its latest rate limit status is below
+    /// the [threshold](Self::rate_limit_threshold), or used up if no
+    /// threshold is set, and its rate limit window has not reset
+    /// since. Installations with no known status have a budget.
+    pub fn has_budget(&self, installation_id: u64) -> bool {
+        let Some(rate_limit) = self.rate_limit(installation_id) else {
+            return true;
+        };
+        if rate_limit.reset <= self.params.now() {
+            return true;
+        }
+        match self.rate_limit_threshold {
+            Some(threshold) => !rate_limit.is_below(threshold),
+            None => rate_limit.remaining > 0,
+        }
+    }
+
+    /// Refresh up to `max_refreshes` of the tokens that are due to be
+    /// refreshed, concurrently, and return how many were refreshed.
+    ///
+    /// This is for a background task that calls it periodically, so
+    /// that requests rarely have to wait for a refresh. When more
+    /// tokens are due than `max_refreshes`, those that became due
+    /// first are refreshed first, so when many tokens expire together
+    /// they are taken in turn across calls, and an installation whose
+    /// token was just refreshed goes to the back of the line. An
+    /// [`AuthEvent::RefreshDeferred`] event is sent to the
+    /// [observer](GithubAuthParams::observer) for each token left for
+    /// a later call. Tokens that are in use by another call, for
+    /// example one already refreshing it, are skipped. Failed
+    /// refreshes are logged and reported to the observer as usual,
+    /// and tried again on the next call.
+    pub async fn refresh_due(&self, max_refreshes: usize) -> usize {
+        let slots: Vec<(u64, Slot)> = self
+            .tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, slot)| (*id, slot.clone()))
+            .collect();
+        let mut due = Vec::new();
+        for (installation_id, slot) in slots {
+            let next_refresh_at = match slot.try_lock().as_deref() {
+                Some(Some(token)) if token.needs_refresh() => {
+                    token.next_refresh_at()
+                }
+                _ => continue,
+            };
+            due.push((next_refresh_at, installation_id, slot));
+        }
+        due.sort_by_key(|(next_refresh_at, installation_id, _)| {
+            (*next_refresh_at, *installation_id)
+        });
+
+        let deferred = due.split_off(due.len().min(max_refreshes));
+        for (next_refresh_at, installation_id, _) in deferred {
+            self.params.notify(AuthEvent::RefreshDeferred {
+                installation_id,
+                next_refresh_at,
+            });
+        }
+        let refreshes = due.into_iter().map(|(_, installation_id, slot)| {
+            self.refresh_slot(installation_id, slot)
+        });
+        let results = future::join_all(refreshes).await;
+        results.into_iter().filter(|refreshed| *refreshed).count()
+    }
+
+    /// Refresh the token in `slot` if it needs it, returning whether a
+    /// new token was fetched.
+    async fn refresh_slot(&self, installation_id: u64, slot: Slot) -> bool {
+        let mut slot = slot.lock().await;
+        let Some(token) = slot.as_mut() else {
+            return false;
+        };
+        match token.refresh_if_needed().await {
+            Ok(refreshed) => {
+                self.update_rate_limit(installation_id, token);
+                refreshed
+            }
+            Err(err) => {
+                warn!(
+                    "failed to refresh token for installation {}: {}",
+                    installation_id, err
+                );
+                false
+            }
+        }
+    }
+
+    /// Forget the token for an installation, for example after the app
+    /// was uninstalled. Returns false if there was no token.
+    pub fn remove(&self, installation_id: u64) -> bool {
+        self.rate_limits.lock().unwrap().remove(&installation_id);
+        self.tokens
+            .lock()
+            .unwrap()
+            .remove(&installation_id)
+            .is_some()
+    }
+
+    /// Forget the token for the installation that an `installation`
+    /// webhook event is about, if the event
+    /// [invalidates](InstallationEvent::invalidates_tokens) it, for
+    /// example because the app was uninstalled. Returns true if a
+    /// token was removed.
+    pub fn handle_installation_event(&self, event: &InstallationEvent) -> bool {
+        event.invalidates_tokens() && self.remove(event.installation.id)
+    }
+
+    /// Forget the token for the installation that an
+    /// `installation_repositories` webhook event is about, if the
+    /// event
+    /// [invalidates](InstallationRepositoriesEvent::invalidates_tokens)
+    /// it. Returns true if a token was removed.
+    pub fn handle_installation_repositories_event(
+        &self,
+        event: &InstallationRepositoriesEvent,
+    ) -> bool {
+        event.invalidates_tokens() && self.remove(event.installation.id)
+    }
+
+    /// Number of installations that have a token, or are fetching one.
+    pub fn len(&self) -> usize {
+        self.tokens.lock().unwrap().len()
+    }
+
+    /// Whether the store holds no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the token in `slot`, fetching it or refreshing it first if
+    /// necessary.
+    async fn fresh_token<'a>(
+        &self,
+        slot: &'a mut Option<InstallationAccessToken>,
+        installation_id: u64,
+    ) -> Result<&'a mut InstallationAccessToken, AuthError> {
+        match slot {
+            Some(token) => {
+                token.refresh_if_needed().await?;
+            }
+            None => {
+                let params = GithubAuthParams {
+                    installation_id,
+                    ..self.params.clone()
+                };
+                let mut token = InstallationAccessToken::new(params).await?;
+                token.rate_limit_threshold = self.rate_limit_threshold;
+                *slot = Some(token);
+            }
+        }
+        let token = slot.as_mut().unwrap();
+        self.update_rate_limit(installation_id, token);
+        Ok(token)
+    }
+
+    fn update_rate_limit(
+        &self,
+        installation_id: u64,
+        token: &InstallationAccessToken,
+    ) {
+        if let Some(rate_limit) = token.rate_limit() {
+            self.rate_limits
+                .lock()
+                .unwrap()
+                .insert(installation_id, rate_limit.clone());
+        }
+    }
+
+    /// Remove an installation's slot if its first fetch failed, so
+    /// that unknown or uninstalled installation IDs are not counted by
+    /// [`len`](Self::len) and don't accumulate.
+    fn remove_empty_slot(
+        &self,
+        installation_id: u64,
+        slot: &Slot,
+        token: &Option<InstallationAccessToken>,
+    ) {
+        if token.is_some() {
+            return;
+        }
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens
+            .get(&installation_id)
+            .is_some_and(|current| Arc::ptr_eq(current, slot))
+        {
+            tokens.remove(&installation_id);
+        }
+    }
+
+    fn slot(&self, installation_id: u64) -> Slot {
+        self.tokens
+            .lock()
+            .unwrap()
+            .entry(installation_id)
+            .or_default()
+            .clone()
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_store() {
+        let github = MockGithub::start().await.unwrap();
+        let store = InstallationTokenStore::new(github.params()).unwrap();
+        assert!(store.is_empty());
+
+        let (first, second) =
+            futures_util::future::join(store.header(2), store.header(2)).await;
+        assert_eq!(first.unwrap(), second.unwrap());
+        store.header(3).await.unwrap();
+        store.header(3).await.unwrap();
+        assert_eq!(github.token_requests(), 2);
+        assert_eq!(store.len(), 2);
+
+        assert!(store.remove(3));
+        assert!(!store.remove(3));
+        store.header(3).await.unwrap();
+        assert_eq!(github.token_requests(), 3);
+
+        let state = store.state(3).await.unwrap();
+        assert_eq!(state.installation_id, 3);
+        assert_eq!(state.token, "mock-token-3");
+        assert_eq!(github.token_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_first_fetch() {
+        use crate::test_util::MockFailure;
+
+        let github = MockGithub::start().await.unwrap();
+        let store = InstallationTokenStore::new(github.params()).unwrap();
+        github.fail_next_token_request(MockFailure::Status(404));
+        assert!(store.header(4).await.is_err());
+        github.fail_next_token_request(MockFailure::Status(404));
+        assert!(store.state(5).await.is_err());
+        assert!(store.is_empty());
+
+        store.header(4).await.unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_budget() {
+        let github = MockGithub::start().await.unwrap();
+        let store = InstallationTokenStore::new(github.params())
+            .unwrap()
+            .rate_limit_threshold(RateLimitThreshold::Remaining(100));
+        let response = |remaining: u64, reset: chrono::DateTime<Utc>| {
+            reqwest::Response::from(
+                http::Response::builder()
+                    .header("x-ratelimit-limit", "5000")
+                    .header("x-ratelimit-remaining", remaining.to_string())
+                    .header("x-ratelimit-reset", reset.timestamp().to_string())
+                    .body("")
+                    .unwrap(),
+            )
+        };
+        let later = Utc::now() + chrono::Duration::minutes(30);
+
+        store.header(2).await.unwrap();
+        assert_eq!(store.rate_limit(2).unwrap().remaining, 4999);
+        assert!(store.has_budget(2));
+        store.record_response(2, &response(50, later)).await;
+        assert_eq!(store.rate_limit(2).unwrap().remaining, 50);
+        assert!(!store.has_budget(2));
+
+        // Installations without a token, or without a known status.
+        assert!(store.has_budget(3));
+        store.record_response(3, &response(99, later)).await;
+        assert!(!store.has_budget(3));
+        assert_eq!(store.rate_limits().len(), 2);
+        assert_eq!(store.len(), 1);
+
+        // The budget comes back once the window resets.
+        let earlier = Utc::now() - chrono::Duration::minutes(1);
+        store.record_response(3, &response(0, earlier)).await;
+        assert!(store.has_budget(3));
+
+        assert!(store.remove(2));
+        assert!(store.rate_limit(2).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_due() {
+        use crate::observer::tests::RecordingObserver;
+
+        let deferred = |recorder: &RecordingObserver| -> Vec<_> {
+            let events = recorder.events("refresh_deferred");
+            events
+                .iter()
+                .map(|event| event["installation_id"].as_u64().unwrap())
+                .collect()
+        };
+
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(chrono::Duration::zero());
+        let recorder = Arc::new(RecordingObserver::default());
+        let store = InstallationTokenStore::new(GithubAuthParams {
+            observer: Some(recorder.clone()),
+            ..github.params()
+        })
+        .unwrap();
+        assert_eq!(store.refresh_due(2).await, 0);
+        for installation_id in 1..=3 {
+            store.state(installation_id).await.unwrap();
+        }
+        assert_eq!(github.token_requests(), 3);
+
+        // The token that became due last waits for the next call, and
+        // then goes ahead of the tokens that were just refreshed.
+        assert_eq!(store.refresh_due(2).await, 2);
+        assert_eq!(deferred(&recorder), [3]);
+        assert_eq!(store.refresh_due(2).await, 2);
+        let deferred = deferred(&recorder);
+        assert_eq!(deferred.len(), 2);
+        assert_ne!(deferred[1], 3);
+        assert_eq!(github.token_requests(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_handle_installation_event() {
+        let github = MockGithub::start().await.unwrap();
+        let store = InstallationTokenStore::new(github.params()).unwrap();
+        store.header(3).await.unwrap();
+
+        let event = |action: &str| -> InstallationEvent {
+            serde_json::from_value(serde_json::json!({
+                "action": action,
+                "installation": {"id": 3},
+            }))
+            .unwrap()
+        };
+        assert!(!store.handle_installation_event(&event("unsuspend")));
+        assert_eq!(store.len(), 1);
+        assert!(store.handle_installation_event(&event("deleted")));
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+//! A one-shot HTTP server for testing requests to third-party APIs,
+//! such as the secret stores, that `MockGithub` doesn't implement.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Serve one response with a JSON `body` on a local port. Returns
+/// the URL, and a handle that gives the request that was received.
+pub(crate) async fn serve_once(
+    body: &'static str,
+) -> (String, JoinHandle<String>) {
+    serve_once_with_status("200 OK", body).await
+}
+
+/// Like [`serve_once`], but with the given status line, for example
+/// `400 Bad Request`.
+pub(crate) async fn serve_once_with_status(
+    status: &'static str,
+    body: &'static str,
+) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            request.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&request).into_owned();
+            let Some((head, rest)) = text.split_once("\r\n\r\n") else {
+                continue;
+            };
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse().unwrap())
+                })
+                .unwrap_or(0);
+            if n == 0 || rest.len() >= length {
+                break;
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    (url, handle)
+}
@@ -0,0 +1,907 @@
+//! Utilities for testing code that uses this crate, without real
+//! GitHub credentials.
+//!
+//! [`MockGithub`] is a tiny HTTP server implementing the endpoints the
+//! crate calls. Point [`GithubAuthParams::base_url`] at it (or use
+//! [`MockGithub::params`]) to exercise token fetching and refreshing:
+//!
+//! ```
+//! use github_app_auth::test_util::{MockFailure, MockGithub};
+//! use github_app_auth::InstallationAccessToken;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let github = MockGithub::start().await.unwrap();
+//! let token = InstallationAccessToken::new(github.params()).await.unwrap();
+//! assert_eq!(github.token_requests(), 1);
+//!
+//! github.fail_next_token_request(MockFailure::Status(500));
+//! assert!(InstallationAccessToken::new(github.params()).await.is_err());
+//! # }
+//! ```
+//!
+//! For tests against real GitHub responses, [`RecordingClient`]
+//! records the requests made through an [`HttpClient`], such as
+//! [`fetch_installation_token`], to a HAR file with the credentials
+//! redacted, and [`ReplayClient`] plays such a file back without
+//! credentials or network access:
+//!
+//! ```no_run
+//! use github_app_auth::test_util::ReplayClient;
+//! use github_app_auth::{fetch_installation_token, GithubAuthParams};
+//!
+//! # async fn wrapper(params: GithubAuthParams) {
+//! let client = ReplayClient::from_file("tests/fixtures/token.har").unwrap();
+//! let state = fetch_installation_token(&client, &params).await.unwrap();
+//! # }
+//! ```
+//!
+//! This module requires the `test-util` feature and must be used
+//! inside a Tokio runtime.
+//!
+//! [`HttpClient`]: crate::HttpClient
+//! [`fetch_installation_token`]: crate::fetch_installation_token
+
+mod replay;
+
+pub use replay::{RecordingClient, ReplayClient};
+
+use crate::user_auth::OAuthCredentials;
+use crate::{Clock, GithubAuthParams, Permissions};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Private key that can be used with [`MockGithub`], which does not
+/// check JWT signatures.
+pub const TEST_PRIVATE_KEY: &[u8] =
+    include_bytes!("../tests/data/test_private_key.pem");
+
+/// App ID used by [`MockGithub::params`].
+pub const TEST_APP_ID: u64 = 1;
+
+/// Installation ID used by [`MockGithub::params`].
+pub const TEST_INSTALLATION_ID: u64 = 2;
+
+/// Client ID used by [`MockGithub::oauth_credentials`].
+pub const TEST_CLIENT_ID: &str = "Iv1.mock";
+
+/// OAuth code that [`MockGithub`] exchanges for a user access token.
+pub const TEST_OAUTH_CODE: &str = "mock-code";
+
+/// App manifest code that [`MockGithub`] converts into an app.
+pub const TEST_MANIFEST_CODE: &str = "mock-manifest-code";
+
+/// Organization that the mock app is installed on.
+const MOCK_ORG: &str = "mock-org";
+
+/// A failure that [`MockGithub`] returns instead of a token.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum MockFailure {
+    /// Respond with this HTTP status code and a JSON error body. Use
+    /// 401 to simulate a rejected JWT.
+    Status(u16),
+
+    /// Close the connection without responding, which the client sees
+    /// as a network error.
+    Disconnect,
+
+    /// Respond with 403 and the headers GitHub sends when the primary
+    /// rate limit is exhausted (`x-ratelimit-remaining: 0`).
+    RateLimited,
+
+    /// Respond with 201 and a body that is not valid token JSON.
+    MalformedBody,
+
+    /// Respond with 403 and the error GitHub returns when the
+    /// installation is suspended.
+    Suspended,
+
+    /// Respond normally, but only after waiting this long, to test
+    /// timeouts.
+    Delay(time::Duration),
+}
+
+/// A [`Clock`] that only moves when told to, for testing expiry and
+/// refresh without waiting.
+///
+/// ```
+/// use github_app_auth::test_util::MockClock;
+/// use github_app_auth::GithubAuthParams;
+/// use std::sync::Arc;
+///
+/// let clock = Arc::new(MockClock::new());
+/// let params = GithubAuthParams {
+///     clock: Some(clock.clone()),
+///     ..Default::default()
+/// };
+/// clock.advance(chrono::Duration::hours(1));
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    /// Create a clock set to the current time.
+    pub fn new() -> Self {
+        Self::at(Utc::now())
+    }
+
+    /// Create a clock set to `now`.
+    pub fn at(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Set the time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Move the time forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+struct State {
+    token_lifetime: Duration,
+    permissions: Permissions,
+    scoped_permissions: Option<Permissions>,
+    failures: VecDeque<MockFailure>,
+    tokens: Vec<String>,
+    revoked: Vec<String>,
+    installation_lookups: usize,
+    connections: usize,
+    user_tokens: usize,
+    device_polls: usize,
+    addr: SocketAddr,
+}
+
+/// A mock GitHub API server for tests.
+///
+/// It implements:
+///
+/// - `POST /app/installations/{id}/access_tokens`, which returns a new
+///   token named `mock-token-1`, `mock-token-2`, and so on, for any
+///   installation ID. If the request asks for specific permissions or
+///   repositories, those are reported as granted, unless other
+///   permissions are set with [`set_scoped_permissions`].
+/// - `GET /app`, for [`health_check`].
+/// - `GET /meta`, which lists `192.30.252.0/22` as the only webhook
+///   IP range, for [`HookIpValidator`]. Like GitHub, it rejects
+///   requests without a `User-Agent` header.
+/// - `GET /app/installations/{id}`, which returns an installation on
+///   the organization `mock-org`.
+/// - `GET /orgs/{org}/installation` and
+///   `GET /repos/{owner}/{repo}/installation`, which return the same
+///   installation for `mock-org` and its repositories, and 404 for
+///   any other account.
+/// - `GET /installation/repositories`, which accepts only the most
+///   recently issued token, for [`verify`]. It lists the repositories
+///   `mock-org/hello-world` and the private `mock-org/secret`, one per
+///   page.
+/// - `DELETE /installation/token`, which revokes a token.
+/// - `POST /login/oauth/access_token`, which exchanges
+///   [`TEST_OAUTH_CODE`] for a user access token named
+///   `mock-user-token-1` and so on, for [`user_auth`]. The most
+///   recent refresh token can also be exchanged for a new token.
+/// - `POST /app-manifests/{code}/conversions`, which returns the mock
+///   app with [`TEST_PRIVATE_KEY`] for [`TEST_MANIFEST_CODE`].
+/// - `POST /login/device/code`, which starts a device flow. The first
+///   poll for its token reports a pending authorization, the second
+///   asks for polling to slow down, and the third returns a token.
+///
+/// The server shuts down when this is dropped.
+///
+/// [`health_check`]: crate::InstallationAccessToken::health_check
+/// [`HookIpValidator`]: crate::webhook::HookIpValidator
+/// [`verify`]: crate::InstallationAccessToken::verify
+/// [`user_auth`]: crate::user_auth
+/// [`set_scoped_permissions`]: MockGithub::set_scoped_permissions
+pub struct MockGithub {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockGithub {
+    /// Start the server on a random local port.
+    pub async fn start() -> io::Result<MockGithub> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            token_lifetime: Duration::hours(1),
+            permissions: Permissions::default(),
+            scoped_permissions: None,
+            failures: VecDeque::new(),
+            tokens: Vec::new(),
+            revoked: Vec::new(),
+            installation_lookups: 0,
+            connections: 0,
+            user_tokens: 0,
+            device_polls: 0,
+            addr,
+        }));
+        let task = tokio::spawn(accept_loop(listener, state.clone()));
+        Ok(MockGithub { addr, state, task })
+    }
+
+    /// Base URL of the server, for [`GithubAuthParams::base_url`].
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Parameters that authenticate against this server, using
+    /// [`TEST_PRIVATE_KEY`], [`TEST_APP_ID`], and
+    /// [`TEST_INSTALLATION_ID`].
+    pub fn params(&self) -> GithubAuthParams {
+        GithubAuthParams {
+            user_agent: "github-app-auth-test".into(),
+            private_key: TEST_PRIVATE_KEY.to_vec(),
+            app_id: TEST_APP_ID,
+            installation_id: TEST_INSTALLATION_ID,
+            base_url: Some(self.base_url()),
+            ..Default::default()
+        }
+    }
+
+    /// OAuth credentials for this server, for [`user_auth`], using
+    /// [`TEST_CLIENT_ID`].
+    ///
+    /// [`user_auth`]: crate::user_auth
+    pub fn oauth_credentials(&self) -> OAuthCredentials {
+        OAuthCredentials {
+            base_url: Some(self.base_url()),
+            ..OAuthCredentials::new(
+                "github-app-auth-test",
+                TEST_CLIENT_ID,
+                "mock-secret",
+            )
+        }
+    }
+
+    /// Set how long newly issued tokens are valid for. Defaults to
+    /// one hour, like GitHub.
+    pub fn set_token_lifetime(&self, lifetime: Duration) {
+        self.state().token_lifetime = lifetime;
+    }
+
+    /// Set the permissions reported for newly issued tokens. Defaults
+    /// to no permissions.
+    pub fn set_permissions(&self, permissions: Permissions) {
+        self.state().permissions = permissions;
+    }
+
+    /// Set the permissions reported for newly issued tokens that ask
+    /// for specific permissions, instead of the requested ones, as if
+    /// GitHub granted less than was asked for.
+    pub fn set_scoped_permissions(&self, permissions: Permissions) {
+        self.state().scoped_permissions = Some(permissions);
+    }
+
+    /// Make the next token request fail. Failures are queued, so
+    /// calling this twice makes the next two requests fail. See
+    /// [`MockFailure`] for the kinds of failure that can be injected.
+    pub fn fail_next_token_request(&self, failure: MockFailure) {
+        self.state().failures.push_back(failure);
+    }
+
+    /// Number of successful token requests so far.
+    pub fn token_requests(&self) -> usize {
+        self.state().tokens.len()
+    }
+
+    /// Number of repository and organization installation lookups so
+    /// far, including ones that found nothing.
+    pub fn installation_lookups(&self) -> usize {
+        self.state().installation_lookups
+    }
+
+    /// Number of connections accepted so far. The server supports
+    /// keep-alive, so this shows whether clients reuse connections.
+    pub fn connections(&self) -> usize {
+        self.state().connections
+    }
+
+    /// Number of tokens that have been revoked so far.
+    pub fn revocations(&self) -> usize {
+        self.state().revoked.len()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for MockGithub {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(listener: TcpListener, state: Arc<Mutex<State>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        state.lock().unwrap().connections += 1;
+        tokio::spawn(handle_connection(stream, state.clone()));
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    user_agent: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut buf = Vec::new();
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let mut path = request_line.next().unwrap_or_default().to_string();
+    // Requests sent through a proxy have the full URL as the target,
+    // so the server can also stand in for an HTTP proxy.
+    if let Some(rest) = path.strip_prefix("http://") {
+        path = rest.find('/').map_or("/".into(), |i| rest[i..].into());
+    }
+
+    let mut authorization = None;
+    let mut user_agent = None;
+    let mut content_length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("user-agent") {
+            user_agent = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().unwrap_or(0);
+        }
+    }
+
+    let mut body = buf.split_off((header_end + 4).min(buf.len()));
+    while body.len() < content_length {
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        user_agent,
+        body,
+    })
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    while let Ok(request) = read_request(&mut stream).await {
+        // Dropping the stream without writing anything simulates a
+        // network error.
+        let Some(resp) = route(&request, &state) else {
+            return;
+        };
+        tokio::time::sleep(resp.delay).await;
+        let reason = match resp.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            _ => "Error",
+        };
+        let mut response = format!("HTTP/1.1 {} {}\r\n", resp.status, reason);
+        for (name, value) in &resp.headers {
+            response += &format!("{}: {}\r\n", name, value);
+        }
+        response += &format!(
+            "Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            resp.body.len(),
+            resp.body
+        );
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+    delay: time::Duration,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Option<Response> {
+        Some(Response {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+            delay: time::Duration::ZERO,
+        })
+    }
+
+    fn error(status: u16, message: &str) -> Option<Response> {
+        Response::json(
+            status,
+            serde_json::json!({
+                "message": message,
+                "documentation_url": "https://docs.github.com/rest",
+            }),
+        )
+    }
+}
+
+fn injected_failure(failure: MockFailure) -> Option<Response> {
+    match failure {
+        MockFailure::Status(status) => {
+            Response::error(status, "Injected failure")
+        }
+        MockFailure::Disconnect => None,
+        MockFailure::Delay(_) => unreachable!("handled by route"),
+        MockFailure::Suspended => {
+            Response::error(403, "This installation has been suspended")
+        }
+        MockFailure::RateLimited => Some(Response {
+            status: 403,
+            headers: vec![
+                ("x-ratelimit-limit", "5000".into()),
+                ("x-ratelimit-remaining", "0".into()),
+                (
+                    "x-ratelimit-reset",
+                    (Utc::now() + Duration::hours(1)).timestamp().to_string(),
+                ),
+            ],
+            body: serde_json::json!({ "message": "API rate limit exceeded" })
+                .to_string(),
+            delay: time::Duration::ZERO,
+        }),
+        MockFailure::MalformedBody => Response::json(
+            201,
+            serde_json::json!({ "unexpected": "not a token" }),
+        ),
+    }
+}
+
+fn installation_json(state: &State) -> serde_json::Value {
+    serde_json::json!({
+        "id": TEST_INSTALLATION_ID,
+        "account": {"id": 3, "login": MOCK_ORG, "type": "Organization"},
+        "suspended_at": null,
+        "permissions": state.permissions,
+    })
+}
+
+/// Repositories granted to a scoped token: one per requested name,
+/// then one per requested ID, all owned by `MOCK_ORG`.
+fn scoped_repositories(scope: &serde_json::Value) -> Vec<serde_json::Value> {
+    let names = scope["repositories"].as_array().into_iter().flatten();
+    let names = names
+        .filter_map(|name| name.as_str())
+        .enumerate()
+        .map(|(i, name)| (i as u64 + 1, name.to_string()));
+    let ids = scope["repository_ids"].as_array().into_iter().flatten();
+    let ids = ids
+        .filter_map(|id| id.as_u64())
+        .map(|id| (id, format!("repo-{}", id)));
+    names
+        .chain(ids)
+        .map(|(id, name)| {
+            serde_json::json!({
+                "id": id,
+                "full_name": format!("{}/{}", MOCK_ORG, name),
+                "name": name,
+            })
+        })
+        .collect()
+}
+
+fn route(request: &Request, state: &Mutex<State>) -> Option<Response> {
+    let mut state = state.lock().unwrap();
+    let path = request.path.split('?').next().unwrap_or_default();
+    let bearer = request
+        .authorization
+        .as_deref()
+        .and_then(|auth| auth.strip_prefix("Bearer "));
+
+    let is_token_path = path
+        .strip_prefix("/app/installations/")
+        .and_then(|rest| rest.strip_suffix("/access_tokens"))
+        .is_some_and(|id| id.parse::<u64>().is_ok());
+    match (request.method.as_str(), path) {
+        ("POST", _) if is_token_path => {
+            if bearer.is_none() {
+                return Response::error(401, "A JSON web token is required");
+            }
+            let mut delay = time::Duration::ZERO;
+            match state.failures.pop_front() {
+                Some(MockFailure::Delay(duration)) => delay = duration,
+                Some(failure) => return injected_failure(failure),
+                None => {}
+            }
+            let token = format!("mock-token-{}", state.tokens.len() + 1);
+            state.tokens.push(token.clone());
+            // Like GitHub, report the requested permissions for a
+            // scoped token rather than the installation's.
+            let scope: serde_json::Value =
+                serde_json::from_slice(&request.body).unwrap_or_default();
+            let permissions = match scope.get("permissions") {
+                Some(requested) => match &state.scoped_permissions {
+                    Some(granted) => serde_json::json!(granted),
+                    None => requested.clone(),
+                },
+                None => serde_json::json!(state.permissions),
+            };
+            let mut body = serde_json::json!({
+                "token": token,
+                "expires_at": Utc::now() + state.token_lifetime,
+                "permissions": permissions,
+            });
+            let repositories = scoped_repositories(&scope);
+            if repositories.is_empty() {
+                body["repository_selection"] = "all".into();
+            } else {
+                body["repositories"] = repositories.into();
+                body["repository_selection"] = "selected".into();
+            }
+            let mut response = Response::json(201, body)?;
+            response.headers = vec![
+                ("x-ratelimit-limit", "5000".into()),
+                ("x-ratelimit-remaining", "4999".into()),
+                (
+                    "x-ratelimit-reset",
+                    (Utc::now() + Duration::hours(1)).timestamp().to_string(),
+                ),
+            ];
+            response.delay = delay;
+            Some(response)
+        }
+        ("GET", "/meta") if request.user_agent.is_none() => {
+            Response::error(403, "Request forbidden by administrative rules")
+        }
+        ("GET", "/meta") => Response::json(
+            200,
+            serde_json::json!({ "hooks": ["192.30.252.0/22", "not a range"] }),
+        ),
+        ("GET", "/app") => match bearer {
+            Some(_) => Response::json(
+                200,
+                serde_json::json!({
+                    "id": TEST_APP_ID,
+                    "slug": "mock-app",
+                    "name": "Mock App",
+                    "owner": installation_json(&state)["account"],
+                    "permissions": state.permissions,
+                    "events": ["push"],
+                }),
+            ),
+            None => Response::error(401, "A JSON web token is required"),
+        },
+        ("GET", path)
+            if path
+                == format!("/app/installations/{}", TEST_INSTALLATION_ID) =>
+        {
+            if bearer.is_none() {
+                return Response::error(401, "A JSON web token is required");
+            }
+            Response::json(200, installation_json(&state))
+        }
+        ("GET", path)
+            if path.ends_with("/installation")
+                && (path.starts_with("/orgs/")
+                    || path.starts_with("/repos/")) =>
+        {
+            if bearer.is_none() {
+                return Response::error(401, "A JSON web token is required");
+            }
+            state.installation_lookups += 1;
+            let account = path.split('/').nth(2).unwrap_or_default();
+            if account.eq_ignore_ascii_case(MOCK_ORG) {
+                Response::json(200, installation_json(&state))
+            } else {
+                Response::error(404, "Not Found")
+            }
+        }
+        ("GET", "/installation/repositories") => {
+            let current =
+                state.tokens.last().filter(|t| !state.revoked.contains(t));
+            let given = request.authorization.as_deref().and_then(|auth| {
+                auth.strip_prefix("token ")
+                    .or_else(|| auth.strip_prefix("Bearer "))
+            });
+            if current.is_some() && given == current.map(String::as_str) {
+                let page2 =
+                    request.path.split(['?', '&']).any(|p| p == "page=2");
+                let (id, name, private) = if page2 {
+                    (2, "secret", true)
+                } else {
+                    (1, "hello-world", false)
+                };
+                let body = serde_json::json!({
+                    "total_count": 2,
+                    "repositories": [{
+                        "id": id,
+                        "name": name,
+                        "full_name": format!("{}/{}", MOCK_ORG, name),
+                        "private": private,
+                    }],
+                });
+                let mut response = Response::json(200, body)?;
+                if !page2 {
+                    let next = format!(
+                        "<http://{}/installation/repositories?page=2>; \
+                         rel=\"next\"",
+                        state.addr
+                    );
+                    response.headers.push(("link", next));
+                }
+                Some(response)
+            } else {
+                Response::error(401, "Bad credentials")
+            }
+        }
+        ("DELETE", "/installation/token") => {
+            let token = request
+                .authorization
+                .as_deref()
+                .and_then(|auth| auth.strip_prefix("token "))
+                .filter(|t| state.tokens.iter().any(|issued| issued == t))
+                .filter(|t| !state.revoked.iter().any(|revoked| revoked == t))
+                .map(str::to_string);
+            match token {
+                Some(token) => {
+                    state.revoked.push(token);
+                    Some(Response {
+                        status: 204,
+                        headers: Vec::new(),
+                        body: String::new(),
+                        delay: time::Duration::ZERO,
+                    })
+                }
+                None => Response::error(401, "Bad credentials"),
+            }
+        }
+        ("POST", path) if path.starts_with("/app-manifests/") => {
+            let expected =
+                format!("/app-manifests/{}/conversions", TEST_MANIFEST_CODE);
+            if path != expected {
+                return Response::error(404, "Not Found");
+            }
+            Response::json(
+                201,
+                serde_json::json!({
+                    "id": TEST_APP_ID,
+                    "slug": "mock-app",
+                    "name": "Mock App",
+                    "owner": installation_json(&state)["account"],
+                    "client_id": TEST_CLIENT_ID,
+                    "client_secret": "mock-secret",
+                    "webhook_secret": "mock-webhook-secret",
+                    "pem": String::from_utf8_lossy(TEST_PRIVATE_KEY),
+                }),
+            )
+        }
+        ("POST", "/login/device/code") => Response::json(
+            200,
+            serde_json::json!({
+                "device_code": "mock-device-code",
+                "user_code": "MOCK-CODE",
+                "verification_uri": "https://github.com/login/device",
+                "expires_in": 900,
+                "interval": 0,
+            }),
+        ),
+        ("POST", "/login/oauth/access_token") => {
+            let body = String::from_utf8_lossy(&request.body);
+            let form: HashMap<&str, &str> = body
+                .split('&')
+                .filter_map(|kv| kv.split_once('='))
+                .collect();
+            if form.get("client_id") != Some(&TEST_CLIENT_ID) {
+                return oauth_error("incorrect_client_credentials");
+            }
+            let refresh_token =
+                format!("mock-refresh-token-{}", state.user_tokens);
+            if form.contains_key("device_code") {
+                state.device_polls += 1;
+                match state.device_polls {
+                    1 => return oauth_error("authorization_pending"),
+                    2 => {
+                        return Response::json(
+                            200,
+                            serde_json::json!({
+                                "error": "slow_down",
+                                "interval": 1,
+                            }),
+                        )
+                    }
+                    _ => {}
+                }
+            } else if form.get("grant_type") == Some(&"refresh_token") {
+                if form.get("refresh_token") != Some(&refresh_token.as_str()) {
+                    return oauth_error("bad_refresh_token");
+                }
+            } else if form.get("code") != Some(&TEST_OAUTH_CODE) {
+                return oauth_error("bad_verification_code");
+            }
+            state.user_tokens += 1;
+            let n = state.user_tokens;
+            Response::json(
+                200,
+                serde_json::json!({
+                    "access_token": format!("mock-user-token-{}", n),
+                    "token_type": "bearer",
+                    "scope": "",
+                    "expires_in": 28800,
+                    "refresh_token": format!("mock-refresh-token-{}", n),
+                    "refresh_token_expires_in": 15897600,
+                }),
+            )
+        }
+        _ => Response::error(404, "Not Found"),
+    }
+}
+
+/// GitHub reports OAuth errors with status 200.
+fn oauth_error(error: &str) -> Option<Response> {
+    Response::json(
+        200,
+        serde_json::json!({
+            "error": error,
+            "error_description": format!("Mock error: {}", error),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthError, InstallationAccessToken, TokenValidity};
+
+    #[tokio::test]
+    async fn test_mock_github() {
+        let github = MockGithub::start().await.unwrap();
+        // Expire immediately so that every header() call refreshes.
+        github.set_token_lifetime(Duration::zero());
+
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        assert_eq!(github.token_requests(), 1);
+        assert_eq!(token.verify().await.unwrap(), TokenValidity::Valid);
+        assert!(token.health_check().await.unwrap());
+        let installation = token.installation().await.unwrap();
+        assert_eq!(installation.account.as_ref().unwrap().login, "mock-org");
+
+        assert!(token.refresh_if_needed().await.unwrap());
+        assert_eq!(github.token_requests(), 2);
+        assert_eq!(token.refresh_count(), 1);
+
+        github.fail_next_token_request(MockFailure::Status(500));
+        let err = token.header().await.unwrap_err();
+        assert_eq!(err.status().map(|s| s.as_u16()), Some(500));
+        assert!(matches!(
+            &err,
+            AuthError::GithubApi { message, documentation_url: Some(_), .. }
+                if message == "Injected failure"
+        ));
+        token.header().await.unwrap();
+        assert_eq!(github.token_requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let github = MockGithub::start().await.unwrap();
+        let clock = Arc::new(MockClock::new());
+        let mut token = InstallationAccessToken::new(GithubAuthParams {
+            clock: Some(clock.clone()),
+            ..github.params()
+        })
+        .await
+        .unwrap();
+        assert_eq!(token.minted_at(), clock.now());
+        assert!(!token.needs_refresh());
+
+        clock.advance(Duration::hours(1));
+        assert!(token.needs_refresh());
+        token.header().await.unwrap();
+        assert_eq!(github.token_requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mock_failures() {
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(Duration::zero());
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        assert_eq!(token.rate_limit().unwrap().remaining, 4999);
+
+        github.fail_next_token_request(MockFailure::Status(401));
+        let err = token.header().await.unwrap_err();
+        assert!(err.is_credential_error());
+        assert!(err.hint().unwrap().contains("private key"));
+
+        github.fail_next_token_request(MockFailure::Disconnect);
+        let err = token.header().await.unwrap_err();
+        assert!(err.status().is_none());
+
+        github.fail_next_token_request(MockFailure::RateLimited);
+        let err = token.header().await.unwrap_err();
+        assert_eq!(err.status().map(|s| s.as_u16()), Some(403));
+        assert!(matches!(
+            err,
+            AuthError::RateLimited {
+                reset_at: Some(_),
+                ..
+            }
+        ));
+
+        github.fail_next_token_request(MockFailure::Status(503));
+        let err = token.header().await.unwrap_err();
+        assert!(matches!(err, AuthError::ServiceUnavailable { .. }));
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(60)));
+
+        github.fail_next_token_request(MockFailure::MalformedBody);
+        let err = token.header().await.unwrap_err();
+        assert!(matches!(err, AuthError::ReqwestError(e) if e.is_decode()));
+
+        github.fail_next_token_request(MockFailure::Suspended);
+        let err = token.header().await.unwrap_err();
+        assert!(matches!(err, AuthError::InstallationSuspended(2)));
+        assert!(!err.is_retryable());
+
+        github.fail_next_token_request(MockFailure::Status(404));
+        let err = token.header().await.unwrap_err();
+        assert!(matches!(err, AuthError::InstallationNotFound(_)));
+        assert_eq!(err.to_string(), "app is not installed on installation 2");
+
+        token.header().await.unwrap();
+        assert_eq!(github.token_requests(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::har::{HarRequest, HarResponse};
+use crate::{AuthError, HarRecorder, HttpClient, HttpFuture};
+use chrono::Utc;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// An [`HttpClient`] that sends requests with another client and
+/// records each exchange, for creating fixtures for [`ReplayClient`].
+///
+/// Exchanges are recorded with a [`HarRecorder`], so credentials are
+/// redacted: the `Authorization` header, and token fields in JSON
+/// bodies. Run a test once against GitHub with real credentials, write
+/// the capture with [`HarRecorder::write_to`], review it, and commit it
+/// as a fixture.
+pub struct RecordingClient<C> {
+    inner: C,
+    recorder: HarRecorder,
+}
+
+impl<C: HttpClient> RecordingClient<C> {
+    /// Create a client that sends requests with `inner`.
+    pub fn new(inner: C) -> Self {
+        RecordingClient {
+            inner,
+            recorder: HarRecorder::new(),
+        }
+    }
+
+    /// The recorded exchanges.
+    pub fn recorder(&self) -> &HarRecorder {
+        &self.recorder
+    }
+}
+
+impl<C: HttpClient> HttpClient for RecordingClient<C> {
+    fn send(&self, request: http::Request<Vec<u8>>) -> HttpFuture<'_> {
+        Box::pin(async move {
+            let method = request.method().clone();
+            let url = Url::parse(&request.uri().to_string())
+                .map_err(|err| AuthError::HttpClientError(err.into()))?;
+            let headers = request.headers().clone();
+            let body = request.body().clone();
+            let started = Utc::now();
+            let start = Instant::now();
+            let result = self.inner.send(request).await;
+            let har_request = HarRequest {
+                method: &method,
+                url: &url,
+                headers: &headers,
+                body: (!body.is_empty()).then_some(body.as_slice()),
+            };
+            let har_response = result.as_ref().ok().map(|resp| HarResponse {
+                status: resp.status(),
+                headers: resp.headers(),
+                body: resp.body(),
+            });
+            self.recorder.record(
+                started,
+                start.elapsed(),
+                har_request,
+                har_response,
+            );
+            result
+        })
+    }
+}
+
+/// An [`HttpClient`] that answers requests with the responses in a
+/// HAR capture, such as one written from a [`RecordingClient`], so
+/// that tests can run against real GitHub responses without
+/// credentials or network access.
+///
+/// Requests must be made in the order they were recorded, with the
+/// same method and the same path and query; the host is ignored, so
+/// a capture from `api.github.com` can be replayed whatever
+/// [`base_url`](crate::GithubAuthParams::base_url) is set. Any other
+/// request fails with [`AuthError::HttpClientError`]. Responses are
+/// returned as recorded: tokens are `[redacted]`, and expiry times
+/// are those of the original tokens.
+pub struct ReplayClient {
+    entries: Mutex<VecDeque<Value>>,
+}
+
+impl ReplayClient {
+    /// Create a client that replays the entries of a HAR document.
+    pub fn from_har(har: &Value) -> Result<Self, AuthError> {
+        let entries = har["log"]["entries"].as_array().ok_or_else(|| {
+            AuthError::InvalidConfig("HAR document has no entries".into())
+        })?;
+        Ok(ReplayClient {
+            entries: Mutex::new(entries.iter().cloned().collect()),
+        })
+    }
+
+    /// Create a client that replays the entries of a HAR file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let har = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|err| AuthError::InvalidConfig(err.to_string()))?;
+        Self::from_har(&har)
+    }
+
+    /// Number of recorded exchanges that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn respond(
+        &self,
+        request: &http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Vec<u8>>, String> {
+        let target = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |target| target.as_str());
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.front().ok_or_else(|| {
+            format!("no recorded response for {} {}", request.method(), target)
+        })?;
+        let recorded_url = entry["request"]["url"].as_str().unwrap_or_default();
+        let recorded_target = Url::parse(recorded_url)
+            .map(|url| match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            })
+            .unwrap_or_default();
+        let recorded_method = entry["request"]["method"].as_str();
+        if recorded_method != Some(request.method().as_str())
+            || recorded_target != target
+        {
+            return Err(format!(
+                "expected {} {}, got {} {}",
+                recorded_method.unwrap_or_default(),
+                recorded_target,
+                request.method(),
+                target
+            ));
+        }
+        let entry = entries.pop_front().unwrap();
+        let response = &entry["response"];
+        let status = response["status"].as_u64().unwrap_or_default();
+        if status == 0 {
+            return Err(format!("recorded request to {} failed", target));
+        }
+        let mut headers = HeaderMap::new();
+        for header in response["headers"].as_array().into_iter().flatten() {
+            let name = header["name"].as_str().unwrap_or_default();
+            let value = header["value"].as_str().unwrap_or_default();
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        let body = response["content"]["text"].as_str().unwrap_or_default();
+        let mut builder = http::Response::builder().status(status as u16);
+        if let Some(builder_headers) = builder.headers_mut() {
+            *builder_headers = headers;
+        }
+        builder
+            .body(body.as_bytes().to_vec())
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl HttpClient for ReplayClient {
+    fn send(&self, request: http::Request<Vec<u8>>) -> HttpFuture<'_> {
+        let result = self
+            .respond(&request)
+            .map_err(|err| AuthError::HttpClientError(err.into()));
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch_installation_token;
+    use crate::test_util::MockGithub;
+
+    #[tokio::test]
+    async fn test_record_replay() {
+        let github = MockGithub::start().await.unwrap();
+        let client = RecordingClient::new(reqwest::Client::new());
+        let recorded = fetch_installation_token(&client, &github.params())
+            .await
+            .unwrap();
+        assert_eq!(recorded.token, "mock-token-1");
+        assert_eq!(client.recorder().len(), 1);
+
+        let dir = std::env::temp_dir()
+            .join(format!("github-app-auth-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("token.har");
+        client.recorder().write_to(&path).unwrap();
+        assert!(!std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("mock-token-1"));
+
+        // The host is ignored, so the fixture replays against any
+        // base URL.
+        let replay = ReplayClient::from_file(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let params = crate::GithubAuthParams {
+            base_url: Some("https://ghe.example.com".into()),
+            ..github.params()
+        };
+        let state = fetch_installation_token(&replay, &params).await.unwrap();
+        assert_eq!(state.token, "[redacted]");
+        assert_eq!(state.expires_at, recorded.expires_at);
+        assert_eq!(replay.remaining(), 0);
+        assert!(matches!(
+            fetch_installation_token(&replay, &params).await,
+            Err(AuthError::HttpClientError(_))
+        ));
+        assert_eq!(github.token_requests(), 1);
+
+        // Requests that differ from the recording are rejected.
+        let replay =
+            ReplayClient::from_har(&client.recorder().to_json()).unwrap();
+        let params = crate::GithubAuthParams {
+            installation_id: 3,
+            ..params
+        };
+        let err = fetch_installation_token(&replay, &params)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("expected POST"), "{}", err);
+        assert_eq!(replay.remaining(), 1);
+    }
+}
@@ -0,0 +1,2376 @@
+use crate::diagnostics::diagnose;
+use crate::guard::revoke_token;
+use crate::installations::{
+    get_installation, get_installation_at, next_page_url,
+};
+use crate::jwt_cache::CachedJwt;
+use crate::key::{
+    decode_private_key, parse_private_key, wipe, wipe_decoded, zero,
+};
+use crate::permissions::missing_permissions;
+use crate::response::{check_status, send};
+use crate::retry::random_fraction;
+use crate::signer::{app_jwt, sign_with};
+use crate::{
+    sign_jwt, AuthError, AuthEvent, DeprecationNotice, GithubAuthParams,
+    Installation, InstallationAccessTokenBuilder, MissingPermission,
+    Permissions, RateLimit, RateLimitThreshold, Repository,
+    RepositorySelection, TokenCacheKey, TokenScope, TokenSnapshot, GITHUB_JSON,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures_channel::mpsc;
+use futures_util::future::{self, Either};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time;
+
+/// Default for [`InstallationAccessToken::refresh_safety_margin`].
+const DEFAULT_REFRESH_SAFETY_MARGIN: Duration = Duration::minutes(1);
+
+/// Number of tokens kept by [`InstallationAccessToken::scoped`].
+const MAX_SCOPED_TOKENS: usize = 64;
+
+/// This is the structure of the JSON object returned when requesting
+/// an installation access token.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+pub(crate) struct RawInstallationAccessToken {
+    pub(crate) token: String,
+    pub(crate) expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) permissions: Permissions,
+    #[serde(default)]
+    pub(crate) repositories: Vec<Repository>,
+    #[serde(default)]
+    pub(crate) repository_selection: Option<RepositorySelection>,
+    /// Rate limit headers of the response, if any.
+    #[serde(skip)]
+    pub(crate) rate_limit: Option<RateLimit>,
+}
+
+/// Use the app private key to generate a JWT and use the JWT to get
+/// an installation access token.
+///
+/// If GitHub rejects the JWT and fallback keys are configured, each
+/// fallback key is tried in turn. If the API can't be reached and
+/// fallback base URLs are configured, each of those is tried in turn.
+/// If a retry policy is configured, the whole sequence is retried
+/// with backoff. If all of them are still down and an outage queue is
+/// configured, the request waits in the queue.
+///
+/// Reference:
+/// developer.github.com/apps/building-github-apps/authenticating-with-github-apps
+async fn get_installation_token(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    deadline: Option<time::Instant>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    let fetch = async {
+        let start = time::Instant::now();
+        let attempt =
+            || get_installation_token_retrying(client, params, scope, deadline);
+        let result = match &params.outage_queue {
+            Some(queue) => queue.run(deadline, attempt).await,
+            None => attempt().await,
+        };
+        record!(latency_ms = start.elapsed().as_millis());
+        if let Ok(raw) = &result {
+            record!(expires_at = raw.expires_at);
+        }
+        result
+    };
+    instrument!(
+        fetch,
+        "fetch_installation_token",
+        app_id = params.app_id,
+        installation_id = params.installation_id,
+        expires_at,
+        latency_ms,
+        attempts
+    )
+    .await
+}
+
+/// Get an installation access token from
+/// [`GithubAuthParams::token_cache`] if it holds one that expires
+/// after `min_expires_at` and isn't `stale`, or else fetch one with
+/// [`get_installation_token`] and store it in the cache.
+async fn get_shared_installation_token(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    deadline: Option<time::Instant>,
+    min_expires_at: DateTime<Utc>,
+    stale: Option<&str>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    let Some(cache) = &params.token_cache else {
+        return get_installation_token(client, params, scope, deadline).await;
+    };
+    let key = TokenCacheKey {
+        scope: scope.clone(),
+        ..TokenCacheKey::new(params)
+    };
+    match cache.get(&key).await {
+        Ok(Some(state))
+            if state.expires_at > min_expires_at
+                && Some(state.token.as_str()) != stale =>
+        {
+            debug!("using installation token {} from cache", key);
+            return Ok(state.into());
+        }
+        Ok(_) => {}
+        Err(err) => warn!("failed to read token cache: {}", err),
+    }
+    let raw = get_installation_token(client, params, scope, deadline).await?;
+    let state = raw.to_state(params.installation_id);
+    if let Err(err) = cache.put(&key, &state).await {
+        warn!("failed to store token in cache: {}", err);
+    }
+    Ok(raw)
+}
+
+async fn get_installation_token_retrying(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    deadline: Option<time::Instant>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    match &params.retry_policy {
+        Some(policy) => {
+            policy
+                .run(deadline, params.retry_budget.as_ref(), || {
+                    get_installation_token_once(client, params, scope, deadline)
+                })
+                .await
+        }
+        None => {
+            get_installation_token_once(client, params, scope, deadline).await
+        }
+    }
+}
+
+async fn get_installation_token_once(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    deadline: Option<time::Instant>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    if let Some(deadline) = deadline {
+        remaining_time(deadline)?;
+    }
+    let mut result = get_installation_token_with_keys(
+        client,
+        params,
+        scope,
+        params.base_url_or_default(),
+        deadline,
+    )
+    .await;
+    for base_url in &params.fallback_base_urls {
+        match &result {
+            Err(err) if is_unavailable(err) => {
+                warn!("API unavailable ({}), trying {}", err, base_url);
+                result = get_installation_token_with_keys(
+                    client, params, scope, base_url, deadline,
+                )
+                .await;
+            }
+            _ => break,
+        }
+    }
+    result
+}
+
+/// Whether an error means the API frontend is down, rather than that
+/// the request itself was rejected.
+pub(crate) fn is_unavailable(err: &AuthError) -> bool {
+    if err.is_connection_error() {
+        return true;
+    }
+    match err {
+        AuthError::ReqwestError(err) => {
+            err.status().is_some_and(|status| status.is_server_error())
+        }
+        AuthError::ServiceUnavailable { .. } => true,
+        AuthError::GithubApi { status, .. } => status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Get an installation access token from the API at `base_url`,
+/// trying each of the private keys, or signing the JWT with
+/// [`GithubAuthParams::signer`] if set.
+async fn get_installation_token_with_keys(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    base_url: &str,
+    deadline: Option<time::Instant>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    let primary = primary_jwt(params).await?;
+    let mut result = request_installation_token(
+        client,
+        params,
+        scope,
+        base_url,
+        primary.jwt,
+        primary.pem.as_deref(),
+        deadline,
+    )
+    .await;
+    let rejected = |result: &Result<_, AuthError>| matches!(result, Err(err) if err.status() == Some(StatusCode::UNAUTHORIZED));
+    if rejected(&result) {
+        if let Some(cache) = &params.jwt_cache {
+            cache.clear();
+        }
+    }
+    let fallback_keys = match params.insecure_hs256_secret {
+        Some(_) => &[],
+        None if params.signer.is_some() => &[],
+        None => params.fallback_private_keys.as_slice(),
+    };
+    for fallback_key in fallback_keys {
+        if !rejected(&result) {
+            break;
+        }
+        warn!("JWT rejected, retrying with the next private key");
+        let key = parse_private_key(fallback_key)?;
+        let pem = decode_private_key(fallback_key);
+        result = request_installation_token(
+            client,
+            params,
+            scope,
+            base_url,
+            sign_jwt(params, &key)?,
+            Some(&pem),
+            deadline,
+        )
+        .await;
+        wipe_decoded(pem);
+    }
+    result
+}
+
+/// Sign a JWT with the primary private key or the signer, or reuse one
+/// from [`GithubAuthParams::jwt_cache`].
+async fn primary_jwt(
+    params: &GithubAuthParams,
+) -> Result<CachedJwt, AuthError> {
+    if let Some(cached) = params.jwt_cache.as_ref().and_then(|c| c.get(params))
+    {
+        return Ok(cached);
+    }
+    let (jwt, key) = match &params.signer {
+        Some(signer) => {
+            let jwt = CachedJwt {
+                jwt: sign_with(params, signer.as_ref()).await?,
+                pem: None,
+            };
+            (jwt, None)
+        }
+        None => {
+            // Parse the PEM that was already loaded, rather than have
+            // signing_key() read the key file a second time.
+            let pem = params.load_primary_private_key_pem().await?;
+            let key = match &pem {
+                Some(pem) => {
+                    Cow::Owned(jsonwebtoken::EncodingKey::from_rsa_pem(pem)?)
+                }
+                None => params.signing_key()?,
+            };
+            let jwt = CachedJwt {
+                jwt: sign_jwt(params, &key)?,
+                pem: pem.map(Cow::into_owned),
+            };
+            (jwt, Some(key.into_owned()))
+        }
+    };
+    if let Some(cache) = &params.jwt_cache {
+        cache.insert(params, &jwt, key);
+    }
+    Ok(jwt)
+}
+
+/// Get the time left until `deadline`, or an error if it has passed.
+fn remaining_time(
+    deadline: time::Instant,
+) -> Result<time::Duration, AuthError> {
+    let remaining = deadline.saturating_duration_since(time::Instant::now());
+    if remaining.is_zero() {
+        return Err(AuthError::DeadlineExceeded);
+    }
+    Ok(remaining)
+}
+
+async fn request_installation_token(
+    client: &reqwest::Client,
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    base_url: &str,
+    token: String,
+    private_key_pem: Option<&[u8]>,
+    deadline: Option<time::Instant>,
+) -> Result<RawInstallationAccessToken, AuthError> {
+    if params.verify_jwt {
+        let problems = diagnose(params, &token, private_key_pem);
+        if !problems.is_empty() {
+            return Err(AuthError::InvalidJwt(problems));
+        }
+    }
+
+    let url = format!(
+        "{}/app/installations/{}/access_tokens",
+        base_url.trim_end_matches('/'),
+        params.installation_id
+    );
+    let mut request = params.api_headers(client.post(&url).bearer_auth(token));
+    if !scope.is_empty() {
+        request = request.json(scope);
+    }
+    if let Some(deadline) = deadline {
+        request = request.timeout(remaining_time(deadline)?);
+    }
+    let result: Result<_, AuthError> = async {
+        let resp = send(params, request).await?;
+        if let Some(notice) = DeprecationNotice::from_response(&resp) {
+            params.notify(AuthEvent::DeprecationNotice { notice: &notice });
+        }
+        let rate_limit = RateLimit::from_response(&resp);
+        let mut raw: RawInstallationAccessToken =
+            check_status(resp).await?.json().await?;
+        raw.rate_limit = rate_limit;
+        check_granted_scope(params, scope, &raw);
+        Ok(raw)
+    }
+    .await;
+    match result {
+        Err(AuthError::Timeout(_)) if deadline.is_some() => {
+            Err(AuthError::DeadlineExceeded)
+        }
+        Err(err) => Err(classify_installation_error(params, err)),
+        result => result,
+    }
+}
+
+/// Recognize token request failures caused by the installation being
+/// gone or suspended, so that callers can stop asking for tokens for
+/// it.
+fn classify_installation_error(
+    params: &GithubAuthParams,
+    err: AuthError,
+) -> AuthError {
+    match &err {
+        AuthError::GithubApi {
+            status: StatusCode::NOT_FOUND,
+            ..
+        } => AuthError::InstallationNotFound(format!(
+            "installation {}",
+            params.installation_id
+        )),
+        AuthError::GithubApi {
+            status: StatusCode::FORBIDDEN,
+            message,
+            ..
+        } if message.contains("suspended") => {
+            AuthError::InstallationSuspended(params.installation_id)
+        }
+        _ => err,
+    }
+}
+
+/// Warn and notify the observer if GitHub granted a scoped token less
+/// than `scope` asked for.
+fn check_granted_scope(
+    params: &GithubAuthParams,
+    scope: &TokenScope,
+    raw: &RawInstallationAccessToken,
+) {
+    let missing_permissions = match &scope.permissions {
+        Some(requested) => missing_permissions(requested, &raw.permissions),
+        None => Vec::new(),
+    };
+    let missing_repositories: Vec<String> = scope
+        .repositories
+        .iter()
+        .filter(|name| {
+            !raw.repositories
+                .iter()
+                .any(|repo| repo.name.eq_ignore_ascii_case(name))
+        })
+        .cloned()
+        .collect();
+    let missing_repository_ids: Vec<u64> = scope
+        .repository_ids
+        .iter()
+        .filter(|id| !raw.repositories.iter().any(|repo| repo.id == **id))
+        .copied()
+        .collect();
+    if missing_permissions.is_empty()
+        && missing_repositories.is_empty()
+        && missing_repository_ids.is_empty()
+    {
+        return;
+    }
+    warn!(
+        "installation {} granted less than the requested scope \
+         (permissions: {}; repositories: {:?}; repository IDs: {:?})",
+        params.installation_id,
+        format_reduced(&missing_permissions),
+        missing_repositories,
+        missing_repository_ids
+    );
+    params.notify(AuthEvent::ScopeNotGranted {
+        installation_id: params.installation_id,
+        missing_permissions: &missing_permissions,
+        missing_repositories: &missing_repositories,
+        missing_repository_ids: &missing_repository_ids,
+    });
+}
+
+/// Format permission downgrades as `name: old -> new`.
+fn format_reduced(reduced: &[MissingPermission]) -> String {
+    reduced
+        .iter()
+        .map(|p| match p.granted {
+            Some(granted) => {
+                format!("{}: {} -> {}", p.name, p.required, granted)
+            }
+            None => format!("{}: {} -> none", p.name, p.required),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// An installation access token is the primary method for
+/// authenticating with the GitHub API as an application.
+///
+/// # Cancellation
+///
+/// All async methods are cancel-safe: the token is only updated once
+/// a refresh has completed, so dropping an in-flight call (for
+/// example in `tokio::select!` during shutdown) leaves the current
+/// token in place. [`header_or_cancel`](Self::header_or_cancel) wraps
+/// this for the common case.
+pub struct InstallationAccessToken {
+    /// The [`reqwest::Client`] used to periodically refresh
+    /// the token.
+    ///
+    /// This is made public so that users of the library can re-use
+    /// this client for sending requests, but this is not required.
+    pub client: reqwest::Client,
+
+    /// This time is subtracted from the expiration time to make it less
+    /// likely that the token goes out of date just as a request is
+    /// sent.
+    pub refresh_safety_margin: Duration,
+
+    /// If set, each token is refreshed up to this much earlier than
+    /// [`refresh_safety_margin`](Self::refresh_safety_margin) alone
+    /// would call for, by a random amount chosen whenever a token is
+    /// fetched. This spreads out the refreshes of replicas that
+    /// started at the same time, rather than having the whole fleet
+    /// refresh in the same second. Defaults to zero.
+    pub refresh_jitter: Duration,
+
+    /// What to do if a refresh fails while the current token has not
+    /// yet actually expired. Defaults to
+    /// [`StaleTokenPolicy::Fail`].
+    pub stale_token_policy: StaleTokenPolicy,
+
+    /// If true, [`header`](Self::header) returns all the headers that
+    /// GitHub expects on API requests, not just `Authorization`. This
+    /// makes it harder to forget a required header at a request site.
+    /// Defaults to false.
+    pub include_standard_headers: bool,
+
+    /// Scheme of the `Authorization` header built by
+    /// [`header`](Self::header) and the other header helpers.
+    /// Defaults to [`AuthScheme::Token`].
+    pub auth_scheme: AuthScheme,
+
+    /// If set, [`record_response`](Self::record_response) sends an
+    /// [`AuthEvent::RateLimitLow`] event to the
+    /// [observer](GithubAuthParams::observer) when the remaining rate
+    /// limit drops below this level, so that non-critical work can be
+    /// deferred before requests start failing. Defaults to `None`.
+    pub rate_limit_threshold: Option<RateLimitThreshold>,
+
+    pub(crate) token: String,
+    pub(crate) expires_at: DateTime<Utc>,
+    pub(crate) permissions: Permissions,
+    pub(crate) repositories: Vec<Repository>,
+    pub(crate) repository_selection: Option<RepositorySelection>,
+    pub(crate) params: Arc<GithubAuthParams>,
+    pub(crate) scope: TokenScope,
+    pub(crate) minted_at: DateTime<Utc>,
+    pub(crate) refresh_count: u64,
+    pub(crate) last_fetch_duration: time::Duration,
+    pub(crate) request_count: u64,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) last_refresh_failed: bool,
+    pub(crate) refresh_failures: u64,
+    pub(crate) jitter_fraction: f64,
+    pub(crate) last_refresh_failure: Option<DateTime<Utc>>,
+    pub(crate) failure_log: FailureLog,
+    pub(crate) update_senders: Vec<mpsc::UnboundedSender<TokenSnapshot>>,
+    pub(crate) installation: Option<Installation>,
+    pub(crate) scoped_tokens: HashMap<TokenScope, InstallationAccessToken>,
+}
+
+/// The token is redacted, as is the private key in the parameters, so
+/// the token can be logged.
+impl fmt::Debug for InstallationAccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstallationAccessToken")
+            .field("token", &"[redacted]")
+            .field("expires_at", &self.expires_at)
+            .field("permissions", &self.permissions)
+            .field("repository_selection", &self.repository_selection)
+            .field("refresh_safety_margin", &self.refresh_safety_margin)
+            .field("refresh_jitter", &self.refresh_jitter)
+            .field("auth_scheme", &self.auth_scheme)
+            .field("refresh_count", &self.refresh_count)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A one-line summary of the token, without the token itself, for
+/// example `installation 5678 of app 1234, expires at 2024-01-01
+/// 12:00:00 UTC`.
+impl fmt::Display for InstallationAccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "installation {} of app {}, ",
+            self.params.installation_id, self.params.app_id
+        )?;
+        if self.params.now() >= self.expires_at {
+            write!(f, "expired at {}", self.expires_at)
+        } else {
+            write!(f, "expires at {}", self.expires_at)?;
+            if self.needs_refresh() {
+                write!(f, " (due for refresh)")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The token and the token's copies of the private keys, including
+/// the fallback keys and any HS256 secret, are overwritten with zeros
+/// when dropped, and the old token is overwritten when a refresh
+/// replaces it. The caller's own [`GithubAuthParams`] are not
+/// affected.
+impl Drop for InstallationAccessToken {
+    fn drop(&mut self) {
+        wipe(mem::take(&mut self.token).into_bytes());
+        // Scoped tokens share the parameters, so drop them first and
+        // wipe the key once the last reference is gone.
+        self.scoped_tokens.clear();
+        if let Some(params) = Arc::get_mut(&mut self.params) {
+            wipe_secrets(params);
+        }
+    }
+}
+
+/// Overwrite the private keys and HS256 secret in `params` with zeros.
+fn wipe_secrets(params: &mut GithubAuthParams) {
+    zero(&mut params.private_key);
+    for key in &mut params.fallback_private_keys {
+        zero(key);
+    }
+    if let Some(secret) = &mut params.insecure_hs256_secret {
+        zero(secret);
+    }
+}
+
+impl InstallationAccessToken {
+    /// Start building a token, as an alternative to filling in
+    /// [`GithubAuthParams`] directly. See
+    /// [`InstallationAccessTokenBuilder`].
+    pub fn builder() -> InstallationAccessTokenBuilder {
+        InstallationAccessTokenBuilder::default()
+    }
+
+    /// Fetch an installation access token using the provided
+    /// authentication parameters.
+    pub async fn new(
+        params: GithubAuthParams,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        let client = params.build_client()?;
+        let start = time::Instant::now();
+        let min_expires_at = params.now() + DEFAULT_REFRESH_SAFETY_MARGIN;
+        let raw = get_shared_installation_token(
+            &client,
+            &params,
+            &params.scope,
+            None,
+            min_expires_at,
+            None,
+        )
+        .await
+        .map_err(|error| params.notify_failure(error))?;
+        let missing =
+            missing_permissions(&params.required_permissions, &raw.permissions);
+        if !missing.is_empty() {
+            return Err(AuthError::MissingPermissions(missing));
+        }
+        params.notify(AuthEvent::TokenMinted {
+            installation_id: params.installation_id,
+            expires_at: raw.expires_at,
+        });
+        let mut token =
+            InstallationAccessToken::from_parts(client, params, raw);
+        token.last_fetch_duration = start.elapsed();
+        Ok(token)
+    }
+
+    /// Fetch an installation access token using `client` for the token
+    /// request, for later refreshes, and as the public
+    /// [`client`](Self::client) field. This is for clients that need a
+    /// proxy, custom root certificates, or timeouts; it is the same as
+    /// setting [`GithubAuthParams::http_client`].
+    ///
+    /// The client's user agent is used as is, so it should be set when
+    /// building the client, since GitHub rejects requests without one.
+    pub async fn with_client(
+        client: reqwest::Client,
+        params: GithubAuthParams,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        InstallationAccessToken::new(GithubAuthParams {
+            http_client: Some(client),
+            ..params
+        })
+        .await
+    }
+
+    /// Fetch an installation access token for the repository that a
+    /// GitHub Actions workflow is running in.
+    ///
+    /// The repository is read from the `GITHUB_REPOSITORY` environment
+    /// variable, which Actions sets automatically, and the token is
+    /// fetched with [`for_repo`](Self::for_repo). This means only the
+    /// app ID (or client ID) and private key need to be configured;
+    /// `params.installation_id` is ignored.
+    pub async fn from_actions_env(
+        params: GithubAuthParams,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        let repo = env::var("GITHUB_REPOSITORY").map_err(|_| {
+            AuthError::MissingEnvVar("GITHUB_REPOSITORY".into())
+        })?;
+        InstallationAccessToken::for_repo(params, &repo).await
+    }
+
+    /// Fetch an installation access token for the app's installation
+    /// on a repository, given as "owner/name".
+    ///
+    /// The installation is looked up with the app JWT, so
+    /// `params.installation_id` is ignored and deployments don't need
+    /// to hardcode it. Returns [`AuthError::InstallationNotFound`] if
+    /// the app is not installed on the repository.
+    pub async fn for_repo(
+        params: GithubAuthParams,
+        repo: &str,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        let path = format!("/repos/{}/installation", repo);
+        InstallationAccessToken::for_installation_at(params, repo, &path).await
+    }
+
+    /// Fetch an installation access token for the app's installation
+    /// on an organization. See [`for_repo`](Self::for_repo).
+    pub async fn for_org(
+        params: GithubAuthParams,
+        org: &str,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        let path = format!("/orgs/{}/installation", org);
+        InstallationAccessToken::for_installation_at(params, org, &path).await
+    }
+
+    async fn for_installation_at(
+        mut params: GithubAuthParams,
+        name: &str,
+        path: &str,
+    ) -> Result<InstallationAccessToken, AuthError> {
+        let client = params.build_client()?;
+        let installation = get_installation_at(&client, &params, path)
+            .await
+            .map_err(|err| match err.status() {
+                Some(StatusCode::NOT_FOUND) => {
+                    AuthError::InstallationNotFound(name.to_string())
+                }
+                _ => err,
+            })?;
+        info!("found installation {} for {}", installation.id, name);
+        params.installation_id = installation.id;
+        let mut token = InstallationAccessToken::new(params).await?;
+        token.installation = Some(installation);
+        Ok(token)
+    }
+
+    pub(crate) fn from_parts(
+        client: reqwest::Client,
+        params: impl Into<Arc<GithubAuthParams>>,
+        raw: RawInstallationAccessToken,
+    ) -> InstallationAccessToken {
+        let params = params.into();
+        let minted_at = params.now();
+        InstallationAccessToken {
+            client,
+            token: raw.token,
+            expires_at: raw.expires_at,
+            permissions: raw.permissions,
+            repositories: raw.repositories,
+            repository_selection: raw.repository_selection,
+            scope: params.scope.clone(),
+            params,
+            refresh_safety_margin: DEFAULT_REFRESH_SAFETY_MARGIN,
+            refresh_jitter: Duration::zero(),
+            jitter_fraction: random_fraction(),
+            stale_token_policy: StaleTokenPolicy::default(),
+            include_standard_headers: false,
+            auth_scheme: AuthScheme::default(),
+            rate_limit_threshold: None,
+            minted_at,
+            refresh_count: 0,
+            last_fetch_duration: time::Duration::ZERO,
+            request_count: 0,
+            rate_limit: raw.rate_limit,
+            last_refresh_failed: false,
+            refresh_failures: 0,
+            last_refresh_failure: None,
+            failure_log: FailureLog::default(),
+            update_senders: Vec::new(),
+            installation: None,
+            scoped_tokens: HashMap::new(),
+        }
+    }
+
+    /// Create a token with a fixed value and expiration time, without
+    /// making any requests, for unit tests of code that takes an
+    /// [`InstallationAccessToken`].
+    ///
+    /// The token has default [`GithubAuthParams`], so refreshing it
+    /// fails; pick an `expires_at` far enough in the future, or use
+    /// [`MockGithub`](crate::test_util::MockGithub) to test refreshes.
+    /// Requires the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn for_tests(
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> InstallationAccessToken {
+        InstallationAccessToken::from_parts(
+            reqwest::Client::new(),
+            GithubAuthParams::default(),
+            RawInstallationAccessToken {
+                token: token.into(),
+                expires_at,
+                permissions: Permissions::default(),
+                repositories: Vec::new(),
+                repository_selection: None,
+                rate_limit: None,
+            },
+        )
+    }
+
+    /// Get an HTTP authentication header for the installation access
+    /// token.
+    ///
+    /// This method is mutable because the installation access token
+    /// must be periodically refreshed.
+    ///
+    /// If [`include_standard_headers`] is set, the returned headers
+    /// also include `Accept`, `X-GitHub-Api-Version`, and `User-Agent`.
+    ///
+    /// [`include_standard_headers`]: Self::include_standard_headers
+    pub async fn header(&mut self) -> Result<HeaderMap, AuthError> {
+        self.refresh_if_needed().await?;
+        self.build_headers()
+    }
+
+    /// Like [`header`](Self::header), but if the token needs to be
+    /// refreshed, give up on the refresh once `deadline` passes and
+    /// return [`AuthError::DeadlineExceeded`].
+    ///
+    /// This keeps an inline refresh within the caller's own request
+    /// budget. If the deadline has already passed and a refresh is
+    /// needed, the error is returned without sending a request. As
+    /// with any refresh failure, the [`stale_token_policy`] may allow
+    /// the current token to be returned instead.
+    ///
+    /// [`stale_token_policy`]: Self::stale_token_policy
+    pub async fn header_with_deadline(
+        &mut self,
+        deadline: time::Instant,
+    ) -> Result<HeaderMap, AuthError> {
+        self.refresh_before(Some(deadline)).await?;
+        self.build_headers()
+    }
+
+    /// Like [`header`](Self::header), but stop waiting for an
+    /// in-flight refresh as soon as `cancel` completes and return
+    /// [`AuthError::Cancelled`].
+    ///
+    /// `cancel` can be any future, for example a shutdown signal or
+    /// `CancellationToken::cancelled()` from `tokio-util`. The
+    /// current token is left unchanged if the refresh is cancelled.
+    pub async fn header_or_cancel(
+        &mut self,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<HeaderMap, AuthError> {
+        let header = pin!(self.header());
+        let cancel = pin!(cancel);
+        match future::select(header, cancel).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(AuthError::Cancelled),
+        }
+    }
+
+    /// Like [`header`](Self::header), but return the headers as
+    /// name/value pairs rather than a [`HeaderMap`].
+    ///
+    /// `HeaderMap` is the type from the `http` 0.2 crate that
+    /// `reqwest` 0.11 uses. Code built on `http` 1.x, or on any other
+    /// HTTP library, can use the pairs directly. Names are static and
+    /// lowercase, so they can be passed to `HeaderName::from_static`,
+    /// and each value is an owned `String` that
+    /// `HeaderValue::try_from` takes without copying:
+    ///
+    /// ```no_run
+    /// # use github_app_auth::InstallationAccessToken;
+    /// # async fn wrapper(mut token: InstallationAccessToken) {
+    /// for (name, value) in token.header_pairs().await.unwrap() {
+    ///     println!("{}: {}", name, value);
+    /// }
+    /// # }
+    /// ```
+    pub async fn header_pairs(
+        &mut self,
+    ) -> Result<Vec<(&'static str, String)>, AuthError> {
+        self.refresh_if_needed().await?;
+        Ok(self.build_header_pairs())
+    }
+
+    /// Get the value of the `Authorization` header for the token,
+    /// refreshing it first if needed. The value is marked as
+    /// sensitive.
+    ///
+    /// This is for HTTP clients that take the header value on its own,
+    /// rather than a [`HeaderMap`].
+    pub async fn header_value(&mut self) -> Result<HeaderValue, AuthError> {
+        self.refresh_if_needed().await?;
+        self.build_header_value()
+    }
+
+    fn build_header_value(&self) -> Result<HeaderValue, AuthError> {
+        let mut value = HeaderValue::try_from(self.authorization())?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    fn authorization(&self) -> String {
+        format!("{} {}", self.auth_scheme.as_str(), self.token)
+    }
+
+    fn build_header_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![("authorization", self.authorization())];
+        if self.include_standard_headers {
+            pairs.extend([
+                ("accept", GITHUB_JSON.to_string()),
+                (
+                    "x-github-api-version",
+                    self.params.api_version_or_default().into(),
+                ),
+                ("user-agent", self.params.user_agent.clone()),
+            ]);
+        }
+        pairs
+    }
+
+    fn build_headers(&self) -> Result<HeaderMap, AuthError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.build_header_pairs() {
+            let mut value = HeaderValue::try_from(value)?;
+            value.set_sensitive(name == "authorization");
+            headers.insert(name, value);
+        }
+        Ok(headers)
+    }
+
+    /// Time at which the current token expires, as reported by
+    /// GitHub.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// Time left until the current token expires, which is negative
+    /// if it already has. The current time is taken from the
+    /// [`clock`](GithubAuthParams::clock), corrected for any
+    /// [`clock_skew`](GithubAuthParams::clock_skew).
+    pub fn time_until_expiry(&self) -> Duration {
+        self.expires_at - self.params.now()
+    }
+
+    /// Permissions granted to the current token. For a token limited
+    /// by [`GithubAuthParams::scope`], these are the requested
+    /// permissions rather than all of the installation's.
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Repositories the current token can access, if it was limited
+    /// to specific repositories by [`GithubAuthParams::scope`]. Empty
+    /// if the token can access all of the installation's
+    /// repositories.
+    pub fn repositories(&self) -> &[Repository] {
+        &self.repositories
+    }
+
+    /// Whether the current token can access all of the installation's
+    /// repositories or only selected ones, as reported by GitHub.
+    /// `None` for a token restored from a [`TokenState`] saved by an
+    /// older version of this crate.
+    ///
+    /// [`TokenState`]: crate::TokenState
+    pub fn repository_selection(&self) -> Option<RepositorySelection> {
+        self.repository_selection
+    }
+
+    /// Time at which the current token was fetched from GitHub, or
+    /// restored with [`from_state`](Self::from_state).
+    pub fn minted_at(&self) -> DateTime<Utc> {
+        self.minted_at
+    }
+
+    /// Installation that the token is for.
+    pub fn installation_id(&self) -> u64 {
+        self.params.installation_id
+    }
+
+    /// ID of the app that the token was minted for.
+    pub fn app_id(&self) -> u64 {
+        self.params.app_id
+    }
+
+    /// Parameters the token was created with. The `Debug` output of
+    /// the parameters leaves out the private key and other secrets,
+    /// so it is safe to log.
+    pub fn params(&self) -> &GithubAuthParams {
+        &self.params
+    }
+
+    /// Number of times the token has been refreshed since it was
+    /// first fetched by [`new`](Self::new).
+    pub fn refresh_count(&self) -> u64 {
+        self.refresh_count
+    }
+
+    /// Counts of successful and failed refreshes, for exporting
+    /// metrics about the health of authentication. See
+    /// [`RefreshStats`].
+    pub fn refresh_stats(&self) -> RefreshStats {
+        RefreshStats {
+            successes: self.refresh_count,
+            failures: self.refresh_failures,
+            consecutive_failures: self.failure_log.failures,
+            last_success_at: self.minted_at,
+            last_failure_at: self.last_refresh_failure,
+        }
+    }
+
+    /// How long the most recent token fetch took. Before the first
+    /// refresh, this is the duration of the initial fetch.
+    pub fn last_fetch_duration(&self) -> time::Duration {
+        self.last_fetch_duration
+    }
+
+    /// Number of requests built with [`request`](Self::request) and
+    /// the method helpers such as [`get`](Self::get).
+    ///
+    /// Since each token belongs to a single installation, this can be
+    /// used to attribute API usage to installations in a multi-tenant
+    /// service. Requests sent with [`client`](Self::client) and
+    /// [`header`](Self::header) directly are not counted.
+    pub fn request_count(&self) -> u64 {
+        self.request_count
+    }
+
+    /// Rate limit status from the most recent response passed to
+    /// [`record_response`](Self::record_response), or from the
+    /// response that minted or refreshed the token, whichever came
+    /// last.
+    pub fn rate_limit(&self) -> Option<&RateLimit> {
+        self.rate_limit.as_ref()
+    }
+
+    /// Record the rate limit headers of a response to a request made
+    /// with this token.
+    ///
+    /// If [`rate_limit_threshold`](Self::rate_limit_threshold) is set
+    /// and the remaining rate limit has dropped below it, an
+    /// [`AuthEvent::RateLimitLow`] event is sent. The event is sent
+    /// once per rate limit window, not for every response.
+    pub fn record_response(&mut self, resp: &reqwest::Response) {
+        let rate_limit = match RateLimit::from_response(resp) {
+            Some(rate_limit) => rate_limit,
+            None => return,
+        };
+        if let Some(threshold) = self.rate_limit_threshold {
+            let already_low = self.rate_limit.as_ref().is_some_and(|prev| {
+                prev.reset == rate_limit.reset && prev.is_below(threshold)
+            });
+            if rate_limit.is_below(threshold) && !already_low {
+                self.params.notify(AuthEvent::RateLimitLow {
+                    installation_id: self.params.installation_id,
+                    rate_limit: &rate_limit,
+                });
+            }
+        }
+        self.rate_limit = Some(rate_limit);
+    }
+
+    /// Get the installation the token is for, including the account
+    /// (user or organization) it is installed on and whether it is
+    /// suspended.
+    ///
+    /// The installation is fetched with the app JWT on the first call
+    /// and cached after that, so this is cheap to call when logging or
+    /// routing by tenant. Tokens created with
+    /// [`from_actions_env`](Self::from_actions_env) already have the
+    /// installation from the lookup, so no request is needed.
+    pub async fn installation(&mut self) -> Result<&Installation, AuthError> {
+        if self.installation.is_none() {
+            let installation = get_installation(
+                &self.client,
+                &self.params,
+                self.params.installation_id,
+            )
+            .await?;
+            self.installation = Some(installation);
+        }
+        Ok(self.installation.as_ref().unwrap())
+    }
+
+    /// Get a token for the same installation that is limited to the
+    /// repositories with the given IDs and, if set, to `permissions`.
+    ///
+    /// This is for handling a webhook from one repository with a token
+    /// that can't touch any other. The scoped token is separate from
+    /// this one, which keeps its own scope, and is kept so that later
+    /// calls with the same repositories and permissions reuse it,
+    /// refreshing it if needed. It has its own
+    /// [`header`](Self::header) and [`expires_at`](Self::expires_at),
+    /// and the same HTTP client, parameters, and refresh settings as
+    /// this token. [`GithubAuthParams::required_permissions`] is not
+    /// checked for scoped tokens.
+    ///
+    /// Expired scoped tokens are dropped on each call, and at most
+    /// 64 are kept; when that many are held, the one that expires
+    /// first is dropped to make room.
+    pub async fn scoped(
+        &mut self,
+        repository_ids: &[u64],
+        permissions: Option<Permissions>,
+    ) -> Result<&mut InstallationAccessToken, AuthError> {
+        let scope = TokenScope {
+            repositories: Vec::new(),
+            repository_ids: repository_ids.to_vec(),
+            permissions,
+        };
+        let now = self.params.now();
+        self.scoped_tokens.retain(|_, token| token.expires_at > now);
+        if self.scoped_tokens.contains_key(&scope) {
+            let token = self.scoped_tokens.get_mut(&scope).unwrap();
+            token.refresh_if_needed().await?;
+            return Ok(token);
+        }
+
+        let start = time::Instant::now();
+        let raw = get_shared_installation_token(
+            &self.client,
+            &self.params,
+            &scope,
+            None,
+            now + DEFAULT_REFRESH_SAFETY_MARGIN,
+            None,
+        )
+        .await
+        .map_err(|error| self.params.notify_failure(error))?;
+        self.params.notify(AuthEvent::TokenMinted {
+            installation_id: self.params.installation_id,
+            expires_at: raw.expires_at,
+        });
+        let mut token = InstallationAccessToken::from_parts(
+            self.client.clone(),
+            self.params.clone(),
+            raw,
+        );
+        token.scope = scope.clone();
+        token.last_fetch_duration = start.elapsed();
+        token.refresh_safety_margin = self.refresh_safety_margin;
+        token.refresh_jitter = self.refresh_jitter;
+        token.stale_token_policy = self.stale_token_policy;
+        token.include_standard_headers = self.include_standard_headers;
+        token.auth_scheme = self.auth_scheme;
+
+        if self.scoped_tokens.len() >= MAX_SCOPED_TOKENS {
+            let first_to_expire = self
+                .scoped_tokens
+                .iter()
+                .min_by_key(|(_, token)| token.expires_at)
+                .map(|(scope, _)| scope.clone());
+            if let Some(first_to_expire) = first_to_expire {
+                self.scoped_tokens.remove(&first_to_expire);
+            }
+        }
+        Ok(self.scoped_tokens.entry(scope).or_insert(token))
+    }
+
+    /// Check whether the token is due to be refreshed.
+    ///
+    /// This is true once the current time is within
+    /// [`refresh_safety_margin`], plus any [`refresh_jitter`], of
+    /// [`expires_at`]. The same check is used internally by
+    /// [`header`], so callers can use it to refresh ahead of time (for
+    /// example during idle periods) rather than on the first request
+    /// after expiry.
+    ///
+    /// [`refresh_safety_margin`]: Self::refresh_safety_margin
+    /// [`refresh_jitter`]: Self::refresh_jitter
+    /// [`expires_at`]: Self::expires_at
+    /// [`header`]: Self::header
+    pub fn needs_refresh(&self) -> bool {
+        self.next_refresh_at() <= self.params.now()
+    }
+
+    /// Time at which [`needs_refresh`](Self::needs_refresh) becomes
+    /// true, for schedulers that refresh the token explicitly with
+    /// [`refresh_if_needed`](Self::refresh_if_needed).
+    ///
+    /// This changes whenever a new token is fetched, and may already
+    /// have passed.
+    pub fn next_refresh_at(&self) -> DateTime<Utc> {
+        let jitter_ms = self.refresh_jitter.num_milliseconds() as f64;
+        let jitter =
+            Duration::milliseconds((jitter_ms * self.jitter_fraction) as i64);
+        self.expires_at - self.refresh_safety_margin - jitter
+    }
+
+    /// Refresh the token if [`needs_refresh`](Self::needs_refresh) is
+    /// true, returning whether a new token was fetched.
+    ///
+    /// This is the same conditional refresh that [`header`] performs,
+    /// so schedulers can call it from idle loops and record exactly
+    /// when refreshes happen. If the refresh fails but the
+    /// [`stale_token_policy`] allows the current token to be kept,
+    /// this returns `Ok(false)`.
+    ///
+    /// [`header`]: Self::header
+    /// [`stale_token_policy`]: Self::stale_token_policy
+    pub async fn refresh_if_needed(&mut self) -> Result<bool, AuthError> {
+        self.refresh_before(None).await
+    }
+
+    /// Fetch a new token now, even if the current one has not expired.
+    ///
+    /// GitHub can revoke tokens early, for example when the app's
+    /// permissions change, which shows up as 401 responses to requests
+    /// made with a token that should still be valid. Unlike a normal
+    /// refresh, the current token is not kept if this fails, whatever
+    /// the [`stale_token_policy`](Self::stale_token_policy).
+    pub async fn force_refresh(&mut self) -> Result<(), AuthError> {
+        self.invalidate();
+        self.refresh_if_needed().await.map(|_| ())
+    }
+
+    /// Mark the current token as expired, so that the next call to
+    /// [`header`](Self::header) or
+    /// [`refresh_if_needed`](Self::refresh_if_needed) fetches a new
+    /// one. No request is made.
+    pub fn invalidate(&mut self) {
+        self.expires_at = self.params.now();
+    }
+
+    /// If `resp` is a 401 response to a request made with this token,
+    /// [`invalidate`](Self::invalidate) the token and return true, so
+    /// the caller can retry the request with a new token.
+    pub fn handle_unauthorized(&mut self, resp: &reqwest::Response) -> bool {
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return false;
+        }
+        warn!(
+            "token for installation {} was rejected, fetching a new one",
+            self.params.installation_id
+        );
+        self.invalidate();
+        true
+    }
+
+    /// Send `request` with the token's headers, and if GitHub responds
+    /// with 401, fetch a new token and send the request once more.
+    ///
+    /// This covers a token that is revoked, or expires, between
+    /// getting the header and sending the request. The request can be
+    /// built with any client, such as [`client`](Self::client). It is
+    /// only retried if it can be cloned, which is the case unless the
+    /// body is a stream. The rate limit of the final response is
+    /// [recorded](Self::record_response), and its status is otherwise
+    /// not checked.
+    pub async fn execute(
+        &mut self,
+        request: RequestBuilder,
+    ) -> Result<reqwest::Response, AuthError> {
+        let retry = request.try_clone();
+        let headers = self.header().await?;
+        self.request_count += 1;
+        let mut resp = send(&self.params, request.headers(headers)).await?;
+        if let Some(retry) = retry {
+            if self.handle_unauthorized(&resp) {
+                let headers = self.header().await?;
+                self.request_count += 1;
+                resp = send(&self.params, retry.headers(headers)).await?;
+            }
+        }
+        self.record_response(&resp);
+        Ok(resp)
+    }
+
+    async fn refresh_before(
+        &mut self,
+        deadline: Option<time::Instant>,
+    ) -> Result<bool, AuthError> {
+        if self.needs_refresh() {
+            info!("refreshing installation token");
+            let start = time::Instant::now();
+            let over_budget = self.last_refresh_failed
+                && self
+                    .params
+                    .retry_budget
+                    .as_ref()
+                    .is_some_and(|budget| !budget.try_acquire());
+            let result = if over_budget {
+                Err(AuthError::RetryBudgetExhausted)
+            } else {
+                let refresh = get_shared_installation_token(
+                    &self.client,
+                    &self.params,
+                    &self.scope,
+                    deadline,
+                    self.params.now() + self.refresh_safety_margin,
+                    Some(&self.token),
+                );
+                instrument!(
+                    refresh,
+                    "refresh_installation_token",
+                    app_id = self.params.app_id,
+                    installation_id = self.params.installation_id,
+                    old_expires_at = self.expires_at,
+                    refresh_count = self.refresh_count
+                )
+                .await
+            };
+            self.last_refresh_failed = result.is_err();
+            let raw = match result {
+                Ok(raw) => raw,
+                Err(error) => {
+                    let error = self.params.notify_failure(error);
+                    self.refresh_failures += 1;
+                    self.last_refresh_failure = Some(self.params.now());
+                    let keep_existing = self.stale_token_policy
+                        == StaleTokenPolicy::UseUntilExpiry
+                        && self.expires_at > self.params.now();
+                    self.failure_log.failure(&error, keep_existing);
+                    if keep_existing {
+                        self.params.notify(AuthEvent::TokenExpiring {
+                            installation_id: self.params.installation_id,
+                            expires_at: self.expires_at,
+                        });
+                        return Ok(false);
+                    }
+                    return Err(error);
+                }
+            };
+            self.failure_log.success();
+            self.params.notify(AuthEvent::TokenRefreshed {
+                installation_id: self.params.installation_id,
+                expires_at: raw.expires_at,
+            });
+            let reduced =
+                missing_permissions(&self.permissions, &raw.permissions);
+            if !reduced.is_empty() {
+                warn!(
+                    "permissions of installation {} were reduced: {}",
+                    self.params.installation_id,
+                    format_reduced(&reduced)
+                );
+                self.params.notify(AuthEvent::PermissionsReduced {
+                    installation_id: self.params.installation_id,
+                    reduced: &reduced,
+                });
+            }
+            wipe(mem::replace(&mut self.token, raw.token).into_bytes());
+            self.expires_at = raw.expires_at;
+            self.permissions = raw.permissions;
+            self.repositories = raw.repositories;
+            self.repository_selection = raw.repository_selection;
+            if raw.rate_limit.is_some() {
+                self.rate_limit = raw.rate_limit;
+            }
+            self.minted_at = self.params.now();
+            self.jitter_fraction = random_fraction();
+            self.refresh_count += 1;
+            self.last_fetch_duration = start.elapsed();
+            self.publish_update();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Start building an authenticated request to the GitHub API.
+    ///
+    /// `path` is resolved against the API base URL, for example
+    /// `/repos/owner/name`. The token is refreshed first if necessary,
+    /// and the headers from [`header`](Self::header) are applied to
+    /// the request.
+    pub async fn request(
+        &mut self,
+        method: Method,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        let headers = self.header().await?;
+        self.request_count += 1;
+        Ok(self
+            .client
+            .request(method, self.params.api_url(path))
+            .headers(headers))
+    }
+
+    /// Start building an authenticated `GET` request. See
+    /// [`request`](Self::request).
+    pub async fn get(
+        &mut self,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        self.request(Method::GET, path).await
+    }
+
+    /// Start building an authenticated `POST` request. See
+    /// [`request`](Self::request).
+    pub async fn post(
+        &mut self,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        self.request(Method::POST, path).await
+    }
+
+    /// Start building an authenticated `PUT` request. See
+    /// [`request`](Self::request).
+    pub async fn put(
+        &mut self,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        self.request(Method::PUT, path).await
+    }
+
+    /// Start building an authenticated `PATCH` request. See
+    /// [`request`](Self::request).
+    pub async fn patch(
+        &mut self,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        self.request(Method::PATCH, path).await
+    }
+
+    /// Start building an authenticated `DELETE` request. See
+    /// [`request`](Self::request).
+    pub async fn delete(
+        &mut self,
+        path: &str,
+    ) -> Result<RequestBuilder, AuthError> {
+        self.request(Method::DELETE, path).await
+    }
+
+    /// List the repositories that the token can access, by calling
+    /// `GET /installation/repositories` and following pagination
+    /// links.
+    ///
+    /// Unlike [`repositories`](Self::repositories), which is only
+    /// filled in for scoped tokens, this works for any token and
+    /// reflects repositories added to or removed from the installation
+    /// since the token was fetched. The token is refreshed first if
+    /// necessary.
+    pub async fn list_repositories(
+        &mut self,
+    ) -> Result<Vec<Repository>, AuthError> {
+        #[derive(Deserialize)]
+        struct Page {
+            repositories: Vec<Repository>,
+        }
+
+        let mut next = Some(
+            self.params
+                .api_url("/installation/repositories?per_page=100"),
+        );
+        let mut repositories = Vec::new();
+        while let Some(url) = next.take() {
+            let headers = self.header().await?;
+            self.request_count += 1;
+            let request = self.client.get(url).headers(headers);
+            let resp = check_status(send(&self.params, request).await?).await?;
+            next = next_page_url(resp.headers());
+            repositories.extend(resp.json::<Page>().await?.repositories);
+        }
+        Ok(repositories)
+    }
+
+    /// Check whether the app credentials are currently valid.
+    ///
+    /// This signs a new JWT with the primary private key and uses it
+    /// to call `GET /app`. Returns `false` if GitHub rejects the JWT
+    /// (HTTP 401), which usually means the key has been revoked or
+    /// does not belong to the configured app. This is intended for
+    /// use in readiness probes.
+    pub async fn health_check(&self) -> Result<bool, AuthError> {
+        let jwt = app_jwt(&self.params).await?;
+        let request = self.params.api_headers(
+            self.client
+                .get(self.params.api_url("/app"))
+                .bearer_auth(jwt),
+        );
+        let resp = send(&self.params, request).await?;
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Ok(false);
+        }
+        check_status(resp).await?;
+        Ok(true)
+    }
+
+    /// Check whether GitHub still accepts the current token.
+    ///
+    /// This makes a cheap authenticated request
+    /// (`GET /installation/repositories?per_page=1`) with the current
+    /// token, without refreshing it first. This is useful after a
+    /// suspected revocation, since GitHub can invalidate tokens
+    /// before their expiration time.
+    ///
+    /// If the token is [rejected](TokenValidity::Rejected), it is also
+    /// [invalidated](Self::invalidate), so the next call to
+    /// [`header`](Self::header) fetches a new one.
+    pub async fn verify(&mut self) -> Result<TokenValidity, AuthError> {
+        let request = self.params.api_headers(
+            self.client
+                .get(self.params.api_url("/installation/repositories"))
+                .query(&[("per_page", "1")])
+                .header(AUTHORIZATION, self.build_header_value()?),
+        );
+        let resp = send(&self.params, request).await?;
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            self.invalidate();
+            return Ok(TokenValidity::Rejected);
+        }
+        match check_status(resp).await {
+            Ok(_) => Ok(TokenValidity::Valid),
+            Err(err) if err.status() == Some(StatusCode::FORBIDDEN) => {
+                Ok(TokenValidity::Forbidden)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Revoke the current token with `DELETE /installation/token`, so
+    /// that it stops working immediately instead of when it expires.
+    ///
+    /// This is intended for short-lived jobs that should not leave a
+    /// valid credential behind. Only the current token is revoked; if
+    /// the token was refreshed, earlier tokens remain valid until they
+    /// expire. See also [`ScopedTokenGuard`](crate::ScopedTokenGuard),
+    /// which revokes the token when dropped.
+    pub async fn revoke(self) -> Result<(), AuthError> {
+        revoke_token(&self.client, &self.params, &self.token).await?;
+        info!(
+            "revoked token for installation {}",
+            self.params.installation_id
+        );
+        self.params.notify(AuthEvent::TokenRevoked {
+            installation_id: self.params.installation_id,
+        });
+        Ok(())
+    }
+}
+
+/// Refresh counters of an [`InstallationAccessToken`], returned by
+/// [`refresh_stats`](InstallationAccessToken::refresh_stats).
+///
+/// A rising [`consecutive_failures`](Self::consecutive_failures) is
+/// the earliest sign of trouble: with
+/// [`StaleTokenPolicy::UseUntilExpiry`], requests keep working with
+/// the old token until it expires.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RefreshStats {
+    /// Number of successful refreshes, not counting the initial
+    /// fetch. The same as
+    /// [`refresh_count`](InstallationAccessToken::refresh_count).
+    pub successes: u64,
+
+    /// Number of failed refreshes, including those after which the
+    /// existing token was kept.
+    pub failures: u64,
+
+    /// Number of refreshes that have failed since the last success.
+    pub consecutive_failures: u64,
+
+    /// When the current token was fetched.
+    pub last_success_at: DateTime<Utc>,
+
+    /// When the most recent refresh failed, if any has.
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// Minimum time between log lines about repeated refresh failures.
+const FAILURE_LOG_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+/// Tracks consecutive refresh failures, so that an outage produces
+/// one warning followed by periodic summaries rather than a line for
+/// every failed request.
+#[derive(Debug, Default)]
+pub(crate) struct FailureLog {
+    failures: u64,
+    suppressed: u64,
+    last_logged: Option<time::Instant>,
+}
+
+impl FailureLog {
+    /// Record a failure, logging it unless a line was logged recently.
+    /// Returns whether it was logged.
+    fn failure(&mut self, error: &AuthError, keep_existing: bool) -> bool {
+        self.failures += 1;
+        let now = time::Instant::now();
+        if self
+            .last_logged
+            .is_some_and(|last| now - last < FAILURE_LOG_INTERVAL)
+        {
+            self.suppressed += 1;
+            return false;
+        }
+        let action = if keep_existing {
+            ", using existing token"
+        } else {
+            ""
+        };
+        if self.failures == 1 {
+            warn!("failed to refresh installation token{}: {}", action, error);
+        } else {
+            warn!(
+                "installation token refresh has failed {} times in a row \
+                 ({} not logged){}, latest error: {}",
+                self.failures, self.suppressed, action, error
+            );
+        }
+        self.suppressed = 0;
+        self.last_logged = Some(now);
+        true
+    }
+
+    /// Record a success, logging a recovery after repeated failures.
+    fn success(&mut self) {
+        if self.failures > 1 {
+            info!(
+                "installation token refresh recovered after {} failures",
+                self.failures
+            );
+        }
+        *self = FailureLog::default();
+    }
+}
+
+/// Policy for handling refresh failures when the current token is
+/// due to be refreshed but has not yet expired.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StaleTokenPolicy {
+    /// Return the refresh error to the caller.
+    #[default]
+    Fail,
+
+    /// Log a warning and keep using the current token until it
+    /// actually expires. Refreshing is attempted again on every call
+    /// to [`InstallationAccessToken::header`].
+    UseUntilExpiry,
+}
+
+/// Scheme of the `Authorization` header for an installation access
+/// token, see [`InstallationAccessToken::auth_scheme`].
+///
+/// GitHub accepts both for installation access tokens.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AuthScheme {
+    /// `Authorization: token ...`, the form used by older GitHub
+    /// documentation.
+    #[default]
+    Token,
+
+    /// `Authorization: Bearer ...`, the form GitHub now documents,
+    /// and the one expected by some generic OAuth tooling.
+    Bearer,
+}
+
+impl AuthScheme {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthScheme::Token => "token",
+            AuthScheme::Bearer => "Bearer",
+        }
+    }
+}
+
+/// Result of [`InstallationAccessToken::verify`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenValidity {
+    /// GitHub accepted the token.
+    Valid,
+
+    /// GitHub rejected the token (HTTP 401). The token has expired or
+    /// been revoked, and must be refreshed.
+    Rejected,
+
+    /// GitHub accepted the token but denied access (HTTP 403). The
+    /// token itself is fine, but the installation lacks the required
+    /// permissions.
+    Forbidden,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use reqwest::header::{ACCEPT, AUTHORIZATION, USER_AGENT};
+
+    #[test]
+    fn test_raw_installation_access_token_parse() {
+        let resp = r#"{
+            "token": "v1.1f699f1069f60xxx",
+            "expires_at": "2016-07-11T22:14:10Z",
+            "repository_selection": "selected"
+            }"#;
+        let token =
+            serde_json::from_str::<RawInstallationAccessToken>(resp).unwrap();
+        assert_eq!(
+            token,
+            RawInstallationAccessToken {
+                token: "v1.1f699f1069f60xxx".into(),
+                expires_at: Utc
+                    .with_ymd_and_hms(2016, 7, 11, 22, 14, 10)
+                    .unwrap(),
+                permissions: Permissions::default(),
+                repositories: Vec::new(),
+                repository_selection: Some(RepositorySelection::Selected),
+                rate_limit: None,
+            }
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_permissions_reduced() {
+        use crate::observer::tests::RecordingObserver;
+        use crate::test_util::MockGithub;
+        use crate::PermissionLevel;
+        use serde_json::json;
+        use std::sync::Arc;
+
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(Duration::zero());
+        github.set_permissions(Permissions {
+            contents: Some(PermissionLevel::Write),
+            issues: Some(PermissionLevel::Read),
+            ..Default::default()
+        });
+        let recorder = Arc::new(RecordingObserver::default());
+        let mut token = InstallationAccessToken::new(GithubAuthParams {
+            observer: Some(recorder.clone()),
+            ..github.params()
+        })
+        .await
+        .unwrap();
+
+        token.refresh_if_needed().await.unwrap();
+        assert!(recorder.events("permissions_reduced").is_empty());
+
+        github.set_permissions(Permissions {
+            contents: Some(PermissionLevel::Read),
+            issues: Some(PermissionLevel::Read),
+            ..Default::default()
+        });
+        token.refresh_if_needed().await.unwrap();
+        let events = recorder.events("permissions_reduced");
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0]["reduced"],
+            json!([{"name": "contents", "previous": "write", "current": "read"}])
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_scoped_token() {
+        use crate::test_util::MockGithub;
+        use crate::{PermissionLevel, TokenScope};
+
+        let github = MockGithub::start().await.unwrap();
+        github.set_permissions(Permissions {
+            contents: Some(PermissionLevel::Write),
+            issues: Some(PermissionLevel::Write),
+            ..Default::default()
+        });
+        let requested = Permissions {
+            contents: Some(PermissionLevel::Read),
+            ..Default::default()
+        };
+        let token = InstallationAccessToken::new(GithubAuthParams {
+            scope: TokenScope {
+                repositories: vec!["hello-world".into()],
+                repository_ids: vec![42],
+                permissions: Some(requested.clone()),
+            },
+            ..github.params()
+        })
+        .await
+        .unwrap();
+        assert_eq!(token.permissions(), &requested);
+        assert_eq!(token.to_state().permissions, requested);
+        let names: Vec<_> =
+            token.repositories().iter().map(|r| &r.full_name).collect();
+        assert_eq!(names, ["mock-org/hello-world", "mock-org/repo-42"]);
+        assert_eq!(
+            token.repository_selection(),
+            Some(RepositorySelection::Selected)
+        );
+        assert_eq!(
+            token.to_state().repository_selection,
+            Some(RepositorySelection::Selected)
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_scope_not_granted() {
+        use crate::observer::tests::RecordingObserver;
+        use crate::test_util::MockGithub;
+        use crate::{PermissionLevel, Repository, TokenScope};
+        use serde_json::json;
+        use std::sync::Arc;
+
+        let github = MockGithub::start().await.unwrap();
+        let recorder = Arc::new(RecordingObserver::default());
+        let params = GithubAuthParams {
+            observer: Some(recorder.clone()),
+            scope: TokenScope {
+                repositories: vec!["hello-world".into()],
+                permissions: Some(Permissions {
+                    contents: Some(PermissionLevel::Write),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..github.params()
+        };
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        assert!(recorder.events("scope_not_granted").is_empty());
+
+        github.set_scoped_permissions(Permissions {
+            contents: Some(PermissionLevel::Read),
+            ..Default::default()
+        });
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        let events = recorder.events("scope_not_granted");
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0]["missing_permissions"],
+            json!([{"name": "contents", "requested": "write", "granted": "read"}])
+        );
+        assert_eq!(events[0]["missing_repositories"], json!([]));
+
+        // Repository names are compared ignoring case, like GitHub.
+        let raw = RawInstallationAccessToken {
+            token: "token".into(),
+            expires_at: Utc::now(),
+            permissions: Permissions {
+                contents: Some(PermissionLevel::Write),
+                ..Default::default()
+            },
+            repositories: vec![Repository {
+                id: 1,
+                name: "Hello-World".into(),
+                full_name: "octo/Hello-World".into(),
+                private: false,
+            }],
+            repository_selection: Some(RepositorySelection::Selected),
+            rate_limit: None,
+        };
+        let scope = TokenScope {
+            repositories: vec!["hello-world".into(), "secret".into()],
+            repository_ids: vec![1, 42],
+            ..params.scope.clone()
+        };
+        check_granted_scope(&params, &scope, &raw);
+        let events = recorder.events("scope_not_granted");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1]["missing_permissions"], json!([]));
+        assert_eq!(events[1]["missing_repositories"], json!(["secret"]));
+        assert_eq!(events[1]["missing_repository_ids"], json!([42]));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_scoped() {
+        use crate::test_util::MockGithub;
+        use crate::{PermissionLevel, TokenScope};
+
+        let github = MockGithub::start().await.unwrap();
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let read = Permissions {
+            contents: Some(PermissionLevel::Read),
+            ..Default::default()
+        };
+        let scoped = token.scoped(&[42], Some(read.clone())).await.unwrap();
+        assert_eq!(scoped.permissions(), &read);
+        assert_eq!(scoped.repositories()[0].full_name, "mock-org/repo-42");
+        let scoped_token = scoped.token.clone();
+        assert_eq!(github.token_requests(), 2);
+
+        // The same scope is reused, another one gets its own token.
+        let again = token.scoped(&[42], Some(read.clone())).await.unwrap();
+        assert_eq!(again.token, scoped_token);
+        assert_eq!(github.token_requests(), 2);
+        let params = token.params.clone();
+        let other = token.scoped(&[43], Some(read.clone())).await.unwrap();
+        assert_ne!(other.token, scoped_token);
+        assert!(Arc::ptr_eq(&other.params, &params));
+        assert_eq!(github.token_requests(), 3);
+        assert!(token.repositories().is_empty());
+        assert_eq!(token.token, "mock-token-1");
+
+        // Expired tokens are dropped, and the one that expires first
+        // makes room once the cache is full.
+        let scope = |id| TokenScope {
+            repositories: Vec::new(),
+            repository_ids: vec![id],
+            permissions: Some(read.clone()),
+        };
+        token.scoped_tokens.clear();
+        token.scoped_tokens.insert(
+            scope(1),
+            InstallationAccessToken::for_tests("expired", Utc::now()),
+        );
+        for id in 100..100 + MAX_SCOPED_TOKENS as u64 {
+            let expires_at = Utc::now() + Duration::minutes(id as i64);
+            token.scoped_tokens.insert(
+                scope(id),
+                InstallationAccessToken::for_tests("cached", expires_at),
+            );
+        }
+        token.scoped(&[44], Some(read.clone())).await.unwrap();
+        assert_eq!(token.scoped_tokens.len(), MAX_SCOPED_TOKENS);
+        assert!(!token.scoped_tokens.contains_key(&scope(1)));
+        assert!(!token.scoped_tokens.contains_key(&scope(100)));
+        assert!(token.scoped_tokens.contains_key(&scope(101)));
+        assert!(token.scoped_tokens.contains_key(&scope(44)));
+    }
+
+    #[test]
+    fn test_wipe_secrets() {
+        let mut params = GithubAuthParams {
+            private_key: b"primary".to_vec(),
+            fallback_private_keys: vec![b"old".to_vec(), b"older".to_vec()],
+            insecure_hs256_secret: Some(b"secret".to_vec()),
+            ..Default::default()
+        };
+        wipe_secrets(&mut params);
+        assert_eq!(params.private_key, [0; 7]);
+        assert_eq!(params.fallback_private_keys, [vec![0; 3], vec![0; 5]]);
+        assert_eq!(params.insecure_hs256_secret, Some(vec![0; 6]));
+    }
+
+    #[test]
+    fn test_identifier_accessors() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        Arc::make_mut(&mut token.params).app_id = 1234;
+        Arc::make_mut(&mut token.params).installation_id = 5678;
+        assert_eq!(token.app_id(), 1234);
+        assert_eq!(token.installation_id(), 5678);
+        assert_eq!(token.params().app_id, 1234);
+    }
+
+    #[test]
+    fn test_debug_display() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        Arc::make_mut(&mut token.params).app_id = 1234;
+        Arc::make_mut(&mut token.params).installation_id = 5678;
+        Arc::make_mut(&mut token.params).private_key = b"myKey".to_vec();
+        token.expires_at = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let debug = format!("{:?}", token);
+        assert!(!debug.contains("myToken"));
+        assert!(!debug.contains("myKey"));
+        assert!(debug.contains("installation_id: 5678"));
+        assert_eq!(
+            token.to_string(),
+            "installation 5678 of app 1234, expired at 2024-01-01 12:00:00 UTC"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_count() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        token.header().await.unwrap();
+        assert_eq!(token.request_count(), 0);
+        let _ = token.get("/repos/owner/name").await.unwrap();
+        let _ = token.delete("/repos/owner/name").await.unwrap();
+        assert_eq!(token.request_count(), 2);
+    }
+
+    #[test]
+    fn test_record_response() {
+        use crate::observer::tests::RecordingObserver;
+        use std::sync::Arc;
+
+        let response = |remaining: u64, reset: u64| {
+            reqwest::Response::from(
+                http::Response::builder()
+                    .header("x-ratelimit-limit", "5000")
+                    .header("x-ratelimit-remaining", remaining.to_string())
+                    .header("x-ratelimit-reset", reset.to_string())
+                    .body("")
+                    .unwrap(),
+            )
+        };
+
+        let recorder = Arc::new(RecordingObserver::default());
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        Arc::make_mut(&mut token.params).observer = Some(recorder.clone());
+        token.rate_limit_threshold = Some(RateLimitThreshold::Percent(10));
+
+        token.record_response(&response(1000, 1700000000));
+        token.record_response(&response(400, 1700000000));
+        token.record_response(&response(300, 1700000000));
+        token.record_response(&response(200, 1700003600));
+        let remaining: Vec<_> = recorder
+            .events("rate_limit_low")
+            .iter()
+            .map(|event| event["remaining"].clone())
+            .collect();
+        assert_eq!(remaining, [400, 200]);
+        assert_eq!(token.rate_limit().unwrap().remaining, 200);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_jwt_signer() {
+        use crate::signer::tests::TestSigner;
+        use crate::test_util::MockGithub;
+        use std::sync::atomic::Ordering;
+        use std::sync::Arc;
+
+        let github = MockGithub::start().await.unwrap();
+        let signer = Arc::new(TestSigner::new());
+        let params = GithubAuthParams {
+            private_key: Vec::new(),
+            signer: Some(signer.clone()),
+            verify_jwt: true,
+            ..github.params()
+        };
+        params.validate().unwrap();
+        let token = InstallationAccessToken::new(params.clone()).await.unwrap();
+        assert!(token.health_check().await.unwrap());
+        assert_eq!(signer.calls.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(
+            crate::sign_app_jwt(&params),
+            Err(AuthError::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_list_repositories() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let repos = token.list_repositories().await.unwrap();
+        let names: Vec<_> = repos.iter().map(|r| &r.full_name).collect();
+        assert_eq!(names, ["mock-org/hello-world", "mock-org/secret"]);
+        assert!(!repos[0].private);
+        assert!(repos[1].private);
+        assert_eq!(token.request_count(), 2);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_revoke() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        assert_eq!(token.verify().await.unwrap(), TokenValidity::Valid);
+        token.auth_scheme = AuthScheme::Bearer;
+        assert_eq!(token.verify().await.unwrap(), TokenValidity::Valid);
+        assert!(!token.needs_refresh());
+        let state = token.to_state();
+        token.revoke().await.unwrap();
+        assert_eq!(github.revocations(), 1);
+
+        let mut token =
+            InstallationAccessToken::from_state(github.params(), state)
+                .unwrap();
+        assert_eq!(token.verify().await.unwrap(), TokenValidity::Rejected);
+        assert!(token.needs_refresh());
+        assert!(token.revoke().await.is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_for_repo() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let params = GithubAuthParams {
+            installation_id: 0,
+            ..github.params()
+        };
+        let token =
+            InstallationAccessToken::for_repo(params.clone(), "mock-org/hello")
+                .await
+                .unwrap();
+        assert_eq!(token.installation_id(), 2);
+        let token =
+            InstallationAccessToken::for_org(params.clone(), "mock-org")
+                .await
+                .unwrap();
+        assert_eq!(token.installation_id(), 2);
+
+        let err = InstallationAccessToken::for_org(params, "other").await;
+        assert!(matches!(err, Err(AuthError::InstallationNotFound(_))));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_fallback_base_url() {
+        use crate::test_util::{MockFailure, MockGithub};
+
+        let github = MockGithub::start().await.unwrap();
+        let primary = MockGithub::start().await.unwrap();
+        primary.fail_next_token_request(MockFailure::Status(502));
+        let params = GithubAuthParams {
+            fallback_base_urls: vec![github.base_url()],
+            ..primary.params()
+        };
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        assert_eq!(primary.token_requests(), 0);
+        assert_eq!(github.token_requests(), 1);
+
+        // Requests that GitHub rejects are not retried elsewhere.
+        primary.fail_next_token_request(MockFailure::Status(404));
+        assert!(InstallationAccessToken::new(params).await.is_err());
+        assert_eq!(github.token_requests(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_retry_budget() {
+        use crate::test_util::{MockFailure, MockGithub};
+        use crate::RetryBudget;
+
+        let github = MockGithub::start().await.unwrap();
+        github.set_token_lifetime(Duration::zero());
+        let mut token = InstallationAccessToken::new(GithubAuthParams {
+            retry_budget: Some(RetryBudget::new(
+                0,
+                time::Duration::from_secs(60),
+            )),
+            ..github.params()
+        })
+        .await
+        .unwrap();
+
+        // The first failure is not a retry, so it goes to GitHub.
+        github.fail_next_token_request(MockFailure::Status(500));
+        assert!(matches!(
+            token.refresh_if_needed().await,
+            Err(AuthError::GithubApi { .. })
+        ));
+        assert!(matches!(
+            token.refresh_if_needed().await,
+            Err(AuthError::RetryBudgetExhausted)
+        ));
+        assert_eq!(github.token_requests(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_retry_policy() {
+        use crate::test_util::{MockFailure, MockGithub};
+        use crate::RetryPolicy;
+
+        let github = MockGithub::start().await.unwrap();
+        let params = GithubAuthParams {
+            retry_policy: Some(RetryPolicy::default().backoff(
+                time::Duration::from_millis(1),
+                time::Duration::from_millis(10),
+            )),
+            ..github.params()
+        };
+        github.fail_next_token_request(MockFailure::Status(502));
+        github.fail_next_token_request(MockFailure::Status(503));
+        InstallationAccessToken::new(params.clone()).await.unwrap();
+        assert_eq!(github.token_requests(), 1);
+
+        github.fail_next_token_request(MockFailure::Status(401));
+        github.fail_next_token_request(MockFailure::Status(401));
+        assert!(InstallationAccessToken::new(params).await.is_err());
+        assert_eq!(github.token_requests(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_force_refresh() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        token.force_refresh().await.unwrap();
+        assert_eq!(github.token_requests(), 2);
+        assert!(!token.needs_refresh());
+
+        let ok = reqwest::Response::from(http::Response::new(""));
+        assert!(!token.handle_unauthorized(&ok));
+        let unauthorized = reqwest::Response::from(
+            http::Response::builder().status(401).body("").unwrap(),
+        );
+        assert!(token.handle_unauthorized(&unauthorized));
+        token.header().await.unwrap();
+        assert_eq!(github.token_requests(), 3);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_execute() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let mut token =
+            InstallationAccessToken::new(github.params()).await.unwrap();
+        let url = format!("{}/installation/repositories", github.base_url());
+        let resp = token.execute(token.client.get(&url)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // The mock only accepts the newest token, so minting another
+        // one makes the current token look revoked.
+        InstallationAccessToken::new(github.params()).await.unwrap();
+        let resp = token.execute(token.client.get(&url)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(github.token_requests(), 3);
+        assert_eq!(token.request_count(), 3);
+    }
+
+    #[test]
+    fn test_failure_log() {
+        let mut log = FailureLog::default();
+        let error = AuthError::Cancelled;
+        assert!(log.failure(&error, false));
+        assert!(!log.failure(&error, false));
+        assert!(!log.failure(&error, true));
+        assert_eq!((log.failures, log.suppressed), (3, 2));
+
+        log.last_logged = Some(time::Instant::now() - FAILURE_LOG_INTERVAL);
+        assert!(log.failure(&error, false));
+        assert_eq!((log.failures, log.suppressed), (4, 0));
+
+        log.success();
+        assert!(log.failure(&error, false));
+        assert_eq!(log.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_header_with_deadline() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        let deadline = time::Instant::now();
+        assert!(token.header_with_deadline(deadline).await.is_ok());
+
+        token.expires_at = Utc::now();
+        assert!(matches!(
+            token.header_with_deadline(deadline).await,
+            Err(AuthError::DeadlineExceeded)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_header_or_cancel() {
+        // A server that accepts connections but never responds.
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        token.params = GithubAuthParams {
+            private_key: include_bytes!("../tests/data/test_private_key.pem")
+                .to_vec(),
+            app_id: 1,
+            base_url: Some(format!(
+                "http://{}",
+                listener.local_addr().unwrap()
+            )),
+            ..Default::default()
+        }
+        .into();
+        token.expires_at = Utc::now();
+
+        let cancel = tokio::time::sleep(time::Duration::from_millis(50));
+        assert!(matches!(
+            token.header_or_cancel(cancel).await,
+            Err(AuthError::Cancelled)
+        ));
+        assert_eq!(token.token, "myToken");
+        assert_eq!(token.refresh_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stale_token_policy() {
+        // The default params have no private key, so refreshing fails.
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        token.expires_at = Utc::now() + Duration::seconds(30);
+        assert!(token.needs_refresh());
+        assert!(token.header().await.is_err());
+
+        token.stale_token_policy = StaleTokenPolicy::UseUntilExpiry;
+        assert!(token.header().await.is_ok());
+        let stats = token.refresh_stats();
+        assert_eq!(stats.failures, 2);
+        assert_eq!(stats.consecutive_failures, 2);
+        assert_eq!(stats.successes, 0);
+        assert!(stats.last_failure_at.is_some());
+
+        token.expires_at = Utc::now() - Duration::seconds(1);
+        assert!(token.header().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_standard_headers() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        Arc::make_mut(&mut token.params).user_agent =
+            "my-cool-user-agent".into();
+        let headers = token.header().await.unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[AUTHORIZATION], "token myToken");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+
+        token.include_standard_headers = true;
+        let headers = token.header().await.unwrap();
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers[ACCEPT], "application/vnd.github+json");
+        assert_eq!(headers["x-github-api-version"], "2022-11-28");
+        assert_eq!(headers[USER_AGENT], "my-cool-user-agent");
+
+        let pairs = token.header_pairs().await.unwrap();
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs[0], ("authorization", "token myToken".to_string()));
+        for (name, value) in pairs {
+            assert_eq!(headers[name], value);
+        }
+
+        Arc::make_mut(&mut token.params).api_version =
+            Some("2026-03-10".into());
+        let headers = token.header().await.unwrap();
+        assert_eq!(headers["x-github-api-version"], "2026-03-10");
+
+        token.auth_scheme = AuthScheme::Bearer;
+        let value = token.header_value().await.unwrap();
+        assert_eq!(value, "Bearer myToken");
+        assert!(value.is_sensitive());
+        assert_eq!(token.header().await.unwrap()[AUTHORIZATION], value);
+    }
+
+    #[tokio::test]
+    async fn test_request_helpers() {
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        let req = token
+            .get("/repos/owner/name")
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(
+            req.url().as_str(),
+            "https://api.github.com/repos/owner/name"
+        );
+        assert_eq!(req.headers()[AUTHORIZATION], "token myToken");
+    }
+
+    #[test]
+    fn test_needs_refresh() {
+        use std::thread::sleep;
+        let mut token = InstallationAccessToken::for_tests(
+            "myToken",
+            Utc::now() + Duration::hours(1),
+        );
+        token.expires_at = Utc::now() + Duration::seconds(2);
+        token.refresh_safety_margin = Duration::seconds(0);
+        assert!(!token.needs_refresh());
+        sleep(Duration::milliseconds(1500).to_std().unwrap());
+        assert!(!token.needs_refresh());
+        token.refresh_safety_margin = Duration::seconds(1);
+        assert!(token.needs_refresh());
+
+        token.expires_at = Utc::now() + Duration::minutes(5);
+        token.refresh_safety_margin = Duration::minutes(1);
+        token.refresh_jitter = Duration::minutes(5);
+        token.jitter_fraction = 0.0;
+        assert!(!token.needs_refresh());
+        token.jitter_fraction = 0.9;
+        assert!(token.needs_refresh());
+        assert_eq!(
+            token.next_refresh_at(),
+            token.expires_at - Duration::seconds(330)
+        );
+        assert!(token.time_until_expiry() > Duration::minutes(4));
+        assert!(token.time_until_expiry() <= Duration::minutes(5));
+    }
+}
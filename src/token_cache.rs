@@ -0,0 +1,162 @@
+use crate::{AuthError, GithubAuthParams, TokenScope, TokenState};
+use ring::digest;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by the methods of [`TokenCacheStore`].
+pub type TokenCacheFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, AuthError>> + Send + 'a>>;
+
+/// Shared storage for installation access tokens, such as Redis or
+/// DynamoDB, so that several replicas of a service can use the same
+/// tokens instead of each minting its own.
+///
+/// Set [`GithubAuthParams::token_cache`] to use it. Before requesting
+/// a token from GitHub, the cache is checked for a token that is not
+/// close to expiring, and each newly fetched token is stored in it.
+/// Cache errors are logged and otherwise ignored, so an unavailable
+/// cache only costs extra token requests.
+///
+/// The stored [`TokenState`] contains the token itself, so the cache
+/// must be protected as well as the token. Entries can be given a
+/// time to live of
+/// [`expires_at`](TokenState::expires_at); expired entries are never
+/// used.
+pub trait TokenCacheStore: Send + Sync {
+    /// Get the token stored under `key`, if any.
+    fn get<'a>(
+        &'a self,
+        key: &'a TokenCacheKey,
+    ) -> TokenCacheFuture<'a, Option<TokenState>>;
+
+    /// Store `state` under `key`, replacing any previous token.
+    fn put<'a>(
+        &'a self,
+        key: &'a TokenCacheKey,
+        state: &'a TokenState,
+    ) -> TokenCacheFuture<'a, ()>;
+}
+
+/// Identifies the tokens that can be shared through a
+/// [`TokenCacheStore`]: those for the same app, installation, and
+/// [scope](GithubAuthParams::scope).
+///
+/// The [`Display`](fmt::Display) form is stable and suitable as a
+/// key in key-value stores: the app ID and installation ID separated
+/// by `:`, followed by `:` and a hash of the scope if the token is
+/// scoped, for example `1234:5678`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenCacheKey {
+    /// App ID.
+    pub app_id: u64,
+
+    /// Installation ID.
+    pub installation_id: u64,
+
+    /// Restrictions requested for the token.
+    pub scope: TokenScope,
+}
+
+impl TokenCacheKey {
+    /// Get the key for tokens fetched with `params`.
+    pub fn new(params: &GithubAuthParams) -> Self {
+        Self {
+            app_id: params.app_id,
+            installation_id: params.installation_id,
+            scope: params.scope.clone(),
+        }
+    }
+}
+
+impl fmt::Display for TokenCacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.app_id, self.installation_id)?;
+        if !self.scope.is_empty() {
+            let json = serde_json::to_vec(&self.scope).unwrap_or_default();
+            let hash = digest::digest(&digest::SHA256, &json);
+            write!(f, ":")?;
+            for byte in &hash.as_ref()[..8] {
+                write!(f, "{:02x}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_display() {
+        let params = GithubAuthParams {
+            app_id: 1234,
+            installation_id: 5678,
+            ..Default::default()
+        };
+        assert_eq!(TokenCacheKey::new(&params).to_string(), "1234:5678");
+
+        let scoped = GithubAuthParams {
+            scope: TokenScope {
+                repositories: vec!["hello-world".into()],
+                ..Default::default()
+            },
+            ..params
+        };
+        let key = TokenCacheKey::new(&scoped).to_string();
+        assert!(key.starts_with("1234:5678:"));
+        assert_eq!(key.len(), "1234:5678:".len() + 16);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_token_cache() {
+        use crate::test_util::MockGithub;
+        use crate::InstallationAccessToken;
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct MemoryCache(Mutex<HashMap<String, TokenState>>);
+
+        impl TokenCacheStore for MemoryCache {
+            fn get<'a>(
+                &'a self,
+                key: &'a TokenCacheKey,
+            ) -> TokenCacheFuture<'a, Option<TokenState>> {
+                let state =
+                    self.0.lock().unwrap().get(&key.to_string()).cloned();
+                Box::pin(async move { Ok(state) })
+            }
+
+            fn put<'a>(
+                &'a self,
+                key: &'a TokenCacheKey,
+                state: &'a TokenState,
+            ) -> TokenCacheFuture<'a, ()> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), state.clone());
+                Box::pin(async move { Ok(()) })
+            }
+        }
+
+        let github = MockGithub::start().await.unwrap();
+        let params = GithubAuthParams {
+            token_cache: Some(Arc::new(MemoryCache::default())),
+            ..github.params()
+        };
+        let mut first =
+            InstallationAccessToken::new(params.clone()).await.unwrap();
+        let second = InstallationAccessToken::new(params).await.unwrap();
+        assert_eq!(github.token_requests(), 1);
+        assert_eq!(first.token, second.token);
+
+        // A rejected token is not taken from the cache again.
+        first.force_refresh().await.unwrap();
+        assert_eq!(github.token_requests(), 2);
+        assert_ne!(first.token, second.token);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::InstallationAccessToken;
+use chrono::{DateTime, Utc};
+use futures_channel::mpsc;
+use futures_core::Stream;
+
+/// A copy of the installation access token as it was at the time of
+/// a refresh. See [`InstallationAccessToken::token_updates`].
+#[derive(Clone)]
+pub struct TokenSnapshot {
+    /// The installation access token.
+    pub token: String,
+
+    /// Time at which the token expires.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl InstallationAccessToken {
+    /// Get a stream that yields a [`TokenSnapshot`] each time the
+    /// token is refreshed.
+    ///
+    /// This lets consumers react to new tokens (for example by
+    /// rewriting a credentials file) without polling. The stream ends
+    /// when the `InstallationAccessToken` is dropped. Dropping the
+    /// stream unsubscribes it.
+    pub fn token_updates(&mut self) -> impl Stream<Item = TokenSnapshot> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.update_senders.push(sender);
+        receiver
+    }
+
+    pub(crate) fn publish_update(&mut self) {
+        let snapshot = TokenSnapshot {
+            token: self.token.clone(),
+            expires_at: self.expires_at,
+        };
+        self.update_senders
+            .retain(|sender| sender.unbounded_send(snapshot.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    #[test]
+    fn test_token_updates() {
+        let mut token = InstallationAccessToken::for_tests(
+            "first",
+            Utc::now() + Duration::hours(1),
+        );
+        let mut updates = pin!(token.token_updates());
+        let dropped = token.token_updates();
+        drop(dropped);
+
+        token.token = "second".into();
+        token.publish_update();
+        assert_eq!(token.update_senders.len(), 1);
+
+        let mut cx = Context::from_waker(Waker::noop());
+        match updates.as_mut().poll_next(&mut cx) {
+            Poll::Ready(Some(snapshot)) => assert_eq!(snapshot.token, "second"),
+            _ => panic!("expected a snapshot"),
+        }
+        assert!(updates.as_mut().poll_next(&mut cx).is_pending());
+
+        drop(token);
+        assert!(matches!(
+            updates.as_mut().poll_next(&mut cx),
+            Poll::Ready(None)
+        ));
+    }
+}
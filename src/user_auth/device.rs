@@ -0,0 +1,181 @@
+use super::{post_form, request_raw_token, OAuthCredentials, UserToken};
+use crate::AuthError;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::fmt;
+use std::time;
+
+/// Grant type for polling with a device code.
+const DEVICE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// How much longer to wait between polls after a `slow_down` error
+/// that doesn't give a new interval.
+const SLOW_DOWN_STEP: time::Duration = time::Duration::from_secs(5);
+
+/// Response of `POST /login/device/code`.
+#[derive(Deserialize)]
+struct RawDeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: i64,
+    interval: u64,
+}
+
+/// An in-progress device flow, for getting a user access token in a
+/// program that can't receive a redirect, such as a command-line tool.
+///
+/// Only the client ID is needed; the client secret can be left empty,
+/// since it can't be kept secret in a program given to users. The
+/// device flow must be enabled in the app's settings.
+///
+/// ```no_run
+/// use github_app_auth::user_auth::{DeviceFlow, OAuthCredentials};
+///
+/// # async fn wrapper() -> Result<(), github_app_auth::AuthError> {
+/// let credentials = OAuthCredentials::new("my-cli", "Iv1.abc", "");
+/// let mut flow = DeviceFlow::start(&credentials).await?;
+/// println!(
+///     "Open {} and enter the code {}",
+///     flow.verification_uri(),
+///     flow.user_code()
+/// );
+/// let token = flow.wait().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DeviceFlow {
+    credentials: OAuthCredentials,
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_at: DateTime<Utc>,
+    interval: time::Duration,
+}
+
+impl DeviceFlow {
+    /// Request a device code and a user code with
+    /// `POST /login/device/code`.
+    pub async fn start(
+        credentials: &OAuthCredentials,
+    ) -> Result<DeviceFlow, AuthError> {
+        let raw: RawDeviceCode = post_form(
+            credentials,
+            "/login/device/code",
+            &[("client_id", credentials.client_id.as_str())],
+        )
+        .await?;
+        Ok(DeviceFlow {
+            credentials: credentials.clone(),
+            device_code: raw.device_code,
+            user_code: raw.user_code,
+            verification_uri: raw.verification_uri,
+            expires_at: Utc::now() + Duration::seconds(raw.expires_in),
+            interval: time::Duration::from_secs(raw.interval),
+        })
+    }
+
+    /// Code that the user enters at the
+    /// [verification URI](Self::verification_uri).
+    pub fn user_code(&self) -> &str {
+        &self.user_code
+    }
+
+    /// Page where the user enters the [user code](Self::user_code),
+    /// normally `https://github.com/login/device`.
+    pub fn verification_uri(&self) -> &str {
+        &self.verification_uri
+    }
+
+    /// Time after which the codes can no longer be used. GitHub allows
+    /// fifteen minutes.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// How long to wait between calls to [`poll`](Self::poll). This
+    /// grows if GitHub asks for polling to slow down.
+    pub fn interval(&self) -> time::Duration {
+        self.interval
+    }
+
+    /// Check once whether the user has entered the code. Returns
+    /// `None` if they have not yet; call this again after
+    /// [`interval`](Self::interval).
+    ///
+    /// If the user denies access, or the codes expire, GitHub's error
+    /// is returned as [`AuthError::OAuth`], for example with the code
+    /// `access_denied` or `expired_token`.
+    pub async fn poll(&mut self) -> Result<Option<UserToken>, AuthError> {
+        let raw = request_raw_token(
+            &self.credentials,
+            &[
+                ("device_code", self.device_code.as_str()),
+                ("grant_type", DEVICE_GRANT),
+            ],
+        )
+        .await?;
+        match raw.error.as_deref() {
+            Some("authorization_pending") => Ok(None),
+            Some("slow_down") => {
+                self.interval = match raw.interval {
+                    Some(interval) => time::Duration::from_secs(interval),
+                    None => self.interval + SLOW_DOWN_STEP,
+                };
+                Ok(None)
+            }
+            _ => raw.into_token().map(Some),
+        }
+    }
+
+    /// [`poll`](Self::poll) at the requested interval until the user
+    /// enters the code, denies access, or the codes expire.
+    pub async fn wait(&mut self) -> Result<UserToken, AuthError> {
+        loop {
+            tokio::time::sleep(self.interval).await;
+            if let Some(token) = self.poll().await? {
+                return Ok(token);
+            }
+        }
+    }
+}
+
+/// The device code is redacted.
+impl fmt::Debug for DeviceFlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceFlow")
+            .field("credentials", &self.credentials)
+            .field("user_code", &self.user_code)
+            .field("verification_uri", &self.verification_uri)
+            .field("expires_at", &self.expires_at)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::MockGithub;
+
+    #[tokio::test]
+    async fn test_device_flow() {
+        let github = MockGithub::start().await.unwrap();
+        let credentials = OAuthCredentials {
+            client_secret: String::new(),
+            ..github.oauth_credentials()
+        };
+        let mut flow = DeviceFlow::start(&credentials).await.unwrap();
+        assert_eq!(flow.user_code(), "MOCK-CODE");
+        assert!(flow.expires_at() > Utc::now());
+
+        // The mock server reports a pending authorization, then asks
+        // for polling to slow down, then issues a token.
+        assert!(flow.poll().await.unwrap().is_none());
+        assert!(flow.poll().await.unwrap().is_none());
+        assert_eq!(flow.interval(), time::Duration::from_secs(1));
+        flow.interval = time::Duration::ZERO;
+        let token = flow.wait().await.unwrap();
+        assert_eq!(token.access_token, "mock-user-token-1");
+    }
+}
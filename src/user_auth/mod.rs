@@ -0,0 +1,390 @@
+//! User-to-server authentication, for acting on behalf of a user.
+//!
+//! A GitHub App can make requests as a user who has authorized it,
+//! using the OAuth web application flow: the user is redirected to
+//! `https://github.com/login/oauth/authorize?client_id=...`, and
+//! GitHub redirects back to the app with a `code` that is exchanged
+//! for a user access token with [`exchange_code`].
+//!
+//! ```no_run
+//! use github_app_auth::user_auth::{exchange_code, OAuthCredentials};
+//!
+//! # async fn wrapper(code: &str) -> Result<(), github_app_auth::AuthError> {
+//! let credentials =
+//!     OAuthCredentials::new("my-app", "Iv1.abc", "client secret");
+//! let token = exchange_code(&credentials, code, None).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! If token expiration is enabled for the app, user access tokens
+//! expire after eight hours, and can be renewed with
+//! [`refresh_user_token`]. [`UserAccessToken`] does this
+//! automatically, like
+//! [`InstallationAccessToken`](crate::InstallationAccessToken).
+//!
+//! Command-line tools that can't receive a redirect can use the device
+//! flow instead, with [`DeviceFlow`].
+//!
+//! Unlike installation access tokens, these requests go to the GitHub
+//! web host rather than the API, and are authenticated with the app's
+//! client ID and client secret rather than a JWT.
+//!
+//! See [Generating a user access token for a GitHub App](https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/generating-a-user-access-token-for-a-github-app).
+
+mod device;
+
+pub use device::DeviceFlow;
+
+use crate::response::{check_status, send};
+use crate::{AuthError, GithubAuthParams};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+
+/// Web host used if [`OAuthCredentials::base_url`] is not set.
+const DEFAULT_WEB_URL: &str = "https://github.com";
+
+/// The app's OAuth client credentials, from the app's settings page.
+#[derive(Clone, Default)]
+pub struct OAuthCredentials {
+    /// User agent sent with each request. GitHub rejects requests
+    /// without one.
+    pub user_agent: String,
+
+    /// The app's client ID, for example `Iv1.abc123`.
+    pub client_id: String,
+
+    /// A client secret generated in the app's settings.
+    pub client_secret: String,
+
+    /// Base URL of the GitHub web host. Defaults to
+    /// `https://github.com`. For GitHub Enterprise Server, set this to
+    /// the instance's root, for example `https://ghe.example.com`.
+    pub base_url: Option<String>,
+}
+
+impl OAuthCredentials {
+    /// Create credentials for `https://github.com`.
+    pub fn new(
+        user_agent: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            user_agent: user_agent.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            base_url: None,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        let base_url = self.base_url.as_deref().unwrap_or(DEFAULT_WEB_URL);
+        format!("{}{}", base_url.trim_end_matches('/'), path)
+    }
+
+    /// Parameters used to send requests, so that they are logged and
+    /// recorded the same way as the crate's other requests.
+    fn params(&self) -> GithubAuthParams {
+        GithubAuthParams {
+            user_agent: self.user_agent.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The client secret is redacted.
+impl fmt::Debug for OAuthCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthCredentials")
+            .field("user_agent", &self.user_agent)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"[redacted]")
+            .field("base_url", &self.base_url)
+            .finish()
+    }
+}
+
+/// A user access token, returned by [`exchange_code`],
+/// [`refresh_user_token`], and [`DeviceFlow`].
+#[derive(Clone)]
+pub struct UserToken {
+    /// The token, used as `Authorization: token <access_token>`.
+    pub access_token: String,
+
+    /// Token type, normally `bearer`.
+    pub token_type: String,
+
+    /// Scopes granted. Always empty for GitHub Apps, whose access is
+    /// governed by the app's permissions instead.
+    pub scope: String,
+
+    /// When the access token expires, if token expiration is enabled
+    /// for the app.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Token that can be used to get a new access token, if token
+    /// expiration is enabled for the app.
+    pub refresh_token: Option<String>,
+
+    /// When the refresh token expires.
+    pub refresh_token_expires_at: Option<DateTime<Utc>>,
+}
+
+/// The access and refresh tokens are redacted.
+impl fmt::Debug for UserToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserToken")
+            .field("access_token", &"[redacted]")
+            .field("token_type", &self.token_type)
+            .field("scope", &self.scope)
+            .field("expires_at", &self.expires_at)
+            .field("refresh_token", &self.refresh_token.as_ref().map(|_| ".."))
+            .field("refresh_token_expires_at", &self.refresh_token_expires_at)
+            .finish()
+    }
+}
+
+/// Response of `POST /login/oauth/access_token`. Errors are returned
+/// with status 200 and an `error` field.
+#[derive(Deserialize)]
+struct RawUserToken {
+    access_token: Option<String>,
+    #[serde(default)]
+    token_type: String,
+    #[serde(default)]
+    scope: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+    refresh_token_expires_in: Option<i64>,
+    error: Option<String>,
+    #[serde(default)]
+    error_description: String,
+    /// Polling interval in seconds, sent with the device flow's
+    /// `slow_down` error.
+    interval: Option<u64>,
+}
+
+/// Exchange the `code` that GitHub passed to the app's callback URL
+/// for a user access token.
+///
+/// `redirect_uri` must match the one passed to the authorize URL, if
+/// one was passed. Codes expire after ten minutes and can only be used
+/// once; GitHub's error is returned as [`AuthError::OAuth`].
+pub async fn exchange_code(
+    credentials: &OAuthCredentials,
+    code: &str,
+    redirect_uri: Option<&str>,
+) -> Result<UserToken, AuthError> {
+    let mut form = vec![("code", code)];
+    if let Some(redirect_uri) = redirect_uri {
+        form.push(("redirect_uri", redirect_uri));
+    }
+    request_token(credentials, &form).await
+}
+
+/// Get a new user access token and refresh token with a refresh token.
+///
+/// The old refresh token can't be used again, so the new one must be
+/// stored in its place. Refresh tokens expire after six months.
+pub async fn refresh_user_token(
+    credentials: &OAuthCredentials,
+    refresh_token: &str,
+) -> Result<UserToken, AuthError> {
+    request_token(
+        credentials,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ],
+    )
+    .await
+}
+
+/// A user access token that is refreshed automatically before it
+/// expires.
+///
+/// Tokens without an expiration time or refresh token are used as is.
+#[derive(Debug)]
+pub struct UserAccessToken {
+    /// This time is subtracted from the expiration time to make it less
+    /// likely that the token goes out of date just as a request is
+    /// sent.
+    pub refresh_safety_margin: Duration,
+
+    credentials: OAuthCredentials,
+    token: UserToken,
+}
+
+impl UserAccessToken {
+    /// Wrap a token from [`exchange_code`] or [`refresh_user_token`],
+    /// or one loaded from storage.
+    pub fn new(credentials: OAuthCredentials, token: UserToken) -> Self {
+        Self {
+            refresh_safety_margin: Duration::minutes(1),
+            credentials,
+            token,
+        }
+    }
+
+    /// The current token. Store this after a refresh, since the old
+    /// refresh token no longer works.
+    pub fn token(&self) -> &UserToken {
+        &self.token
+    }
+
+    /// Whether the token is due for a refresh.
+    pub fn needs_refresh(&self) -> bool {
+        match (&self.token.expires_at, &self.token.refresh_token) {
+            (Some(expires_at), Some(_)) => {
+                *expires_at - self.refresh_safety_margin <= Utc::now()
+            }
+            _ => false,
+        }
+    }
+
+    /// Refresh the token if it is about to expire. Returns whether it
+    /// was refreshed.
+    pub async fn refresh_if_needed(&mut self) -> Result<bool, AuthError> {
+        if !self.needs_refresh() {
+            return Ok(false);
+        }
+        let refresh_token =
+            self.token.refresh_token.as_deref().unwrap_or_default();
+        self.token =
+            refresh_user_token(&self.credentials, refresh_token).await?;
+        Ok(true)
+    }
+
+    /// Get an HTTP authentication header for the token, refreshing it
+    /// first if needed.
+    pub async fn header(&mut self) -> Result<HeaderMap, AuthError> {
+        self.refresh_if_needed().await?;
+        let mut value = HeaderValue::try_from(format!(
+            "token {}",
+            self.token.access_token
+        ))?;
+        value.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+        Ok(headers)
+    }
+}
+
+/// Request a token from `POST /login/oauth/access_token` with the
+/// client credentials and `form`.
+async fn request_token(
+    credentials: &OAuthCredentials,
+    form: &[(&str, &str)],
+) -> Result<UserToken, AuthError> {
+    request_raw_token(credentials, form).await?.into_token()
+}
+
+/// Like [`request_token`], but leave errors in the response for the
+/// caller to handle. The client secret is left out if it is empty,
+/// since the device flow doesn't use it.
+async fn request_raw_token(
+    credentials: &OAuthCredentials,
+    form: &[(&str, &str)],
+) -> Result<RawUserToken, AuthError> {
+    let mut body = vec![("client_id", credentials.client_id.as_str())];
+    if !credentials.client_secret.is_empty() {
+        body.push(("client_secret", credentials.client_secret.as_str()));
+    }
+    body.extend_from_slice(form);
+    post_form(credentials, "/login/oauth/access_token", &body).await
+}
+
+impl RawUserToken {
+    /// Get the token, or the error that GitHub returned instead.
+    fn into_token(self) -> Result<UserToken, AuthError> {
+        if let Some(error) = self.error {
+            return Err(AuthError::OAuth {
+                error,
+                description: self.error_description,
+            });
+        }
+        let access_token =
+            self.access_token.ok_or_else(|| AuthError::OAuth {
+                error: "missing_token".into(),
+                description: "the response did not include an access token"
+                    .into(),
+            })?;
+        let now = Utc::now();
+        let expires_at =
+            |secs: Option<i64>| secs.map(|s| now + Duration::seconds(s));
+        Ok(UserToken {
+            access_token,
+            token_type: self.token_type,
+            scope: self.scope,
+            expires_at: expires_at(self.expires_in),
+            refresh_token: self.refresh_token,
+            refresh_token_expires_at: expires_at(self.refresh_token_expires_in),
+        })
+    }
+}
+
+/// Send a form to the web host and parse the JSON response.
+async fn post_form<T: DeserializeOwned>(
+    credentials: &OAuthCredentials,
+    path: &str,
+    form: &[(&str, &str)],
+) -> Result<T, AuthError> {
+    let params = credentials.params();
+    let client = params.build_client()?;
+    let request = client
+        .post(credentials.url(path))
+        .header("Accept", "application/json")
+        .form(form);
+    let resp = send(&params, request).await?;
+    Ok(check_status(resp).await?.json().await?)
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{MockGithub, TEST_OAUTH_CODE};
+
+    #[tokio::test]
+    async fn test_exchange_code() {
+        let github = MockGithub::start().await.unwrap();
+        let credentials = github.oauth_credentials();
+        let token = exchange_code(&credentials, TEST_OAUTH_CODE, None)
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "mock-user-token-1");
+        assert!(token.expires_at.unwrap() > Utc::now());
+        assert!(token.refresh_token.is_some());
+        assert!(!format!("{:?}", token).contains("mock-user-token"));
+
+        let err = exchange_code(&credentials, "wrong", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AuthError::OAuth { error, .. } if error == "bad_verification_code"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_user_access_token() {
+        let github = MockGithub::start().await.unwrap();
+        let credentials = github.oauth_credentials();
+        let mut token = exchange_code(&credentials, TEST_OAUTH_CODE, None)
+            .await
+            .unwrap();
+        token.expires_at = Some(Utc::now());
+        let mut token = UserAccessToken::new(credentials, token);
+        assert!(token.needs_refresh());
+        let headers = token.header().await.unwrap();
+        assert_eq!(headers[AUTHORIZATION], "token mock-user-token-2");
+        assert_eq!(
+            token.token().refresh_token.as_deref(),
+            Some("mock-refresh-token-2")
+        );
+        assert!(!token.refresh_if_needed().await.unwrap());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::key::{parse_private_key, rsa_key_bits};
+use crate::{
+    GithubAuthParams, JwtClaims, JwtIssuer, MAX_JWT_LIFETIME, MIN_JWT_LIFETIME,
+};
+use http::header::HeaderValue;
+use std::fs;
+use std::time::Duration;
+
+/// A problem found by [`GithubAuthParams::validate`].
+#[derive(thiserror::Error, Debug)]
+pub enum ParamsError {
+    /// The user agent is empty.
+    #[error("user agent is empty")]
+    EmptyUserAgent,
+
+    /// The user agent cannot be used as an HTTP header value.
+    #[error("user agent is not a valid header value")]
+    InvalidUserAgent,
+
+    /// The private key is empty.
+    #[error("private key is empty")]
+    EmptyPrivateKey,
+
+    /// The private key could not be parsed.
+    #[error("private key is invalid: {0}")]
+    InvalidPrivateKey(jsonwebtoken::errors::Error),
+
+    /// The private key could be decoded, but is not an RSA key that
+    /// can sign JWTs, for example because it is truncated or smaller
+    /// than 2048 bits.
+    #[error("private key is not a usable RSA key: {0}")]
+    UnusablePrivateKey(String),
+
+    /// The private key file could not be read.
+    #[error("failed to read private key file: {0}")]
+    UnreadablePrivateKey(std::io::Error),
+
+    /// One of the fallback private keys could not be parsed.
+    #[error("fallback private key {index} is invalid: {error}")]
+    InvalidFallbackPrivateKey {
+        /// Index of the key in
+        /// [`GithubAuthParams::fallback_private_keys`].
+        index: usize,
+        /// Parse error.
+        error: jsonwebtoken::errors::Error,
+    },
+
+    /// The app ID is zero.
+    #[error("app ID is zero")]
+    ZeroAppId,
+
+    /// The client ID is set but empty.
+    #[error("client ID is empty")]
+    EmptyClientId,
+
+    /// The client ID contains characters that never appear in a
+    /// GitHub client ID, such as whitespace from a copy and paste.
+    #[error("client ID {0:?} is not a valid client ID")]
+    InvalidClientId(String),
+
+    /// The installation ID is zero.
+    #[error("installation ID is zero")]
+    ZeroInstallationId,
+
+    /// The JWT lifetime is longer than the ten minutes GitHub allows.
+    #[error("JWT lifetime of {0:?} exceeds GitHub's 10 minute limit")]
+    JwtLifetimeTooLong(Duration),
+
+    /// The JWT lifetime is so short that the JWT is likely to expire
+    /// before GitHub receives it.
+    #[error("JWT lifetime of {0:?} is too short to be usable")]
+    JwtLifetimeTooShort(Duration),
+}
+
+impl GithubAuthParams {
+    /// Check the parameters for configuration mistakes without making
+    /// any network requests.
+    ///
+    /// All problems are collected rather than stopping at the first
+    /// one, so that a misconfigured deployment can be fixed in one go.
+    pub fn validate(&self) -> Result<(), Vec<ParamsError>> {
+        let mut problems = Vec::new();
+
+        if self.user_agent.is_empty() {
+            problems.push(ParamsError::EmptyUserAgent);
+        } else if HeaderValue::from_str(&self.user_agent).is_err() {
+            problems.push(ParamsError::InvalidUserAgent);
+        }
+
+        if self.encoding_key.is_some()
+            || self.insecure_hs256_secret.is_some()
+            || self.signer.is_some()
+            || self.private_key_provider.is_some()
+        {
+            // Already parsed by the caller, not a private key, or not
+            // held locally.
+        } else if let Some(path) = &self.private_key_path {
+            match fs::read(path) {
+                Ok(pem) => problems.extend(check_private_key(&pem)),
+                Err(err) => {
+                    problems.push(ParamsError::UnreadablePrivateKey(err))
+                }
+            }
+        } else if self.private_key.is_empty() {
+            problems.push(ParamsError::EmptyPrivateKey);
+        } else {
+            problems.extend(check_private_key(&self.private_key));
+        }
+        for (index, key) in self.fallback_private_keys.iter().enumerate() {
+            if let Err(error) = parse_private_key(key) {
+                problems.push(ParamsError::InvalidFallbackPrivateKey {
+                    index,
+                    error,
+                });
+            }
+        }
+
+        match &self.client_id {
+            Some(client_id) if client_id.is_empty() => {
+                problems.push(ParamsError::EmptyClientId);
+            }
+            Some(client_id) if !is_valid_client_id(client_id) => {
+                problems.push(ParamsError::InvalidClientId(client_id.clone()));
+            }
+            Some(_) => {}
+            None if self.app_id == 0 => problems.push(ParamsError::ZeroAppId),
+            None => {}
+        }
+        if self.installation_id == 0 {
+            problems.push(ParamsError::ZeroInstallationId);
+        }
+        match self.checked_jwt_lifetime() {
+            Ok(lifetime) if lifetime < MIN_JWT_LIFETIME => {
+                problems.push(ParamsError::JwtLifetimeTooShort(lifetime));
+            }
+            Ok(_) => {}
+            Err(err) => problems.push(err),
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+impl JwtClaims {
+    /// Check generated claims against the limits GitHub enforces, see
+    /// [`GithubAuthParams::validate_claims`].
+    pub(crate) fn check(
+        &self,
+        params: &GithubAuthParams,
+    ) -> Result<(), ParamsError> {
+        // Measured from the time of signing, not the backdated iat.
+        let issued =
+            self.iat + params.jwt_backdate.unwrap_or_default().as_secs();
+        let lifetime = Duration::from_secs(self.exp.saturating_sub(issued));
+        if lifetime > MAX_JWT_LIFETIME {
+            return Err(ParamsError::JwtLifetimeTooLong(lifetime));
+        }
+        if lifetime < MIN_JWT_LIFETIME {
+            return Err(ParamsError::JwtLifetimeTooShort(lifetime));
+        }
+        match &self.iss {
+            JwtIssuer::AppId(0) => Err(ParamsError::ZeroAppId),
+            JwtIssuer::AppId(_) => Ok(()),
+            JwtIssuer::ClientId(id) if id.is_empty() => {
+                Err(ParamsError::EmptyClientId)
+            }
+            JwtIssuer::ClientId(id) if !is_valid_client_id(id) => {
+                Err(ParamsError::InvalidClientId(id.clone()))
+            }
+            JwtIssuer::ClientId(_) => Ok(()),
+        }
+    }
+}
+
+/// Check that a private key can be parsed and used for signing.
+fn check_private_key(key: &[u8]) -> Option<ParamsError> {
+    if let Err(err) = parse_private_key(key) {
+        return Some(ParamsError::InvalidPrivateKey(err));
+    }
+    rsa_key_bits(key).err().map(ParamsError::UnusablePrivateKey)
+}
+
+/// Client IDs look like `Iv1.0123456789abcdef` or `Iv23abcdEFGH1234`.
+fn is_valid_client_id(id: &str) -> bool {
+    id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sign_app_jwt, AuthError};
+
+    #[test]
+    fn test_validate() {
+        let mut params = GithubAuthParams::default();
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [
+                ParamsError::EmptyUserAgent,
+                ParamsError::EmptyPrivateKey,
+                ParamsError::ZeroAppId,
+                ParamsError::ZeroInstallationId,
+            ]
+        ));
+
+        params.user_agent = "bad\nagent".into();
+        params.private_key = b"not a key".to_vec();
+        params.app_id = 1;
+        params.installation_id = 2;
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [
+                ParamsError::InvalidUserAgent,
+                ParamsError::InvalidPrivateKey(_),
+            ]
+        ));
+
+        params.user_agent = "my-cool-user-agent".into();
+        params.private_key =
+            include_bytes!("../tests/data/test_private_key_1024.pem").to_vec();
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ParamsError::UnusablePrivateKey(_)]
+        ));
+
+        params.private_key =
+            include_bytes!("../tests/data/test_private_key.pem").to_vec();
+        params.validate().unwrap();
+
+        params.app_id = 0;
+        params.client_id = Some(String::new());
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(problems.as_slice(), [ParamsError::EmptyClientId]));
+
+        params.client_id = Some("Iv23abcd1234".into());
+        params.validate().unwrap();
+
+        params.jwt_lifetime = Some(Duration::from_secs(11 * 60));
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ParamsError::JwtLifetimeTooLong(_)]
+        ));
+        assert!(sign_app_jwt(&params).is_err());
+
+        params.jwt_lifetime = Some(Duration::from_secs(10 * 60));
+        params.validate().unwrap();
+
+        params.jwt_lifetime = Some(Duration::from_secs(1));
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ParamsError::JwtLifetimeTooShort(_)]
+        ));
+        params.jwt_lifetime = None;
+
+        params.client_id = Some("Iv23abcd1234\n".into());
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ParamsError::InvalidClientId(_)]
+        ));
+        params.client_id = Some("Iv23abcd1234".into());
+
+        params.private_key.clear();
+        params.private_key_path =
+            Some("tests/data/test_private_key.pem".into());
+        params.validate().unwrap();
+
+        params.private_key_path = Some("tests/data/missing.pem".into());
+        let problems = params.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ParamsError::UnreadablePrivateKey(_)]
+        ));
+    }
+
+    #[test]
+    fn test_validate_claims() {
+        let mut params = GithubAuthParams {
+            private_key: include_bytes!("../tests/data/test_private_key.pem")
+                .to_vec(),
+            app_id: 1234,
+            validate_claims: true,
+            ..Default::default()
+        };
+        sign_app_jwt(&params).unwrap();
+
+        params.jwt_lifetime = Some(Duration::from_secs(5));
+        assert!(matches!(
+            sign_app_jwt(&params),
+            Err(AuthError::InvalidClaims(ParamsError::JwtLifetimeTooShort(
+                _
+            )))
+        ));
+        params.jwt_lifetime = None;
+
+        params.app_id = 0;
+        assert!(matches!(
+            sign_app_jwt(&params),
+            Err(AuthError::InvalidClaims(ParamsError::ZeroAppId))
+        ));
+
+        params.client_id = Some("Iv23 abcd".into());
+        assert!(matches!(
+            sign_app_jwt(&params),
+            Err(AuthError::InvalidClaims(ParamsError::InvalidClientId(_)))
+        ));
+
+        // Without validation the claims are signed as they are.
+        params.validate_claims = false;
+        sign_app_jwt(&params).unwrap();
+    }
+
+    #[test]
+    fn test_not_before() {
+        let params = GithubAuthParams {
+            private_key: include_bytes!("../tests/data/test_private_key.pem")
+                .to_vec(),
+            app_id: 1234,
+            jwt_not_before: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let claims = JwtClaims::new(&params).unwrap();
+        assert_eq!(claims.nbf, Some(claims.iat - 30));
+        assert!(serde_json::to_string(&claims).unwrap().contains("\"nbf\""));
+
+        let claims = JwtClaims::new(&GithubAuthParams::default()).unwrap();
+        assert_eq!(claims.nbf, None);
+        assert!(!serde_json::to_string(&claims).unwrap().contains("nbf"));
+    }
+
+    #[test]
+    fn test_backdate() {
+        let mut params = GithubAuthParams {
+            app_id: 1234,
+            jwt_lifetime: Some(MAX_JWT_LIFETIME),
+            jwt_backdate: Some(Duration::from_secs(60)),
+            validate_claims: true,
+            ..Default::default()
+        };
+        let claims = JwtClaims::new(&params).unwrap();
+        assert_eq!(claims.exp - claims.iat, 660);
+
+        // Without the backdate, the same claims would be too long-lived.
+        params.jwt_backdate = None;
+        assert!(matches!(
+            claims.check(&params),
+            Err(ParamsError::JwtLifetimeTooLong(_))
+        ));
+    }
+}
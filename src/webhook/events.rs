@@ -0,0 +1,198 @@
+//! Payloads of the webhook events that report changes to the app's
+//! installations.
+//!
+//! These are the events needed to keep cached installation tokens in
+//! sync, for example with
+//! [`InstallationTokenStore::handle_installation_event`](crate::InstallationTokenStore::handle_installation_event).
+//! The event type is given by the `X-GitHub-Event` header, and the
+//! body can be parsed with `serde_json` after its signature has been
+//! checked with [`WebhookVerifier`](super::WebhookVerifier). See
+//! [Webhook events and payloads](https://docs.github.com/en/webhooks/webhook-events-and-payloads).
+
+use crate::{Account, Installation, Repository, RepositorySelection};
+use serde::Deserialize;
+
+/// Value of the `X-GitHub-Event` header for an [`InstallationEvent`].
+pub const INSTALLATION_EVENT: &str = "installation";
+
+/// Value of the `X-GitHub-Event` header for an
+/// [`InstallationRepositoriesEvent`].
+pub const INSTALLATION_REPOSITORIES_EVENT: &str = "installation_repositories";
+
+/// Action of an [`InstallationEvent`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum InstallationAction {
+    /// The app was installed.
+    Created,
+
+    /// The app was uninstalled.
+    Deleted,
+
+    /// The installation was suspended.
+    Suspend,
+
+    /// The installation was unsuspended.
+    Unsuspend,
+
+    /// The account accepted new permissions requested by the app.
+    NewPermissionsAccepted,
+
+    /// An action that this crate doesn't know about.
+    #[serde(other)]
+    Other,
+}
+
+/// Payload of an `installation` event, sent when the app is
+/// installed, uninstalled, suspended, or granted new permissions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationEvent {
+    /// What happened to the installation.
+    pub action: InstallationAction,
+
+    /// The installation, as it is after the action.
+    pub installation: Installation,
+
+    /// Repositories the installation can access, for
+    /// [`Created`](InstallationAction::Created) events on an
+    /// installation with selected repositories.
+    #[serde(default)]
+    pub repositories: Vec<Repository>,
+
+    /// User who triggered the event.
+    pub sender: Option<Account>,
+}
+
+impl InstallationEvent {
+    /// Whether tokens fetched before the event should be discarded,
+    /// because the installation no longer exists, is suspended, or
+    /// has different permissions.
+    pub fn invalidates_tokens(&self) -> bool {
+        matches!(
+            self.action,
+            InstallationAction::Deleted
+                | InstallationAction::Suspend
+                | InstallationAction::NewPermissionsAccepted
+        )
+    }
+}
+
+/// Action of an [`InstallationRepositoriesEvent`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum InstallationRepositoriesAction {
+    /// Repositories were added to the installation.
+    Added,
+
+    /// Repositories were removed from the installation.
+    Removed,
+
+    /// An action that this crate doesn't know about.
+    #[serde(other)]
+    Other,
+}
+
+/// Payload of an `installation_repositories` event, sent when
+/// repositories are added to or removed from an installation.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationRepositoriesEvent {
+    /// Whether repositories were added or removed.
+    pub action: InstallationRepositoriesAction,
+
+    /// The installation.
+    pub installation: Installation,
+
+    /// Whether the installation now covers all of the account's
+    /// repositories or only selected ones.
+    pub repository_selection: Option<RepositorySelection>,
+
+    /// Repositories added to the installation.
+    #[serde(default)]
+    pub repositories_added: Vec<Repository>,
+
+    /// Repositories removed from the installation.
+    #[serde(default)]
+    pub repositories_removed: Vec<Repository>,
+
+    /// User who triggered the event.
+    pub sender: Option<Account>,
+}
+
+impl InstallationRepositoriesEvent {
+    /// Whether tokens fetched before the event should be discarded,
+    /// because they may list repositories the installation can no
+    /// longer access.
+    pub fn invalidates_tokens(&self) -> bool {
+        !self.repositories_removed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_installation_event() {
+        let payload = r#"{
+            "action": "created",
+            "installation": {
+                "id": 5678,
+                "account": {"id": 1, "login": "octo-org", "type": "Organization"},
+                "suspended_at": null,
+                "permissions": {"contents": "read", "metadata": "read"},
+                "repository_selection": "selected"
+            },
+            "repositories": [
+                {"id": 42, "node_id": "R_1", "name": "hello-world",
+                 "full_name": "octo-org/hello-world", "private": true}
+            ],
+            "sender": {"id": 2, "login": "octocat", "type": "User"}
+        }"#;
+        let event: InstallationEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event.action, InstallationAction::Created);
+        assert_eq!(event.installation.id, 5678);
+        assert_eq!(event.repositories[0].full_name, "octo-org/hello-world");
+        assert!(event.repositories[0].private);
+        assert!(!event.invalidates_tokens());
+        assert_eq!(event.sender.unwrap().login, "octocat");
+
+        let payload = r#"{
+            "action": "suspend",
+            "installation": {"id": 5678, "account": null,
+                             "suspended_at": "2024-01-01T00:00:00Z"}
+        }"#;
+        let event: InstallationEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event.action, InstallationAction::Suspend);
+        assert!(event.installation.suspended_at.is_some());
+        assert!(event.invalidates_tokens());
+
+        let payload = r#"{"action": "renamed", "installation": {"id": 1}}"#;
+        let event: InstallationEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(event.action, InstallationAction::Other);
+    }
+
+    #[test]
+    fn test_installation_repositories_event() {
+        let payload = r#"{
+            "action": "removed",
+            "installation": {"id": 5678},
+            "repository_selection": "selected",
+            "repositories_added": [],
+            "repositories_removed": [
+                {"id": 42, "name": "hello-world",
+                 "full_name": "octo-org/hello-world", "private": false}
+            ]
+        }"#;
+        let event: InstallationRepositoriesEvent =
+            serde_json::from_str(payload).unwrap();
+        assert_eq!(event.action, InstallationRepositoriesAction::Removed);
+        assert_eq!(
+            event.repository_selection,
+            Some(RepositorySelection::Selected)
+        );
+        assert_eq!(event.repositories_removed[0].id, 42);
+        assert!(event.invalidates_tokens());
+    }
+}
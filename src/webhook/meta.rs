@@ -0,0 +1,231 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A CIDR block such as `192.30.252.0/22`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Whether `addr` is inside this range. IPv4 addresses mapped into
+    /// IPv6 (`::ffff:a.b.c.d`) are treated as IPv4.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Error returned when parsing an invalid [`IpRange`].
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+#[error("invalid CIDR block: {0}")]
+pub struct InvalidIpRange(String);
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<IpRange, InvalidIpRange> {
+        let err = || InvalidIpRange(s.to_string());
+        let (network, prefix_len) = s.split_once('/').ok_or_else(err)?;
+        let network: IpAddr = network.parse().map_err(|_| err())?;
+        let prefix_len: u8 = prefix_len.parse().map_err(|_| err())?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max {
+            return Err(err());
+        }
+        Ok(IpRange {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+#[cfg(feature = "client")]
+pub use validator::HookIpValidator;
+
+#[cfg(feature = "client")]
+mod validator {
+    use super::IpRange;
+    use crate::response::check_status;
+    use crate::{AuthError, GithubAuthParams, DEFAULT_API_URL};
+    use chrono::{DateTime, Duration, Utc};
+    use reqwest::header::USER_AGENT;
+    use serde::Deserialize;
+    use std::net::IpAddr;
+
+    /// User agent for validators created with
+    /// [`HookIpValidator::new`].
+    const DEFAULT_USER_AGENT: &str =
+        concat!("github-app-auth/", env!("CARGO_PKG_VERSION"));
+
+    #[derive(Deserialize)]
+    struct Meta {
+        hooks: Vec<String>,
+    }
+
+    /// Checks whether webhook deliveries come from GitHub's published
+    /// webhook IP ranges.
+    ///
+    /// The ranges are fetched from `GET /meta` and cached. This is
+    /// defense in depth; deliveries should still be verified with
+    /// [`WebhookVerifier`](super::super::WebhookVerifier).
+    pub struct HookIpValidator {
+        /// How long fetched ranges are cached before being fetched
+        /// again. Defaults to one hour.
+        pub cache_ttl: Duration,
+
+        /// Base URL of the API to fetch the ranges from. Defaults to
+        /// `https://api.github.com`; set it to the instance's API, for
+        /// example `https://github.example.com/api/v3`, for GitHub
+        /// Enterprise Server.
+        pub base_url: String,
+
+        /// `User-Agent` header sent with the request, since GitHub
+        /// rejects requests without one. Defaults to
+        /// `github-app-auth/VERSION` for [`new`](Self::new), and to
+        /// [`GithubAuthParams::user_agent`] for
+        /// [`from_params`](Self::from_params).
+        pub user_agent: String,
+
+        client: reqwest::Client,
+        cached: Option<(DateTime<Utc>, Vec<IpRange>)>,
+    }
+
+    impl HookIpValidator {
+        /// Create a validator that uses `client` to fetch the ranges.
+        pub fn new(client: reqwest::Client) -> HookIpValidator {
+            HookIpValidator {
+                cache_ttl: Duration::hours(1),
+                base_url: DEFAULT_API_URL.into(),
+                user_agent: DEFAULT_USER_AGENT.into(),
+                client,
+                cached: None,
+            }
+        }
+
+        /// Create a validator that fetches the ranges from the API
+        /// that `params` is configured for, with an HTTP client that
+        /// has the same settings as the one used for tokens.
+        pub fn from_params(
+            params: &GithubAuthParams,
+        ) -> Result<HookIpValidator, AuthError> {
+            Ok(HookIpValidator {
+                base_url: params.base_url_or_default().into(),
+                user_agent: params.user_agent.clone(),
+                ..HookIpValidator::new(params.build_client()?)
+            })
+        }
+
+        /// Check whether `addr` (the peer address of an incoming
+        /// webhook request) is in one of GitHub's webhook IP ranges.
+        ///
+        /// The ranges are fetched first if they are not cached or the
+        /// cache has expired. This method is mutable for that reason.
+        pub async fn validate(
+            &mut self,
+            addr: IpAddr,
+        ) -> Result<bool, AuthError> {
+            let ranges = match &self.cached {
+                Some((fetched_at, ranges))
+                    if *fetched_at + self.cache_ttl > Utc::now() =>
+                {
+                    ranges
+                }
+                _ => {
+                    let ranges = self.fetch().await?;
+                    &self.cached.insert((Utc::now(), ranges)).1
+                }
+            };
+            Ok(ranges.iter().any(|range| range.contains(addr)))
+        }
+
+        async fn fetch(&self) -> Result<Vec<IpRange>, AuthError> {
+            let resp = self
+                .client
+                .get(format!("{}/meta", self.base_url.trim_end_matches('/')))
+                .header(USER_AGENT, &self.user_agent)
+                .send()
+                .await?;
+            let meta: Meta = check_status(resp).await?.json().await?;
+            Ok(meta
+                .hooks
+                .iter()
+                .filter_map(|cidr| match cidr.parse() {
+                    Ok(range) => Some(range),
+                    Err(err) => {
+                        warn!("ignoring webhook IP range: {}", err);
+                        None
+                    }
+                })
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_range() {
+        let range: IpRange = "192.30.252.0/22".parse().unwrap();
+        assert!(range.contains("192.30.252.1".parse().unwrap()));
+        assert!(range.contains("192.30.255.255".parse().unwrap()));
+        assert!(!range.contains("192.30.248.1".parse().unwrap()));
+        assert!(range.contains("::ffff:192.30.253.7".parse().unwrap()));
+        assert!(!range.contains("2a0a:a440::1".parse().unwrap()));
+
+        let range: IpRange = "2a0a:a440::/29".parse().unwrap();
+        assert!(range.contains("2a0a:a447:ffff::1".parse().unwrap()));
+        assert!(!range.contains("2a0a:a448::1".parse().unwrap()));
+
+        let range: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(range.contains("8.8.8.8".parse().unwrap()));
+
+        assert!("192.30.252.0".parse::<IpRange>().is_err());
+        assert!("192.30.252.0/33".parse::<IpRange>().is_err());
+        assert!("not an ip/8".parse::<IpRange>().is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_hook_ip_validator() {
+        use crate::test_util::MockGithub;
+
+        let github = MockGithub::start().await.unwrap();
+        let mut validator =
+            HookIpValidator::from_params(&github.params()).unwrap();
+        assert_eq!(validator.base_url, github.params().base_url.unwrap());
+        assert!(validator
+            .validate("192.30.253.7".parse().unwrap())
+            .await
+            .unwrap());
+        assert!(!validator
+            .validate("8.8.8.8".parse().unwrap())
+            .await
+            .unwrap());
+
+        // A plain client has no user agent of its own.
+        let mut validator = HookIpValidator::new(reqwest::Client::new());
+        validator.base_url = github.base_url();
+        assert!(validator.user_agent.starts_with("github-app-auth/"));
+        assert!(validator
+            .validate("192.30.253.7".parse().unwrap())
+            .await
+            .unwrap());
+    }
+}
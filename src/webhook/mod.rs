@@ -0,0 +1,131 @@
+//! Verification of webhook deliveries, and payloads of the
+//! installation events in `events` (with the `client` feature).
+//!
+//! GitHub signs each webhook delivery with the app's webhook secret
+//! and sends the signature in the `X-Hub-Signature-256` header. See
+//! [Validating webhook deliveries](https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries).
+
+#[cfg(feature = "client")]
+pub mod events;
+mod meta;
+
+#[cfg(feature = "client")]
+pub use meta::HookIpValidator;
+pub use meta::{InvalidIpRange, IpRange};
+
+use ring::hmac;
+
+/// Name of the header containing the delivery signature.
+pub const SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// Webhook verification error.
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum WebhookError {
+    /// The signature header is not of the form `sha256=<hex>`.
+    #[error("malformed webhook signature")]
+    MalformedSignature,
+
+    /// The signature does not match any of the configured secrets.
+    #[error("webhook signature does not match")]
+    SignatureMismatch,
+}
+
+/// Verifies webhook delivery signatures.
+///
+/// Several secrets can be configured at once. A delivery is accepted
+/// if its signature matches any of them, which allows the webhook
+/// secret to be rotated without downtime: add the new secret, update
+/// it in the app settings, then remove the old secret.
+pub struct WebhookVerifier {
+    keys: Vec<hmac::Key>,
+}
+
+impl WebhookVerifier {
+    /// Create a verifier that accepts signatures made with any of
+    /// `secrets`, for example the current and previous secret.
+    pub fn new<S: AsRef<[u8]>>(
+        secrets: impl IntoIterator<Item = S>,
+    ) -> WebhookVerifier {
+        WebhookVerifier {
+            keys: secrets
+                .into_iter()
+                .map(|secret| {
+                    hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref())
+                })
+                .collect(),
+        }
+    }
+
+    /// Verify a delivery. `signature` is the value of the
+    /// [`SIGNATURE_HEADER`] header and `body` is the raw request body.
+    pub fn verify(
+        &self,
+        signature: &str,
+        body: &[u8],
+    ) -> Result<(), WebhookError> {
+        let signature = signature
+            .strip_prefix("sha256=")
+            .and_then(decode_hex)
+            .ok_or(WebhookError::MalformedSignature)?;
+        if self
+            .keys
+            .iter()
+            .any(|key| hmac::verify(key, body, &signature).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(WebhookError::SignatureMismatch)
+        }
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example from the GitHub documentation.
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    const SIGNATURE: &str = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    #[test]
+    fn test_verify() {
+        let verifier = WebhookVerifier::new([SECRET]);
+        assert_eq!(verifier.verify(SIGNATURE, BODY), Ok(()));
+        assert_eq!(
+            verifier.verify(SIGNATURE, b"Goodbye, World!"),
+            Err(WebhookError::SignatureMismatch)
+        );
+        assert_eq!(
+            verifier.verify("sha1=abcd", BODY),
+            Err(WebhookError::MalformedSignature)
+        );
+        assert_eq!(
+            verifier.verify("sha256=xyz", BODY),
+            Err(WebhookError::MalformedSignature)
+        );
+        assert_eq!(
+            verifier.verify("sha256=abc", BODY),
+            Err(WebhookError::MalformedSignature)
+        );
+    }
+
+    #[test]
+    fn test_verify_rotation() {
+        let verifier = WebhookVerifier::new(["new secret", SECRET]);
+        assert_eq!(verifier.verify(SIGNATURE, BODY), Ok(()));
+
+        let verifier = WebhookVerifier::new(["new secret"]);
+        assert_eq!(
+            verifier.verify(SIGNATURE, BODY),
+            Err(WebhookError::SignatureMismatch)
+        );
+    }
+}
@@ -1,5 +1,9 @@
+#![cfg(feature = "client")]
+
 use chrono::Duration;
-use github_app_auth::{GithubAuthParams, InstallationAccessToken};
+use github_app_auth::{
+    GithubAuthParams, InstallationAccessToken, TokenValidity,
+};
 use serde::Deserialize;
 use std::{env, os::unix::ffi::OsStrExt};
 
@@ -44,7 +48,7 @@ async fn check_secrets(
 
     let resp: SecretsResponse = token
         .client
-        .get(&format!(
+        .get(format!(
             "https://api.github.com/repos/{}/actions/secrets",
             repo
         ))
@@ -74,20 +78,33 @@ async fn get_secrets() -> Result<(), BoxError> {
     let app_id = env::var("TEST_APP_ID")?.parse::<u64>()?;
     let installation_id = env::var("TEST_INSTALLATION_ID")?.parse::<u64>()?;
 
-    let mut token = InstallationAccessToken::new(GithubAuthParams {
+    let params = GithubAuthParams {
         user_agent: "github-app-auth-example".into(),
         private_key,
         app_id,
         installation_id,
-    })
-    .await?;
+        ..Default::default()
+    };
+    let mut token = InstallationAccessToken::new(params.clone()).await?;
 
     check_secrets(&mut token).await?;
+    assert_eq!(token.verify().await?, TokenValidity::Valid);
+    assert!(token.health_check().await?);
 
     // Set the refresh margin to a ridiculously large value to ensure
     // a refresh, then verify another request succeeds.
     token.refresh_safety_margin = Duration::weeks(1);
     check_secrets(&mut token).await?;
 
+    // The installation ID can also be looked up from the repository
+    // the workflow is running in.
+    let mut token =
+        InstallationAccessToken::from_actions_env(GithubAuthParams {
+            installation_id: 0,
+            ..params
+        })
+        .await?;
+    check_secrets(&mut token).await?;
+
     Ok(())
 }